@@ -1,6 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use std::io::Write;
 use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
 
 static CMD: &'static str = "./target/release/teip";
 
@@ -184,6 +186,54 @@ fn solid_field_regex_double(lap: usize) {
     let _ = child.wait_with_output();
 }
 
+fn buffer_lines_double(lap: usize, n: &str) {
+    let mut child = Command::new(CMD)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null()) // comment out to check output.
+        .args(&["--buffer-lines", n, "-c", "1-3,6-8", "sed", "s/./A/"])
+        .spawn()
+        .expect("Failed to swapn process");
+    {
+        let stdin = child
+            .stdin
+            .as_mut()
+            .ok_or("Child process stdin has not been captured!")
+            .unwrap();
+        stdin
+            .write_all("@@@@@@@@@@\n".repeat(lap).as_bytes())
+            .unwrap();
+    }
+    let _ = child.wait_with_output();
+}
+
+// Input is given slowly like a slow disk, 100 lines every millisecond
+fn readahead_double(lap: usize, readahead: Option<&str>) {
+    let mut args = vec!["-c", "1-3,6-8", "sed", "s/./A/"];
+    if let Some(n) = readahead {
+        args.splice(0..0, vec!["--input-buffer-readahead", n]);
+    }
+    let mut child = Command::new(CMD)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null()) // comment out to check output.
+        .args(&args)
+        .spawn()
+        .expect("Failed to swapn process");
+    {
+        let stdin = child
+            .stdin
+            .as_mut()
+            .ok_or("Child process stdin has not been captured!")
+            .unwrap();
+        for _ in 0..(lap / 100) {
+            stdin
+                .write_all("@@@@@@@@@@\n".repeat(100).as_bytes())
+                .unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    let _ = child.wait_with_output();
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("character_double 10000", |b| {
         b.iter(|| character_double(black_box(10000)))
@@ -200,6 +250,18 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("field_regex_double 10000", |b| {
         b.iter(|| field_regex_double(black_box(10000)))
     });
+    c.bench_function("buffer_lines_double 10000 (--buffer-lines 1)", |b| {
+        b.iter(|| buffer_lines_double(black_box(10000), "1"))
+    });
+    c.bench_function("buffer_lines_double 10000 (--buffer-lines 1000)", |b| {
+        b.iter(|| buffer_lines_double(black_box(10000), "1000"))
+    });
+    c.bench_function("readahead_double 10000 (slow input)", |b| {
+        b.iter(|| readahead_double(black_box(10000), None))
+    });
+    c.bench_function("readahead_double 10000 (slow input, --input-buffer-readahead 1000)", |b| {
+        b.iter(|| readahead_double(black_box(10000), Some("1000")))
+    });
     c.bench_function("solid_character_double", |b| {
         b.iter(|| solid_character_double(black_box(100)))
     });
@@ -1,7 +1,9 @@
 /// Input stream is devided into multiple Chunks
 pub enum Chunk {
-    Keep(String),   // a string under masking tape. Printed as is.
-    Hole,           // A hole on the masking tape. The string in the hole being processed other thread.
-    SHole(String),  // Solid hole. A hole and string in this hole. Enabled with -s (solid mode)
-    EOF,            // End of file
+    Keep(String),         // a string under masking tape. Printed as is.
+    Hole(Option<String>), // A hole on the masking tape. The string in the hole being processed other thread.
+                          // The original string is given only with --null-if-empty.
+    SHole(String),        // Solid hole. A hole and string in this hole. Enabled with -s (solid mode)
+    LHole(String),        // A hole given to the command spawned for each line ( --command-per-line )
+    EOF,                  // End of file
 }
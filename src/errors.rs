@@ -2,6 +2,7 @@ use super::chunk::Chunk;
 use super::CMD;
 use std::error;
 use std::fmt;
+use std::process::ExitStatus;
 use std::sync::mpsc;
 use log::debug;
 
@@ -21,6 +22,41 @@ pub fn u() -> ! {
     std::process::exit(256);
 }
 
+/// What to do with a hole (or a line) which failed to be processed ( --on-error )
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnError {
+    Passthrough,
+    Empty,
+    Abort,
+    Marker(String),
+}
+
+impl OnError {
+    pub fn parse(mode: &str, marker: &str) -> Result<OnError, String> {
+        match mode {
+            "passthrough" => Ok(OnError::Passthrough),
+            "empty" => Ok(OnError::Empty),
+            "abort" => Ok(OnError::Abort),
+            "marker" => Ok(OnError::Marker(marker.to_string())),
+            _ => Err(format!(
+                "Invalid value for --on-error: '{}' (passthrough, empty, abort or marker is expected)",
+                mode
+            )),
+        }
+    }
+
+    /// The string put instead of the result of the failed original.
+    /// None means the process must be aborted.
+    pub fn apply(&self, original: &str) -> Option<String> {
+        match self {
+            OnError::Passthrough => Some(original.to_string()),
+            OnError::Empty => Some(String::new()),
+            OnError::Abort => None,
+            OnError::Marker(m) => Some(m.clone()),
+        }
+    }
+}
+
 /// Exit silently because the error can be intentional.
 pub fn exit_silently(msg: &str) -> ! {
     debug!("SIGPIPE?:{}", msg);
@@ -65,6 +101,7 @@ impl fmt::Debug for PipeReceiveError {
 pub enum ChunkSendError {
     Channel(mpsc::SendError<Chunk>),
     Pipe(std::io::Error),
+    Terminator(u8), // The hole includes the terminator of the holes given to the command
 }
 
 impl fmt::Display for ChunkSendError {
@@ -72,6 +109,11 @@ impl fmt::Display for ChunkSendError {
         match *self {
             ChunkSendError::Channel(ref err) => write!(f, "Channel error: {}", err),
             ChunkSendError::Pipe(ref err) => write!(f, "IO error: {}", err),
+            ChunkSendError::Terminator(b) => write!(
+                f,
+                "A hole includes {:?}, which terminates each hole given to the command. Use --length-prefixed for such holes",
+                b as char
+            ),
         }
     }
 }
@@ -81,6 +123,7 @@ impl error::Error for ChunkSendError {
         match *self {
             ChunkSendError::Channel(_) => "Channel error",
             ChunkSendError::Pipe(_) => "IO error",
+            ChunkSendError::Terminator(_) => "Terminator in the hole",
         }
     }
 }
@@ -90,6 +133,7 @@ impl fmt::Debug for ChunkSendError {
         match *self {
             ChunkSendError::Channel(ref e) => write!(f, "Channel error: {}", e),
             ChunkSendError::Pipe(ref e) => write!(f, "IO error: {}", e),
+            ChunkSendError::Terminator(b) => write!(f, "Terminator in the hole: {:?}", b as char),
         }
     }
 }
@@ -101,6 +145,8 @@ pub enum SpawnError {
     StdinOpenFailed,
     StdoutOpenFailed,
     Io(std::io::Error),
+    Command { name: String, source: std::io::Error },
+    Exit { name: String, status: ExitStatus, stdout: String },
 }
 
 impl fmt::Display for SpawnError {
@@ -109,6 +155,8 @@ impl fmt::Display for SpawnError {
             SpawnError::StdinOpenFailed => write!(f, "{}", STDIN_ERROR_MSG),
             SpawnError::StdoutOpenFailed => write!(f, "{}", STDOUT_ERROR_MSG),
             SpawnError::Io(ref err) => write!(f, "IO error: {}", err),
+            SpawnError::Command { ref name, ref source } => write!(f, "failed to run '{}': {}", name, source),
+            SpawnError::Exit { ref name, ref status, .. } => write!(f, "'{}' failed: {}", name, status),
         }
     }
 }
@@ -119,6 +167,8 @@ impl error::Error for SpawnError {
             SpawnError::StdinOpenFailed => STDIN_ERROR_MSG,
             SpawnError::StdoutOpenFailed => STDOUT_ERROR_MSG,
             SpawnError::Io(_) => "IO error",
+            SpawnError::Command { .. } => "Failed to run command",
+            SpawnError::Exit { .. } => "Command exited with failure",
         }
     }
 }
@@ -129,6 +179,8 @@ impl fmt::Debug for SpawnError {
             SpawnError::StdinOpenFailed => write!(f, "{}", STDIN_ERROR_MSG),
             SpawnError::StdoutOpenFailed => write!(f, "{}", STDOUT_ERROR_MSG),
             SpawnError::Io(ref err) => write!(f, "IO error: {}", err),
+            SpawnError::Command { ref name, ref source } => write!(f, "failed to run '{}': {}", name, source),
+            SpawnError::Exit { ref name, ref status, .. } => write!(f, "'{}' failed: {}", name, status),
         }
     }
 }
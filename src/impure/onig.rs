@@ -1,11 +1,11 @@
-use std::io::{self, BufRead};
 
 use onig;
 pub type Regex = onig::Regex;
 pub type RegexOptions = onig::RegexOptions;
 pub type Syntax = onig::Syntax;
 
-use super::super::{error_exit, errors, msg_error, stringutils, PipeIntercepter, DEFAULT_CAP};
+use super::super::input::Input;
+use super::super::{error_exit, errors, stringutils, PipeIntercepter, DEFAULT_CAP};
 
 pub fn new_regex() -> Regex {
     Regex::new("").unwrap()
@@ -24,14 +24,13 @@ pub fn new_option_none_regex(s: &str) -> Regex {
 /// Bypassing multiple strings in a line based on Oniguruma Regular Expression ( -g -G -o )
 pub fn regex_onig_proc(
     ch: &mut PipeIntercepter,
-    line: &Vec<u8>,
+    line: &str,
     re: &Regex,
     invert: bool,
 ) -> Result<(), errors::ChunkSendError> {
-    let line = String::from_utf8_lossy(&line).to_string();
     let mut left_index = 0;
     let mut right_index;
-    for cap in re.find_iter(&line) {
+    for cap in re.find_iter(line) {
         right_index = cap.0;
         let unmatched = &line[left_index..right_index];
         let matched = &line[cap.0..cap.1];
@@ -66,21 +65,22 @@ pub fn regex_onig_proc(
 /// Bypassing particular lines based on Oniguruma Regular Expression ( -g -G )
 pub fn regex_onig_line_proc(
     ch: &mut PipeIntercepter,
+    input: &Input,
     re: &Regex,
     invert: bool,
     line_end: u8,
 ) -> Result<(), errors::ChunkSendError> {
-    let stdin = io::stdin();
+    let mut reader = input.open();
     loop {
         let mut buf = Vec::with_capacity(DEFAULT_CAP);
-        match stdin.lock().read_until(line_end, &mut buf) {
+        match stringutils::read_line(&mut reader, line_end, &mut buf) {
             Ok(n) => {
-                let eol = stringutils::trim_eol(&mut buf);
+                let eol = stringutils::trim_eol(&mut buf, line_end);
                 if n == 0 {
                     ch.send_eof()?;
                     break;
                 }
-                let line = String::from_utf8_lossy(&buf).to_string();
+                let line = input.decode(&buf);
                 match re.find(&line) {
                     Some(_) => {
                         if invert {
@@ -99,7 +99,7 @@ pub fn regex_onig_line_proc(
                 };
                 ch.send_keep(eol)?;
             }
-            Err(e) => msg_error(&e.to_string()),
+            Err(e) => error_exit(&e),
         }
     }
     Ok(())
@@ -0,0 +1,385 @@
+use super::errors::{error_exit, msg_error, OnError};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Compression of the input ( --zstd, --bzip2, --auto-decompress )
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Decompress {
+    Zstd,
+    Bzip2,
+    Auto, // Detected for each file by the magic bytes or the extension
+}
+
+/// Input of teip and how it is read
+#[derive(Default)]
+pub struct Input {
+    files: Option<Vec<String>>,        // --input, --files-from
+    decompress: Option<Decompress>,    // --zstd, --bzip2, --auto-decompress
+    readahead: usize,                  // --input-buffer-readahead, the number of lines
+    deadline: Option<Instant>,         // --command-timeout-total
+    line_end: u8,                      // Line terminator of --input-buffer-readahead and --command-timeout-total
+    keep_going: usize,                 // --keep-going
+    strict_utf8: bool,                 // --strict-utf8
+    on_error: Option<OnError>,         // --on-error, for the invalid UTF-8 of --strict-utf8
+}
+
+impl Input {
+    // Read the files instead of standard input ( --input, --files-from )
+    pub fn set_files(&mut self, files: Vec<String>) {
+        if files.is_empty() {
+            error_exit("No input file is given by --input or --files-from");
+        }
+        for file in files.iter() {
+            open_file(file);
+        }
+        self.files = Some(files);
+    }
+
+    // Whether teip reads standard input
+    pub fn is_stdin(&self) -> bool {
+        self.files.is_none()
+    }
+
+    // Files given by --input and --files-from
+    pub fn files(&self) -> Option<&Vec<String>> {
+        self.files.as_ref()
+    }
+
+    // Decompress the input ( --zstd, --bzip2, --auto-decompress )
+    pub fn set_decompress(&mut self, d: Decompress) {
+        if self.decompress.is_some() {
+            error_exit("Only one of --zstd, --bzip2 and --auto-decompress can be given");
+        }
+        self.decompress = Some(d);
+    }
+
+    // Read lines of the input ahead in another thread ( --input-buffer-readahead )
+    pub fn set_readahead(&mut self, lines: usize, line_end: u8) {
+        if lines == 0 {
+            error_exit("<n> of --input-buffer-readahead must be greater than 0");
+        }
+        self.readahead = lines;
+        self.line_end = line_end;
+    }
+
+    // Stop reading the input when the time has passed since now ( --command-timeout-total ).
+    // The input ends at the deadline, after the line read so far.
+    pub fn set_time_limit(&mut self, secs: f64, line_end: u8) {
+        let limit = Duration::try_from_secs_f64(secs)
+            .ok()
+            .filter(|d| !d.is_zero())
+            .unwrap_or_else(|| error_exit(&format!("Invalid value for --command-timeout-total: '{}'", secs)));
+        self.deadline = Some(Instant::now() + limit);
+        self.line_end = line_end;
+    }
+
+    // Deadline of --command-timeout-total
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    // Whether the time of --command-timeout-total has passed
+    pub fn time_limit_exceeded(&self) -> bool {
+        is_expired(self.deadline())
+    }
+
+    // Allow up to n consecutive read errors instead of the first one ( --keep-going )
+    pub fn set_keep_going(&mut self, n: usize) {
+        self.keep_going = n;
+    }
+
+    // Invalid UTF-8 in the input is an error instead of U+FFFD, or handled by --on-error ( --strict-utf8 )
+    pub fn set_strict_utf8(&mut self, on_error: Option<OnError>) {
+        self.strict_utf8 = true;
+        self.on_error = on_error;
+    }
+
+    // Decode a line of the input.
+    // With --strict-utf8, an invalid line is handled by --on-error, passthrough means U+FFFD.
+    pub fn decode(&self, buf: &[u8]) -> String {
+        if !self.strict_utf8 {
+            return String::from_utf8_lossy(buf).to_string();
+        }
+        match std::str::from_utf8(buf) {
+            Ok(s) => s.to_string(),
+            Err(e) => {
+                let lossy = String::from_utf8_lossy(buf);
+                match self.on_error.as_ref().and_then(|p| p.apply(&lossy)) {
+                    Some(s) => s,
+                    None => error_exit(&format!(
+                        "Invalid UTF-8 sequence at byte offset {} of the line: {}",
+                        e.valid_up_to(),
+                        lossy
+                    )),
+                }
+            }
+        }
+    }
+
+    // Open the input of teip. The files are concatenated like cat(1).
+    pub fn open(&self) -> Box<dyn BufRead + Send> {
+        let reader: Box<dyn Read + Send> = match self.files {
+            Some(ref files) => {
+                let mut reader: Box<dyn Read + Send> = Box::new(io::empty());
+                for file in files.iter() {
+                    let d = match self.decompress {
+                        Some(Decompress::Auto) => detect(file),
+                        d => d,
+                    };
+                    reader = match d {
+                        Some(d) => Box::new(reader.chain(Decompressor::new(d, Box::new(open_file(file)), file))),
+                        None => Box::new(reader.chain(open_file(file))),
+                    };
+                }
+                reader
+            }
+            None => match self.decompress {
+                Some(Decompress::Auto) => error_exit("--auto-decompress is available only with --input and --files-from"),
+                Some(d) => Box::new(Decompressor::new(d, Box::new(io::stdin()), "-")),
+                None => Box::new(io::stdin()),
+            },
+        };
+        let reader: Box<dyn Read + Send> = match self.keep_going {
+            0 => reader,
+            max_errors => Box::new(KeepGoing { inner: reader, max_errors }),
+        };
+        // --command-timeout-total reads the input in another thread as well,
+        // so that a read blocked on the input is given up at the deadline.
+        if self.readahead == 0 && self.deadline.is_none() {
+            return Box::new(BufReader::new(reader));
+        }
+        let lines = self.readahead.max(1);
+        Box::new(BufReader::new(Readahead::spawn(reader, lines, self.line_end, self.deadline)))
+    }
+}
+
+// Whether the deadline has passed. No deadline never expires.
+pub fn is_expired(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|d| Instant::now() >= d)
+}
+
+// Paths listed in the file, one per line ( --files-from )
+pub fn read_files_from(path: &str) -> Vec<String> {
+    let mut list = String::new();
+    open_file(path)
+        .read_to_string(&mut list)
+        .unwrap_or_else(|e| error_exit(&format!("{}: {}", path, e)));
+    list.lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+// Read <list> of -f, -c and -l from the file descriptor opened by the shell ( --list-fd )
+pub fn read_list_fd(fd: u32) -> String {
+    // Standard input is the data to be processed
+    if fd == 0 {
+        error_exit("<n> of --list-fd must not be 0 (standard input)");
+    }
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            let path = format!("/dev/fd/{}", fd);
+            let mut list = String::new();
+            File::open(&path)
+                .and_then(|mut f| f.read_to_string(&mut list))
+                .unwrap_or_else(|e| error_exit(&format!("--list-fd {}: {}", fd, e)));
+            let list = list.trim().to_string();
+            if list.is_empty() {
+                error_exit(&format!("--list-fd {}: No list is given", fd));
+            }
+            list
+        } else {
+            error_exit("--list-fd is not available on this platform")
+        }
+    }
+}
+
+// Detect the compression of the file by the magic bytes, or the extension if the file is too short
+fn detect(path: &str) -> Option<Decompress> {
+    let mut magic = Vec::with_capacity(4);
+    open_file(path)
+        .take(4)
+        .read_to_end(&mut magic)
+        .unwrap_or_else(|e| error_exit(&format!("{}: {}", path, e)));
+    if magic == b"\x28\xb5\x2f\xfd" {
+        return Some(Decompress::Zstd);
+    }
+    if magic.starts_with(b"BZh") {
+        return Some(Decompress::Bzip2);
+    }
+    if magic.len() == 4 {
+        return None;
+    }
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("zst") | Some("zstd") => Some(Decompress::Zstd),
+        Some("bz2") => Some(Decompress::Bzip2),
+        _ => None,
+    }
+}
+
+/// Decompressed input. Each format is available only if the cargo feature of the same name is enabled.
+/// Errors are prefixed with the name of the file.
+struct Decompressor {
+    name: String,
+    inner: Box<dyn Read + Send>,
+}
+
+impl Decompressor {
+    fn new(d: Decompress, reader: Box<dyn Read + Send>, name: &str) -> Decompressor {
+        let inner = match d {
+            Decompress::Zstd => zstd_decoder(reader),
+            _ => bzip2_decoder(reader),
+        }
+        .unwrap_or_else(|e| error_exit(&format!("{}: {}", name, e)));
+        Decompressor { name: name.to_string(), inner }
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decoder(reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(zstd::stream::read::Decoder::new(reader)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decoder(_reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::other("zstd is not enabled in this build"))
+}
+
+// Concatenated streams are decompressed like bzip2 -dc
+#[cfg(feature = "bzip2")]
+fn bzip2_decoder(reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(bzip2::read::MultiBzDecoder::new(reader)))
+}
+
+#[cfg(not(feature = "bzip2"))]
+fn bzip2_decoder(_reader: Box<dyn Read + Send>) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::other("bzip2 is not enabled in this build"))
+}
+
+impl Read for Decompressor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner
+            .read(buf)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", self.name, e)))
+    }
+}
+
+/// Lines of the input read by another thread, up to the given number of lines ahead.
+/// Read errors are given to the reader in order, like the input without the thread.
+/// The input ends at the deadline even if the thread is blocked in the middle of a line.
+struct Readahead {
+    rx: Receiver<io::Result<Vec<u8>>>,
+    line: Vec<u8>,
+    pos: usize,
+    deadline: Option<Instant>,
+}
+
+impl Readahead {
+    fn spawn(reader: Box<dyn Read + Send>, lines: usize, line_end: u8, deadline: Option<Instant>) -> Readahead {
+        let (tx, rx) = mpsc::sync_channel(lines);
+        thread::spawn(move || {
+            let mut reader = BufReader::new(reader);
+            loop {
+                let mut line = Vec::new();
+                let result = reader.read_until(line_end, &mut line).map(|_| line);
+                let eof = matches!(result, Ok(ref line) if line.is_empty());
+                // The receiver is dropped if teip exits before the end of the input
+                if tx.send(result).is_err() || eof {
+                    break;
+                }
+            }
+        });
+        Readahead { rx, line: Vec::new(), pos: 0, deadline }
+    }
+}
+
+impl Read for Readahead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.line.len() {
+            let received = match self.deadline {
+                Some(d) => self.rx.recv_timeout(d.saturating_duration_since(Instant::now())).ok(),
+                None => self.rx.recv().ok(),
+            };
+            match received {
+                // The line may arrive just after the deadline
+                Some(Ok(line)) if !is_expired(self.deadline) => {
+                    self.line = line;
+                    self.pos = 0;
+                }
+                Some(Err(e)) => return Err(e),
+                _ => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.line.len() - self.pos);
+        buf[..n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Input which retries up to the given number of read errors in a row ( --keep-going )
+struct KeepGoing {
+    inner: Box<dyn Read + Send>,
+    max_errors: usize,
+}
+
+impl Read for KeepGoing {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut errors = 0;
+        loop {
+            match self.inner.read(buf) {
+                Ok(n) => return Ok(n),
+                Err(e) if errors < self.max_errors => {
+                    errors += 1;
+                    msg_error(&format!("{} (read error {}/{}, retrying)", e, errors, self.max_errors));
+                }
+                Err(e) => {
+                    let msg = format!("{}: giving up after {} consecutive read errors", e, errors + 1);
+                    return Err(io::Error::new(e.kind(), msg));
+                }
+            }
+        }
+    }
+}
+
+fn open_file(path: &str) -> File {
+    File::open(path).unwrap_or_else(|e| error_exit(&format!("{}: {}", path, e)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // Reader which fails `failures` times before reading `data`
+    struct FlakyReader {
+        failures: usize,
+        data: io::Cursor<Vec<u8>>,
+    }
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::other("flaky"));
+            }
+            self.data.read(buf)
+        }
+    }
+    fn flaky(failures: usize, max_errors: usize) -> BufReader<KeepGoing> {
+        let inner = Box::new(FlakyReader { failures, data: io::Cursor::new(b"ab\ncd\n".to_vec()) });
+        BufReader::new(KeepGoing { inner, max_errors })
+    }
+    #[test]
+    fn test_keep_going() {
+        let mut buf = vec![];
+        assert_eq!(flaky(2, 2).read_until(b'\n', &mut buf).unwrap(), 3);
+        assert_eq!(buf, b"ab\n");
+        let mut buf = vec![];
+        assert_eq!(
+            flaky(3, 2).read_until(b'\n', &mut buf).unwrap_err().to_string(),
+            "flaky: giving up after 3 consecutive read errors"
+        );
+    }
+}
@@ -8,6 +8,75 @@ pub fn to_ranges(list: &str, complement: bool) -> Result<Vec<Range>, String> {
     }
 }
 
+// Parse the list keeping the given order and duplicates ( --reorder )
+pub fn to_ordered_ranges(list: &str) -> Result<Vec<Range>, String> {
+    let mut ranges: Vec<Range> = vec![];
+    for item in list.split(',') {
+        match item.parse::<Range>() {
+            Ok(range_item) => ranges.push(range_item),
+            Err(e) => return Err(format!("range '{}' was invalid: {}", item, e)),
+        }
+    }
+    Ok(ranges)
+}
+
+// i.e "1\02-3\0" -> "1,2-3"
+// i.e "1\\02-3\\0" -> "1,2-3"
+// Each range is parsed by itself, so a comma in a range is an error instead of another range.
+pub fn from_nul_delimited(list: &str) -> Result<String, String> {
+    let mut items: Vec<&str> = vec![];
+    for item in list.split('\0').map(|item| item.trim()).filter(|item| !item.is_empty()) {
+        if let Err(e) = item.parse::<Range>() {
+            return Err(format!("range '{}' was invalid: {}", item.escape_debug(), e));
+        }
+        items.push(item);
+    }
+    if items.is_empty() {
+        return Err("no range is given".to_string());
+    }
+    Ok(items.join(","))
+}
+
+// Convert zero-based list to one-based list ( --zero-based )
+// i.e "0,2-3,5-" -> "1,3-4,6-"
+pub fn from_zero_based(list: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut num = String::new();
+    for c in list.chars().chain(std::iter::once(',')) {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        if !num.is_empty() {
+            let n: usize = num
+                .parse()
+                .map_err(|e| format!("number '{}' was invalid: {}", num, e))?;
+            result.push_str(&(n + 1).to_string());
+            num.clear();
+        }
+        result.push(c);
+    }
+    result.pop();
+    Ok(result)
+}
+
+// Resolve the origin of the list.
+// "0:<list>" is zero-based and "1:<list>" is one-based regardless of --zero-based.
+// Otherwise, <list> is zero-based only if zero_based is true.
+pub fn resolve_origin(list: &str, zero_based: bool) -> Result<String, String> {
+    let (zero_based, list) = match list.split_once(':') {
+        Some(("0", l)) => (true, l),
+        Some(("1", l)) => (false, l),
+        Some((o, _)) => return Err(format!("origin '{}' was invalid: it must be 0 or 1", o)),
+        None => (zero_based, list),
+    };
+    if zero_based {
+        from_zero_based(list)
+    } else {
+        Ok(list.to_string())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -17,4 +86,39 @@ mod test {
         assert_eq!(range[0].low, 1);
         assert_eq!(range[0].high, 8);
     }
+    #[test]
+    fn test_to_ordered_ranges() {
+        let range = to_ordered_ranges("3,1-2,3").unwrap();
+        assert_eq!(range, vec![
+            Range { low: 3, high: 3 },
+            Range { low: 1, high: 2 },
+            Range { low: 3, high: 3 },
+        ]);
+        assert!(to_ordered_ranges("2,0").is_err());
+    }
+    #[test]
+    fn test_from_nul_delimited() {
+        assert_eq!(from_nul_delimited("1\u{0}2-3\u{0}").unwrap(), "1,2-3");
+        assert_eq!(from_nul_delimited("5-\0\n1\n\0").unwrap(), "5-,1");
+        assert!(from_nul_delimited("1,2\u{0}3").is_err());
+        assert!(from_nul_delimited("\0\0").is_err());
+    }
+    #[test]
+    fn test_from_zero_based() {
+        assert_eq!(from_zero_based("0").unwrap(), "1");
+        assert_eq!(from_zero_based("0,2-3,5-").unwrap(), "1,3-4,6-");
+        assert_eq!(from_zero_based("-2,10").unwrap(), "-3,11");
+        let range = to_ranges(&from_zero_based("0-1").unwrap(), false).unwrap();
+        assert_eq!(range[0].low, 1);
+        assert_eq!(range[0].high, 2);
+    }
+    #[test]
+    fn test_resolve_origin() {
+        assert_eq!(resolve_origin("1,3", false).unwrap(), "1,3");
+        assert_eq!(resolve_origin("1,3", true).unwrap(), "2,4");
+        assert_eq!(resolve_origin("0:1,3", false).unwrap(), "2,4");
+        assert_eq!(resolve_origin("1:1,3", true).unwrap(), "1,3");
+        assert_eq!(resolve_origin("0:0-", false).unwrap(), "1-");
+        assert!(resolve_origin("2:1", false).is_err());
+    }
 }
@@ -9,6 +9,10 @@
 
 use std::str::FromStr;
 
+/// `high` of open-ended ranges like "5-" and "-", which continue to the end of the line.
+/// It is less than usize::MAX so that `high + 1` never overflows.
+pub const UNBOUNDED: usize = usize::MAX - 1;
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Range {
     pub low: usize,
@@ -19,8 +23,6 @@ impl FromStr for Range {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Range, &'static str> {
-        use std::usize::MAX;
-
         let mut parts = s.splitn(2, '-');
 
         let field = "fields and positions are numbered from 1";
@@ -28,6 +30,8 @@ impl FromStr for Range {
         let inval = "failed to parse range";
 
         match (parts.next(), parts.next()) {
+            // "-" selects everything
+            (Some(""), Some("")) => Ok(Range { low: 1, high: UNBOUNDED }),
             (Some(nm), None) => {
                 if let Ok(nm) = nm.parse::<usize>() {
                     if nm > 0 {
@@ -42,7 +46,7 @@ impl FromStr for Range {
             (Some(n), Some(m)) if m.is_empty() => {
                 if let Ok(low) = n.parse::<usize>() {
                     if low > 0 {
-                        Ok(Range { low, high: MAX - 1 })
+                        Ok(Range { low, high: UNBOUNDED })
                     } else {
                         Err(field)
                     }
@@ -79,6 +83,14 @@ impl FromStr for Range {
 }
 
 impl Range {
+    pub fn contains(&self, n: usize) -> bool {
+        self.low <= n && (self.is_unbounded() || n <= self.high)
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.high == UNBOUNDED
+    }
+
     pub fn from_list(list: &str) -> Result<Vec<Range>, String> {
         use std::cmp::max;
 
@@ -107,9 +119,31 @@ impl Range {
     }
 }
 
-pub fn complement(ranges: &[Range]) -> Vec<Range> {
-    use std::usize;
+/// Membership test of numbers for sorted and non-overlapping ranges (see Range::from_list).
+/// It is efficient for ascending numbers like positions in a line,
+/// and numbers going backward (i.e the next line) start searching from the first range again.
+pub struct Ranges<'a> {
+    ranges: &'a [Range],
+    ri: usize,
+}
+
+impl<'a> Ranges<'a> {
+    pub fn new(ranges: &'a [Range]) -> Ranges<'a> {
+        Ranges { ranges, ri: 0 }
+    }
+
+    pub fn contains(&mut self, n: usize) -> bool {
+        if self.ri > 0 && n <= self.ranges[self.ri - 1].high {
+            self.ri = 0;
+        }
+        while self.ri < self.ranges.len() && self.ranges[self.ri].high < n {
+            self.ri += 1;
+        }
+        self.ri < self.ranges.len() && self.ranges[self.ri].contains(n)
+    }
+}
 
+pub fn complement(ranges: &[Range]) -> Vec<Range> {
     let mut complements = Vec::with_capacity(ranges.len() + 1);
 
     if !ranges.is_empty() && ranges[0].low > 1 {
@@ -131,10 +165,10 @@ pub fn complement(ranges: &[Range]) -> Vec<Range> {
                 }
             }
             (Some(last), None) => {
-                if last.high < usize::MAX - 1 {
+                if !last.is_unbounded() {
                     complements.push(Range {
                         low: last.high + 1,
-                        high: usize::MAX - 1,
+                        high: UNBOUNDED,
                     });
                 }
             }
@@ -144,3 +178,58 @@ pub fn complement(ranges: &[Range]) -> Vec<Range> {
 
     complements
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn test_range_contains() {
+        let range = Range { low: 2, high: 4 };
+        assert!(!range.contains(1));
+        assert!(range.contains(2));
+        assert!(range.contains(4));
+        assert!(!range.contains(5));
+    }
+    #[test]
+    fn test_range_unbounded() {
+        let range: Range = "5-".parse().unwrap();
+        assert!(range.is_unbounded());
+        assert!(!range.contains(4));
+        assert!(range.contains(5));
+        assert!(range.contains(usize::MAX));
+        let range: Range = "-5".parse().unwrap();
+        assert!(!range.is_unbounded());
+        assert!(range.contains(1));
+        assert!(range.contains(5));
+        assert!(!range.contains(6));
+        let range: Range = "-".parse().unwrap();
+        assert_eq!(range, Range { low: 1, high: UNBOUNDED });
+        assert!(range.contains(1));
+        assert!(range.contains(usize::MAX));
+        assert!("--".parse::<Range>().is_err());
+        assert!(complement(&[range]).is_empty());
+        let ranges = Range::from_list("-3,5-").unwrap();
+        assert_eq!(complement(&ranges), vec![Range { low: 4, high: 4 }]);
+    }
+    #[test]
+    fn test_ranges_contains() {
+        let list = Range::from_list("5-6,2,8-").unwrap();
+        let mut ranges = Ranges::new(&list);
+        let got: Vec<usize> = (1..=10).filter(|&n| ranges.contains(n)).collect();
+        assert_eq!(got, vec![2, 5, 6, 8, 9, 10]);
+        // going backward
+        assert!(ranges.contains(2));
+        assert!(!ranges.contains(1));
+        assert!(ranges.contains(6));
+        // skipping ranges
+        let mut ranges = Ranges::new(&list);
+        assert!(ranges.contains(9));
+        assert!(ranges.contains(5));
+        assert!(!ranges.contains(7));
+    }
+    #[test]
+    fn test_ranges_contains_empty() {
+        let mut ranges = Ranges::new(&[]);
+        assert!(!ranges.contains(1));
+    }
+}
@@ -16,19 +16,24 @@ mod pure {
 mod chunk;
 mod procs;
 mod errors;
+mod input;
+mod profile;
 mod spawnutils;
 use errors::*;
 mod pipeintercepter;
-use pipeintercepter::PipeIntercepter;
+use pipeintercepter::{OutputEncoding, PipeIntercepter, ResultWriter};
+use profile::Profile;
 mod stringutils;
+mod transform;
+use transform::Transform;
 
 #[macro_use]
 extern crate lazy_static;
 
 use log::debug; // Enable with RUST_LOG=debug
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use std::env;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, IsTerminal};
 use structopt::StructOpt;
 
 #[cfg(feature = "oniguruma")]
@@ -42,9 +47,12 @@ pub const DEFAULT_CAP: usize = 1024;
 
 lazy_static! {
     static ref REGEX_WS: Regex = Regex::new("\\s+").unwrap();
-    static ref DEFAULT_HIGHLIGHT: String = match env::var("TEIP_HIGHLIGHT") {
+    static ref HIGHLIGHT: Option<String> = env::var("TEIP_HIGHLIGHT").ok();
+    static ref HIGHLIGHT_START: Option<String> = env::var("TEIP_HIGHLIGHT_START").ok();
+    static ref HIGHLIGHT_END: Option<String> = env::var("TEIP_HIGHLIGHT_END").ok();
+    static ref HIGHLIGHT_BYPASS: String = match env::var("TEIP_HIGHLIGHT_BYPASS") {
         Ok(v) => v,
-        Err(_) => "\x1b[36m[\x1b[0m\x1b[01;31m{}\x1b[0m\x1b[36m]\x1b[0m".to_string(),
+        Err(_) => "\x1b[33m[\x1b[0m\x1b[01;32m{}\x1b[0m\x1b[33m]\x1b[0m".to_string(),
     };
     static ref GREP_PATH: String = match env::var("TEIP_GREP_PATH") {
         Ok(v) => v,
@@ -58,45 +66,360 @@ lazy_static! {
         Ok(v) => v,
         Err(_) => "awk".to_string(),
     };
-    static ref HL: Vec<&'static str> = DEFAULT_HIGHLIGHT.split("{}").collect();
 }
 
+const DEFAULT_HIGHLIGHT: &str = "\x1b[36m[\x1b[0m\x1b[01;31m{}\x1b[0m\x1b[36m]\x1b[0m";
+// Number of results kept by --dedup unless --dedup-cache is given
+const DEFAULT_DEDUP_CACHE: usize = 10000;
+
 #[derive(StructOpt, Debug)]
 #[structopt(
     about = "Bypassing a partial range of standard input to an arbitrary command",
     usage = "teip [OPTIONS] [FLAGS] [--] [<command>...]",
     help = "USAGE:
-  teip -g <pattern> [-Gosvz] [--] [<command>...]
+  teip -g <pattern> [-Gosvxz] [--] [<command>...]
   teip -c <list> [-svz] [--] [<command>...]
+  teip -b <list> [-svz] [--] [<command>...]
   teip -l <list> [-svz] [--] [<command>...]
   teip -f <list> [-d <delimiter> | -D <pattern> | --csv] [-svz] [--] [<command>...]
   teip -e <string> [-svz] [--] [<command>...]
 
+  All arguments after the options, or after --, are the <command>.
+  teip reads standard input, or the files given only by --input and --files-from.
+
 OPTIONS:
-    -g <pattern>        Bypassing lines that match the regular expression <pattern>
+    -g, --regex <pattern>
+                        Bypassing lines that match the regular expression <pattern>
         -o              -g bypasses only matched parts
+        --between       -g bypasses the parts between the matches, including the ones
+                        before the first match and after the last match, instead of the
+                        matches. Same as -o -v
         -G              -g interprets Oniguruma regular expressions.
-    -c <list>           Bypassing these characters
-    -l <list>           Bypassing these lines
-    -f <list>           Bypassing these white-space separated fields
-        -d <delimiter>  Use <delimiter> for field delimiter of -f
-        -D <pattern>    Use regular expression <pattern> for field delimiter of -f
+        --regex-engine <rust|onig>
+                        Regular expression engine of -g. rust is the default.
+                        onig is available only if oniguruma feature is enabled
+        -x, --line-regexp
+                        -g matches only the entire line
+        --group <n>     -og bypasses only <n>-th capture group of each match, and
+                        the other parts of the match are kept
+        --include-eol   -g without -o matches <pattern> against the line including
+                        its terminator (LF, CRLF or the one of -z). The terminator is
+                        still excluded from the hole. Note that $ and \\z never match
+                        before the terminator then, use \\r?\\n$ or (?m)$ instead
+        --min-match-length <n>
+                        -og ignores matches shorter than <n> characters, and they are
+                        handled like the unmatched parts
+    --empty-lines       Bypassing empty lines. Same as -g '\\A\\z'
+    --blank-lines       Bypassing empty lines and lines of white spaces only.
+                        Same as -g '\\A\\s*\\z'
+    -c, --chars <list>  Bypassing these characters
+    -b, --bytes <list>  Bypassing the characters including these bytes. A multi-byte
+                        character is selected entirely if any of its bytes is in <list>,
+                        so the hole may be larger than <list>
+        --slurp         -c reads the entire input as one record. Newlines are counted as
+                        characters, and <list> is numbered across the whole input.
+                        Requires -s, -z, --solid-nul or --length-prefixed to execute
+                        the command
+        --record-bytes <n>
+                        -c reads the input as records of <n> bytes regardless of the
+                        line terminators, like fixed-length binary formats. The last
+                        record may be shorter. Requires -s, -z, --solid-nul or
+                        --length-prefixed to execute the command
+        --display-columns
+                        <list> of -c is display columns on the terminal instead of
+                        characters. A wide character like CJK occupies two columns, and
+                        it is selected if either of them is in <list>
+        --expand-tabs <n>
+                        <list> of -c is columns where a tab occupies the columns up to
+                        the next tab stop of every <n> columns, like expand(1). A tab is
+                        selected if any of them is in <list>
+        --no-reexpand   --expand-tabs replaces the tabs with spaces in the output
+                        instead of keeping the original tabs
+        --no-merge      -c makes a hole for each character instead of merging
+                        consecutive characters into one hole. -s executes the command
+                        for each character
+    -l, --lines <list>  Bypassing these lines
+    --head <n>          Bypassing first <n> lines
+    --tail <n>          Bypassing last <n> lines
+        --continuation <pattern>
+                        Join each line matching the regular expression <pattern> to the
+                        previous line, so that -l and -g without -o select the logical
+                        records. Each line of the record is still a separate hole
+    --after-match <pattern> <n>
+                        Bypassing <n> lines after each line matching the regular
+                        expression <pattern>. The matched line is not bypassed
+    --jsonl-key <key>   Bypassing the string value of the top-level <key> in each line
+                        of JSON Lines. The hole is the contents between the double
+                        quotes, and lines without <key> are kept as they are
+        --jsonl-stringify
+                        --jsonl-key also bypasses a number, boolean or null value, and
+                        the result is surrounded by double quotes as a string
+    -f, --fields <list> Bypassing these white-space separated fields
+                        (only in lines matching <pattern> if -g is given)
+        --field-match <pattern>
+                        -f bypasses only fields matching the regular expression <pattern>
+                        among <list> (all fields if -f is not given). -v bypasses fields
+                        not matching <pattern> instead of inverting <list>
+        --field-match-full
+                        --field-match matches only the entire field like -x
+        --field-command <command>
+                        -f bypasses only fields for which <command> exits with 0,
+                        like --field-match. <command> is executed by the shell with
+                        the field as stdin, once per distinct field. It is slow for
+                        many distinct fields because a process is spawned for each
+        --max-fields <n>
+                        -f splits the line into at most <n> fields, and the <n>-th field
+                        is the rest of the line including the delimiters
+        --field-default <value>
+                        If the line has fewer fields than <list>, -f adds the missing
+                        fields with delimiters. Fields in <list> are <value> and
+                        bypassed, and the others are empty
+        --join-fields <separator>
+                        Join the fields bypassed by -f in each line with <separator> into
+                        a single hole put at the first of the fields, and empty the
+                        others. --field-join is an alias
+        --join-placement <first|last>
+                        Where --join-fields puts the single hole. Default is first
+        -d, --delimiter <delimiter>
+                        Use <delimiter> for field delimiter of -f
+                        \\t, \\n, \\r, \\0, \\xNN and \\\\ in <delimiter> are interpreted
+        --no-escape     -d and --join-fields do not interpret escape sequences
+        --select-delimiters
+                        -f bypasses <list>-th delimiters of -d instead of fields, and
+                        the fields are kept as they are
+        -D, --regex-delimiter <pattern>
+                        Use regular expression <pattern> for field delimiter of -f
+        --delimiter-chars <chars>
+                        Use any one of <chars> for field delimiter of -f. Each delimiter
+                        is kept as it is. Escape sequences are interpreted like -d
+        --auto-delimiter
+                        Use the one of , TAB ; and | which appears the most in the first
+                        line for field delimiter of -f like -d. , is used if none of them
+                        appears. The delimiter is printed to standard error with --verbose
+        --delimiter-regex-captures
+                        Each capture group in <pattern> of -D is also a field numbered
+                        next to the field before the delimiter
+        --delimiter-replace <pattern> <replacement>
+                        Replace the regular expression <pattern> in each delimiter of -f
+                        with <replacement> in the output. $1 or ${name} in <replacement>
+                        is a capture group. Not available with -d and --csv
+        --output-delimiter <delimiter>
+                        Use <delimiter> for field delimiter of the output of -f
+                        instead of the delimiter found in the input (not for --csv)
         --csv           -f interprets <list> as field number of a CSV according to
                         RFC 4180, instead of white-space separated fields
+        --reorder       -f bypasses fields in the order of <list> and removes the other
+                        fields. It changes the structure of the output. Requires -d
+    --zero-based        Interpret <list> of -c, -f and -l as zero-based numbers.
+                        i.e -f 0 is the first field. 0:<list> or 1:<list> specifies
+                        the origin of each <list> regardless of --zero-based
     -e <string>         Execute <string> on another process that will receive identical
                         standard input as the teip, and numbers given by the result
                         are used as line numbers for bypassing
+        --mask-delimiter <str>
+                        Split the output of -e by <str> in addition to newlines
+        --mask-bitmap   The output of -e is a bitmap. Its n-th line starting with 1 means
+                        the n-th line is bypassed
+    --exclude-pattern <pattern>
+                        Parts of holes matching the regular expression <pattern> are
+                        not bypassed
+    --max-depth <n>     Exit with an error if teip is nested more than <n> levels by
+                        commands invoking teip recursively. Default is 32
+    --regex-size-limit <bytes>
+                        Size limit of the compiled regular expressions of -g and -D
+    --highlight-start <string>
+                        String put before each hole when no command is given
+    --highlight-end <string>
+                        String put after each hole when no command is given
+    --input <file>      Read <file> instead of standard input. It can be given multiple
+                        times, and the files are read in the order like cat
+    --files-from <file> Read files listed in <file> (one path per line) instead of
+                        standard input, after the files of --input
+    --list-fd <n>       Read <list> of -f, -c and -l given as - from the file descriptor
+                        <n> opened by the shell (e.g 3< file), while the data comes from
+                        standard input. Not available on Windows
+        --list-nul      --list-fd reads ranges delimited by NUL instead of commas,
+                        one range per NUL
+    --zstd              Decompress the input compressed by zstd.
+                        Available only if zstd feature is enabled
+    --bzip2             Decompress the input compressed by bzip2.
+                        Available only if bzip2 feature is enabled
+    --auto-decompress   Decompress each file of --input and --files-from compressed by
+                        zstd or bzip2, detected by the header or the extension
+    --buffer-lines <n>  Accumulate the output and flush it every <n> lines. Larger <n>
+                        improves the throughput, and smaller <n> reduces the latency
+    --input-record-sep <byte>
+                        Record separator of the input instead of a newline. Records are
+                        handled like lines, and may include newlines like -z. -z is the
+                        same as --input-record-sep '\\0'. Escape sequences are interpreted
+                        like -d
+    --output-record-sep <bytes>
+                        Record separator of the output instead of the one of the input.
+                        Each separator of the input in the output is replaced with
+                        <bytes>. Escape sequences are interpreted like -d
+    --output-encoding <utf-8|utf-16|utf-16le|utf-16be>
+                        Encoding of the output. The input is read as UTF-8 regardless.
+                        utf-16 is little endian with the byte order mark, and utf-16le
+                        and utf-16be are without it. Default is utf-8
+    --keep-going <n>    Retry reading standard input up to <n> consecutive read errors
+                        instead of exiting with the first one
+    --command-timeout-total <seconds>
+                        Stop reading the input after <seconds> from the start, and exit
+                        with 124 after the lines already read are processed. The
+                        commands spawned by -s and --command-per-line are not executed
+                        after it, and the rest of the output is discarded
+    --input-buffer-readahead <n>
+                        Read up to <n> lines of the input ahead in another thread, so
+                        reading the input overlaps with processing the lines read so far.
+                        It improves the throughput with a slow input
+    --on-error <passthrough|empty|abort|marker>
+                        What replaces a hole whose command spawned by -s fails, or a line
+                        rejected by --strict-utf8. passthrough keeps the original, empty
+                        removes it, abort exits with an error, and marker puts the string
+                        of --on-error-marker. By default, the exit status of the command
+                        is ignored
+    --on-error-marker <string>
+                        String put by --on-error marker. Default is ERROR
+    --on-no-command <dry-run|error>
+                        What happens when the command is omitted. dry-run highlights
+                        holes without executing anything, and error exits with an
+                        error. Default is dry-run
+    --count-to <stdout|stderr>
+                        Where --count prints the number. stdout prints only the number
+                        without executing the command, and stderr prints it in addition
+                        to the output as usual. Default is stdout
+    --summary-file <path>
+                        Write the report of --summary-json to <path> instead of
+                        standard error
 
 FLAGS:
     -h, --help          Prints help information
     -V, --version       Prints version information
-    -s                  Execute new command for each bypassed chunk
+        --features      Prints optional features enabled at build time, one per line
+    -s, --solid         Execute new command for each bypassed chunk
         --chomp         Command spawned by -s receives standard input without trailing
                         newlines
+        --no-chomp      Command spawned by -s receives standard input with trailing
+                        newlines (default). The last one of --chomp and --no-chomp wins
     -I  <replace-str>   Replace the <replace-str> with bypassed chunk in the <command>
                         then -s is forcefully enabled.
-    -v                  Invert the range of bypassing
-    -z                  Line delimiter is NUL instead of a newline
+        --solid-arg     Give each hole to the command as an argument instead of standard
+                        input. {} in the <command> is replaced with the hole, otherwise
+                        the hole is added as the last argument. -s is forcefully enabled
+        --stream-placeholder
+                        Replace {} in the <command> with the name of the input file
+                        given by --input (- for standard input). Not available with -s
+        --normalize-eol <lf|crlf>
+                        Convert line terminators in the output of the command spawned
+                        by -s to LF or CRLF
+        --trim-eol-preserve
+                        Remove the line terminator (CRLF or LF) at the end of each hole
+                        before giving it to the command spawned by -s, and put the same
+                        one after the result instead of the terminator of the result
+        --null-if-empty Print the original hole instead of the result if the command
+                        prints an empty line for it. Not available with -s
+        --require-output
+                        Exit with an error if the command spawned by -s prints nothing
+                        for a non-empty chunk
+        --wrap-before <string>
+                        Put <string> before each result of the command or the built-in
+                        transforms. The parts out of holes are not changed
+        --wrap-after <string>
+                        Put <string> after each result of the command or the built-in
+                        transforms. --group-separator is put outside of them
+        --dedup         Reuse the result of the command spawned by -s for the holes
+                        identical to the previous ones, instead of spawning it again
+        --dedup-cache <n>
+                        Number of results kept by --dedup. The oldest one is discarded
+                        when it is full. Default is 10000
+        --keep-command-stderr-on-stdout
+                        Print the standard error of the command spawned by -s to standard
+                        output just after its result, instead of standard error of teip
+        --group-separator <string>
+                        Put a line of <string> between the results of consecutive holes
+                        if both results of the command spawned by -s span multiple lines
+        --solid-nul     Give each hole to the command with NUL terminator, and read
+                        NUL-terminated results. Holes may include newlines
+        --length-prefixed
+                        Give each hole to the command as the length in bytes, a newline
+                        and the hole, and read results in the same format. Holes and
+                        results may include newlines
+        --command-per-line
+                        Spawn the command for each line including holes, and give it
+                        the holes of the line instead of keeping a single command for the
+                        entire input. State of the command is not carried over to the
+                        next line, at the cost of a process per line. Not available with -s
+        --keep-selection-order
+                        Exit with an error if the command prints more or fewer results
+                        than the holes given, instead of putting the results in wrong
+                        places. Reordered results of the same number are not detected
+        --yes           Run the command even if it is listed in TEIP_DENY_COMMANDS
+    -v, --invert        Invert the range of bypassing
+        --invert-fields Invert the range of -f only, even if -g selects lines
+        --show-both     Print every chunk in a line prefixed with S: (hole) or B: (others)
+                        instead of executing the command
+        --show-bypassed Highlight the parts out of holes instead of holes, without
+                        executing the command
+        --only-selected Print only the holes each followed by a newline, and discard
+                        the other parts instead of executing the command
+    -0                  --only-selected separates the holes by NUL instead of a newline
+        --annotate-count
+                        Print the number of holes in each line before the line, without
+                        executing the command. --count-matches-per-line is an alias
+        --annotate-suffix
+                        --annotate-count prints the number after the line instead
+        --annotate-separator <string>
+                        Separator between the number of --annotate-count and the line.
+                        Default is TAB
+        --dry-run       Highlight holes without executing the command even if it is
+                        given, and print the summary of holes to standard error
+        --replace-map <file>
+                        Replace each hole with the string mapped in <file> instead of
+                        executing the command. Each line of <file> is from<TAB>to, and
+                        holes not found in it are kept
+        --strip-prefix <string>
+                        Remove <string> from the beginning of each hole instead of
+                        executing the command
+        --strip-suffix <string>
+                        Remove <string> from the end of each hole instead of executing
+                        the command
+        --add-prefix <string>
+                        Put <string> before each hole instead of executing the command
+        --add-suffix <string>
+                        Put <string> after each hole instead of executing the command.
+                        Affixes are stripped at most once, and then added
+        --escape-output Show control characters except for TAB and the line terminator
+                        as \\xNN in the output of dry run (no command, --dry-run,
+                        --show-bypassed and --annotate-count). Ignored if the command runs
+        --exit-nomatch  Exit with 1 if no hole is made, like grep
+        --count         Print the number of holes at the end, like grep -c
+        --stats         Print the number of chunks and bytes of holes and the others, and
+                        the number of command invocations to standard error at the end
+        --profile       Print seconds spent in matching, writing holes to the command,
+                        reading results from it, and the commands spawned by -s to
+                        standard error at the end
+        --summary-json  Print the numbers of --stats, the exit status and the seconds of
+                        --profile to standard error at the end as a JSON object.
+                        Nothing is printed if teip exits with an error on the way
+        --verbose       Print the decisions made by teip, like the delimiter of
+                        --auto-delimiter, to standard error
+        --trace         Print each chunk to standard error as a line of SELECT (hole) or
+                        BYPASS (others), the line number, the byte offset in the line,
+                        the length in bytes and the quoted string
+        --nth <list>    Bypassing only <list>-th holes counted through the entire input
+        --every <n>     Bypassing only every <n>-th hole counted through the entire
+                        input, and the other holes are kept as they are
+        --offset <k>    --every starts from the <k>-th hole. Default is <n>
+        --reverse-lines Count lines of -l and --head, and holes of --nth from the last
+                        line. Only for -l, --head and -g without -o. The entire input is
+                        buffered in memory. --reverse is an alias
+    -z, --zero          Line delimiter is NUL instead of a newline
+        --unbuffered    Flush the output every line, and give each hole to the command
+                        immediately. It is useful for interactive pipelines like tail -f.
+                        --line-buffered is an alias
+        --force-tty     Do not warn when standard input is a terminal
+        --strict-utf8   Exit with an error if the input includes an invalid UTF-8 sequence
+                        instead of replacing it with U+FFFD
 
 ALIASES:
     -g <pattern>
@@ -118,35 +441,251 @@ Full documentation at:<https://github.com/greymd/teip>\n",
 )]
 
 struct Args {
-    #[structopt(short = "g", help = "Bypassing lines that match the regular expression <pattern>")]
+    #[structopt(short = "g", long = "regex", help = "Bypassing lines that match the regular expression <pattern>")]
     regex: Option<String>,
     #[structopt(short = "o", help = "-g bypasses only matched parts" )]
     only_matched: bool,
+    #[structopt(long = "between", help = "-g bypasses the parts between the matches instead of the matches")]
+    between: bool,
     #[structopt(short = "G", help = "-g interprets Oniguruma regular expressions.")]
     onig_enabled: bool,
-    #[structopt(short = "f", help = "Bypassing these white-space separated fields")]
+    #[structopt(long = "regex-engine", help = "Regular expression engine of -g, <rust|onig>")]
+    regex_engine: Option<String>,
+    #[structopt(long = "empty-lines", help = "Bypassing empty lines")]
+    empty_lines: bool,
+    #[structopt(long = "blank-lines", help = "Bypassing empty lines and lines of white spaces only")]
+    blank_lines: bool,
+    #[structopt(short = "x", long = "line-regexp", help = "-g matches only the entire line")]
+    line_regexp: bool,
+    #[structopt(short = "f", long = "fields", help = "Bypassing these white-space separated fields")]
     list: Option<String>,
-    #[structopt(short = "d", help = "Use <delimiter> for field delimiter of -f")]
+    #[structopt(long = "field-match-full", help = "--field-match matches only the entire field")]
+    field_match_full: bool,
+    #[structopt(long = "max-fields", alias = "max-field-count", help = "-f splits the line into at most <n> fields")]
+    max_fields: Option<usize>,
+    #[structopt(long = "field-default", help = "-f adds the fields of <list> missing in the line with <value>")]
+    field_default: Option<String>,
+    #[structopt(long = "field-command", help = "-f bypasses only fields for which <command> exits with 0")]
+    field_command: Option<String>,
+    #[structopt(long = "join-fields", alias = "field-join", help = "Join the fields bypassed by -f in each line with <separator> into a single hole")]
+    join_fields: Option<String>,
+    #[structopt(long = "join-placement", help = "Where --join-fields puts the single hole, first or last")]
+    join_placement: Option<String>,
+    #[structopt(long = "field-match", help = "-f bypasses only fields matching the regular expression <pattern>")]
+    field_match: Option<String>,
+    #[structopt(short = "d", long = "delimiter", help = "Use <delimiter> for field delimiter of -f")]
     delimiter: Option<String>,
-    #[structopt(short = "D", help = "Use regular expression <pattern> for field delimiter of -f" )]
+    #[structopt(short = "D", long = "regex-delimiter", help = "Use regular expression <pattern> for field delimiter of -f" )]
     regexp_delimiter: Option<String>,
+    #[structopt(long = "auto-delimiter", alias = "delimiter-from-header", help = "Use the delimiter detected from the first line for -f")]
+    auto_delimiter: bool,
+    #[structopt(long = "verbose", help = "Print the decisions made by teip to stderr")]
+    verbose: bool,
+    #[structopt(long = "delimiter-chars", help = "Use any one of <chars> for field delimiter of -f")]
+    delimiter_chars: Option<String>,
+    #[structopt(long = "exclude-pattern", help = "Parts of holes matching the regular expression <pattern> are not bypassed")]
+    exclude_pattern: Option<String>,
+    #[structopt(long = "regex-size-limit", help = "Size limit in bytes of the compiled regular expressions of -g and -D")]
+    regex_size_limit: Option<usize>,
+    #[structopt(long = "jsonl-key", alias = "json-lines-key", help = "Bypassing the string value of the top-level <key> in each line of JSON Lines")]
+    jsonl_key: Option<String>,
+    #[structopt(long = "jsonl-stringify", help = "--jsonl-key also bypasses a number, boolean or null value as a string")]
+    jsonl_stringify: bool,
     #[structopt(long = "csv", help = "-f interprets <list> as field number of a CSV according to RFC 4180, instead of white-space separated fields" )]
     csv: bool,
     #[structopt(long = "\x75\x6E\x6B\x6F")]
     u: bool,
-    #[structopt(short = "c", help = "Bypassing these characters")]
+    #[structopt(short = "c", long = "chars", help = "Bypassing these characters")]
     char: Option<String>,
-    #[structopt(short = "l", help = "Bypassing those lines")]
+    #[structopt(short = "b", long = "bytes", help = "Bypassing the characters including these bytes")]
+    bytes: Option<String>,
+    #[structopt(long = "expand-tabs", alias = "tab-expand", help = "<list> of -c counts a tab as the columns up to the next tab stop of every <n> columns")]
+    expand_tabs: Option<usize>,
+    #[structopt(long = "no-reexpand", help = "--expand-tabs prints the spaces instead of the original tabs")]
+    no_reexpand: bool,
+    #[structopt(long = "display-columns", help = "<list> of -c is display columns, where a wide character occupies two columns")]
+    display_columns: bool,
+    #[structopt(long = "no-merge", help = "-c makes a hole for each character instead of merging consecutive ones")]
+    no_merge: bool,
+    #[structopt(short = "l", long = "lines", help = "Bypassing those lines")]
     line: Option<String>,
-    #[structopt(short = "s", help = "Execute new command for each bypassed chunk")]
+    #[structopt(long = "continuation", alias = "merge-adjacent-lines", help = "Join each line matching <pattern> to the previous line for -l and -g")]
+    continuation: Option<String>,
+    #[structopt(long = "head", help = "Bypassing first <n> lines")]
+    head: Option<usize>,
+    #[structopt(long = "after-match", number_of_values = 2, value_names = &["pattern", "n"], help = "Bypassing <n> lines after each line matching <pattern>")]
+    after_match: Vec<String>,
+    #[structopt(long = "tail", help = "Bypassing last <n> lines")]
+    tail: Option<usize>,
+    #[structopt(short = "s", long = "solid", help = "Execute new command for each bypassed chunk")]
     solid: bool,
     #[structopt(short = "I", help = "Replace the <replace-str> with bypassed chunk in the <command> then -s is forcefully enabled.")]
     replace: Option<String>,
-    #[structopt(long = "chomp", help = "Command spawned by -s receives standard input without trailing newlines")]
+    #[structopt(long = "chomp", overrides_with = "solid-no-chomp", help = "Command spawned by -s receives standard input without trailing newlines")]
     solid_chomp: bool,
-    #[structopt(short = "v", help = "Invert the range of bypassing")]
+    #[structopt(long = "no-chomp", overrides_with = "solid-chomp", help = "Command spawned by -s receives standard input with trailing newlines (default)")]
+    solid_no_chomp: bool,
+    #[structopt(long = "solid-nul", help = "Give each hole to the command with NUL terminator, and read NUL-terminated results")]
+    solid_nul: bool,
+    #[structopt(long = "length-prefixed", help = "Give each hole to the command with the length prefix, and read length-prefixed results")]
+    length_prefixed: bool,
+    #[structopt(long = "keep-selection-order", help = "Exit with an error if the number of results of the command differs from the number of holes")]
+    keep_selection_order: bool,
+    #[structopt(long = "command-per-line", help = "Spawn the command for each line including holes")]
+    command_per_line: bool,
+    #[structopt(long = "require-output", help = "Exit with an error if the command spawned by -s prints nothing for a chunk")]
+    require_output: bool,
+    #[structopt(long = "normalize-eol", help = "Convert line terminators in the output of the command spawned by -s to <lf|crlf>")]
+    normalize_eol: Option<String>,
+    #[structopt(long = "reverse-lines", alias = "reverse", help = "Count lines of -l and holes of --nth from the last line")]
+    reverse_lines: bool,
+    #[structopt(long = "every", help = "Bypassing only every <n>-th hole counted through the entire input")]
+    every: Option<usize>,
+    #[structopt(long = "offset", help = "--every starts from the <k>-th hole")]
+    offset: Option<usize>,
+    #[structopt(long = "nth", help = "Bypassing only <list>-th holes counted through the entire input")]
+    nth: Option<String>,
+    #[structopt(short = "v", long = "invert", help = "Invert the range of bypassing")]
     invert: bool,
-    #[structopt(short = "z", help = "Line delimiter is NUL instead of a newline")]
+    #[structopt(long = "mask-delimiter", help = "Split the output of -e by <str> in addition to newlines")]
+    mask_delimiter: Option<String>,
+    #[structopt(long = "mask-bitmap", help = "The output of -e is a bitmap, n-th line starting with 1 selects n-th line")]
+    mask_bitmap: bool,
+    #[structopt(long = "yes", alias = "dangerous", help = "Run the command even if it is listed in TEIP_DENY_COMMANDS")]
+    yes: bool,
+    #[structopt(long = "dedup", help = "Reuse the result of the command spawned by -s for identical holes")]
+    dedup: bool,
+    #[structopt(long = "dedup-cache", help = "Number of results kept by --dedup")]
+    dedup_cache: Option<usize>,
+    #[structopt(long = "wrap-before", help = "Put <string> before each result of the command")]
+    wrap_before: Option<String>,
+    #[structopt(long = "wrap-after", help = "Put <string> after each result of the command")]
+    wrap_after: Option<String>,
+    #[structopt(long = "keep-command-stderr-on-stdout", help = "Print the standard error of the command spawned by -s after its result")]
+    keep_command_stderr_on_stdout: bool,
+    #[structopt(long = "group-separator", help = "Put a line of <string> between consecutive multi-line results of -s")]
+    group_separator: Option<String>,
+    #[structopt(long = "on-error", help = "What replaces a hole whose command fails, or a line rejected by --strict-utf8")]
+    on_error: Option<String>,
+    #[structopt(long = "on-no-command", default_value = "dry-run", help = "What happens when the command is omitted")]
+    on_no_command: String,
+    #[structopt(long = "on-error-marker", default_value = "ERROR", help = "String put by --on-error marker")]
+    on_error_marker: String,
+    #[structopt(long = "strict-utf8", help = "Exit with an error if the input includes an invalid UTF-8 sequence")]
+    strict_utf8: bool,
+    #[structopt(long = "input", number_of_values = 1, help = "Read <file> instead of standard input")]
+    input: Vec<String>,
+    #[structopt(long = "files-from", help = "Read files listed in <file> instead of standard input")]
+    files_from: Option<String>,
+    #[structopt(long = "list-fd", help = "Read <list> of -f, -c and -l given as - from the file descriptor <n>")]
+    list_fd: Option<u32>,
+    #[structopt(long = "list-nul", alias = "null-delimited-list", help = "--list-fd reads ranges delimited by NUL")]
+    list_nul: bool,
+    #[structopt(long = "zstd", help = "Decompress the input compressed by zstd")]
+    zstd: bool,
+    #[structopt(long = "bzip2", help = "Decompress the input compressed by bzip2")]
+    bzip2: bool,
+    #[structopt(long = "auto-decompress", help = "Decompress each input file compressed by zstd or bzip2")]
+    auto_decompress: bool,
+    #[structopt(long = "null-if-empty", help = "Print the original hole instead of the result if the command prints an empty line for it")]
+    null_if_empty: bool,
+    #[structopt(long = "unbuffered", alias = "line-buffered", help = "Flush the output every line, and give each hole to the command immediately")]
+    unbuffered: bool,
+    #[structopt(long = "input-record-sep", help = "Record separator of the input instead of a newline")]
+    input_record_sep: Option<String>,
+    #[structopt(long = "output-record-sep", help = "Record separator of the output instead of the one of the input")]
+    output_record_sep: Option<String>,
+    #[structopt(long = "output-encoding", help = "Encoding of the output")]
+    output_encoding: Option<String>,
+    #[structopt(long = "buffer-lines", help = "Flush the output every <n> lines")]
+    buffer_lines: Option<usize>,
+    #[structopt(long = "command-timeout-total", help = "Stop processing the input after <seconds> and exit with 124")]
+    command_timeout_total: Option<f64>,
+    #[structopt(long = "input-buffer-readahead", help = "Read up to <n> lines of the input ahead in another thread")]
+    input_buffer_readahead: Option<usize>,
+    #[structopt(long = "keep-going", help = "Retry reading standard input up to <n> consecutive read errors")]
+    keep_going: Option<usize>,
+    #[structopt(long = "no-escape", help = "-d and --join-fields do not interpret escape sequences")]
+    no_escape: bool,
+    #[structopt(long = "select-delimiters", help = "-f bypasses <list>-th delimiters of -d instead of fields")]
+    select_delimiters: bool,
+    #[structopt(long = "delimiter-regex-captures", help = "Each capture group in the delimiter of -D is also a field")]
+    delimiter_regex_captures: bool,
+    #[structopt(long = "delimiter-replace", number_of_values = 2, value_names = &["pattern", "replacement"], help = "Replace <pattern> in each delimiter of -f with <replacement>")]
+    delimiter_replace: Vec<String>,
+    #[structopt(long = "output-delimiter", help = "Use <delimiter> for field delimiter of the output of -f")]
+    output_delimiter: Option<String>,
+    #[structopt(long = "record-bytes", alias = "chunked", help = "-c reads the input as records of <n> bytes")]
+    record_bytes: Option<usize>,
+    #[structopt(long = "slurp", help = "-c reads the entire input as one record")]
+    slurp: bool,
+    #[structopt(long = "zero-based", help = "Interpret <list> of -c, -f and -l as zero-based numbers")]
+    zero_based: bool,
+    #[structopt(long = "reorder", help = "-f bypasses fields in the order of <list> and removes the other fields")]
+    reorder: bool,
+    #[structopt(long = "exit-nomatch", help = "Exit with 1 if no hole is made")]
+    exit_nomatch: bool,
+    #[structopt(long = "show-bypassed", help = "Highlight the parts out of holes instead of holes, without executing the command")]
+    show_bypassed: bool,
+    #[structopt(long = "max-depth", default_value = "32", help = "Exit with an error if teip is nested more than <n> levels")]
+    max_depth: usize,
+    #[structopt(long = "force-tty", help = "Do not warn when standard input is a terminal")]
+    force_tty: bool,
+    #[structopt(long = "solid-arg", help = "Give each hole to the command spawned by -s as an argument instead of standard input")]
+    solid_arg: bool,
+    #[structopt(long = "stream-placeholder", help = "Replace {} in the <command> with the name of the input file")]
+    stream_placeholder: bool,
+    #[structopt(long = "trim-eol-preserve", help = "Keep the line terminator (CRLF or LF) at the end of each hole given to the command spawned by -s as it is")]
+    trim_eol_preserve: bool,
+    #[structopt(long = "dry-run", help = "Highlight holes without executing the command, and print the summary of holes to stderr")]
+    dry_run: bool,
+    #[structopt(long = "replace-map", help = "Replace each hole found in the table of <file> instead of executing the command")]
+    replace_map: Option<String>,
+    #[structopt(long = "strip-prefix", help = "Remove <string> from the beginning of each hole instead of executing the command")]
+    strip_prefix: Option<String>,
+    #[structopt(long = "strip-suffix", help = "Remove <string> from the end of each hole instead of executing the command")]
+    strip_suffix: Option<String>,
+    #[structopt(long = "add-prefix", help = "Put <string> before each hole instead of executing the command")]
+    add_prefix: Option<String>,
+    #[structopt(long = "add-suffix", help = "Put <string> after each hole instead of executing the command")]
+    add_suffix: Option<String>,
+    #[structopt(long = "escape-output", help = "Show control characters in the output of dry run as escape sequences")]
+    escape_output: bool,
+    #[structopt(long = "trace", help = "Print each chunk with SELECT or BYPASS and its position to stderr")]
+    trace: bool,
+    #[structopt(long = "profile", help = "Print seconds spent in matching and the command to stderr at the end")]
+    profile: bool,
+    #[structopt(long = "count", help = "Print the number of holes at the end")]
+    count: bool,
+    #[structopt(long = "count-to", help = "Where --count prints the number, stdout or stderr")]
+    count_to: Option<String>,
+    #[structopt(long = "stats", help = "Print the number of chunks, bytes and command invocations to stderr at the end")]
+    stats: bool,
+    #[structopt(long = "summary-json", help = "Print the report of the run to stderr at the end as a JSON object")]
+    summary_json: bool,
+    #[structopt(long = "summary-file", help = "Write the report of --summary-json to <path> instead of stderr")]
+    summary_file: Option<String>,
+    #[structopt(long = "annotate-count", alias = "count-matches-per-line", help = "Print the number of holes in each line before the line, without executing the command")]
+    annotate_count: bool,
+    #[structopt(long = "annotate-suffix", help = "--annotate-count prints the number after the line instead")]
+    annotate_suffix: bool,
+    #[structopt(long = "annotate-separator", default_value = "\t", help = "Separator between the number of --annotate-count and the line")]
+    annotate_separator: String,
+    #[structopt(long = "only-selected", alias = "output-null-on-select", help = "Print only the holes separated by newlines instead of executing the command")]
+    only_selected: bool,
+    #[structopt(short = "0", help = "--only-selected separates the holes by NUL instead of a newline")]
+    only_selected_null: bool,
+    #[structopt(long = "show-both", help = "Print every chunk in a line prefixed with S: (hole) or B: (others) instead of executing the command")]
+    show_both: bool,
+    #[structopt(long = "invert-fields", help = "Invert the range of -f only, even if -g selects lines")]
+    invert_fields: bool,
+    #[structopt(long = "include-eol", alias = "include-eol-in-match", help = "-g without -o matches the pattern against the line including its terminator")]
+    include_eol: bool,
+    #[structopt(long = "min-match-length", help = "-og ignores matches shorter than <n> characters")]
+    min_match_length: Option<usize>,
+    #[structopt(long = "group", help = "-og bypasses only <n>-th capture group of each match")]
+    group: Option<usize>,
+    #[structopt(short = "z", long = "zero", help = "Line delimiter is NUL instead of a newline")]
     zero: bool,
     #[structopt(short = "e", help = "Execute <string> on another process that will receive identical standard input as the teip, and numbers given by the result are used as line numbers for bypassing")]
     exoffload_pipeline: Option<String>,
@@ -160,43 +699,208 @@ struct Args {
     sed: Option<String>,
     #[structopt(long = "awk", help = "Alias of -e 'awk \"<pattern>{print NR}\"'")]
     awk: Option<String>,
+    #[structopt(long = "highlight-start", help = "String put before each hole when no command is given")]
+    highlight_start: Option<String>,
+    #[structopt(long = "highlight-end", help = "String put after each hole when no command is given")]
+    highlight_end: Option<String>,
     #[structopt(long = "completion")]
     completion: Option<String>,
+    #[structopt(long = "features", help = "Prints optional features enabled at build time, one per line")]
+    features: bool,
     #[structopt(name = "command")]
     commands: Vec<String>,
 }
 
+/// Optional features which are enabled at build time (`cargo build --features ...`)
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "oniguruma") {
+        features.push("oniguruma");
+    }
+    if cfg!(feature = "zstd") {
+        features.push("zstd");
+    }
+    if cfg!(feature = "bzip2") {
+        features.push("bzip2");
+    }
+    features
+}
+
+/// Strings put before and after holes in dry run mode.
+/// --highlight-start/--highlight-end > TEIP_HIGHLIGHT > TEIP_HIGHLIGHT_START/TEIP_HIGHLIGHT_END
+fn highlight(start: Option<String>, end: Option<String>) -> (String, String) {
+    let (default_start, default_end) = match *HIGHLIGHT {
+        Some(ref template) => {
+            let hl: Vec<&str> = template.split("{}").collect();
+            if hl.len() != 2 {
+                error_exit("Invalid format in TEIP_HIGHLIGHT variable: it must include exactly one {}")
+            }
+            (hl[0].to_string(), hl[1].to_string())
+        }
+        None => {
+            let hl: Vec<&str> = DEFAULT_HIGHLIGHT.split("{}").collect();
+            (
+                HIGHLIGHT_START.clone().unwrap_or_else(|| hl[0].to_string()),
+                HIGHLIGHT_END.clone().unwrap_or_else(|| hl[1].to_string()),
+            )
+        }
+    };
+    (start.unwrap_or(default_start), end.unwrap_or(default_end))
+}
+
+/// Strings put before and after the parts out of holes ( --show-bypassed )
+fn highlight_bypass() -> (String, String) {
+    let hl: Vec<&str> = HIGHLIGHT_BYPASS.split("{}").collect();
+    if hl.len() != 2 {
+        error_exit("Invalid format in TEIP_HIGHLIGHT_BYPASS variable: it must include exactly one {}")
+    }
+    (hl[0].to_string(), hl[1].to_string())
+}
+
+/// POSIX character classes are checked in the same way for -g of both engines and -D
+fn check_posix_classes(pattern: &str) {
+    if let Err(e) = stringutils::check_posix_classes(pattern) {
+        error_exit(&format!("Invalid regular expression '{}'\n{}", pattern, e));
+    }
+}
+
+/// Compile the regular expression given by users.
+/// Exit with the message which includes the pattern if it is invalid.
+/// onig_hint suggests -G for the syntax not supported, only for the pattern of -g.
+fn compile_regex(mode: &str, pattern: &str, size_limit: Option<usize>, onig_hint: bool) -> Regex {
+    let mut builder = RegexBuilder::new(&(mode.to_owned() + pattern));
+    if let Some(limit) = size_limit {
+        builder.size_limit(limit);
+    }
+    builder.build().unwrap_or_else(|e| match e {
+        regex::Error::CompiledTooBig(limit) => error_exit(&format!(
+            "Regular expression '{}' exceeds the size limit ({} bytes). Raise the limit with --regex-size-limit <bytes>",
+            pattern, limit
+        )),
+        _ => {
+            let mut msg = format!("Invalid regular expression '{}'\n{}", pattern, e);
+            if onig_hint && msg.contains("not supported") {
+                msg.push_str("\nHint: -G enables Oniguruma regular expressions which support look-around and backreferences");
+            }
+            error_exit(&msg)
+        }
+    })
+}
+
 fn main() {
     env_logger::init();
 
     // ***** Parse options and prepare configures *****
-    let args: Args = Args::from_args();
+    let mut args: Args = Args::from_args();
 
-    debug!("{:?}", args);
+    // --list-fd gives <list> of -f, -c and -l written as -
+    if let Some(fd) = args.list_fd {
+        let lists = vec![&mut args.list, &mut args.char, &mut args.line];
+        let mut lists: Vec<&mut String> = lists.into_iter().flatten().filter(|l| l.as_str() == "-").collect();
+        if lists.is_empty() {
+            error_exit("--list-fd requires - as <list> of -f, -c or -l");
+        }
+        let list = input::read_list_fd(fd);
+        // --list-nul gives a range per NUL
+        let list = if args.list_nul {
+            list::converter::from_nul_delimited(&list)
+                .unwrap_or_else(|e| error_exit(&format!("--list-fd {}: {}", fd, e)))
+        } else {
+            list
+        };
+        for l in lists.iter_mut() {
+            **l = list.clone();
+        }
+    }
 
-    if HL.len() < 2 {
-        error_exit("Invalid format in TEIP_HIGHLIGHT variable")
+    if args.list_nul && args.list_fd.is_none() {
+        error_exit("--list-nul requires --list-fd");
     }
 
+    debug!("{:?}", args);
+
+    let (hl_start, hl_end) = highlight(args.highlight_start.clone(), args.highlight_end.clone());
+
     let flag_zero = args.zero;
-    let cmds = args.commands;
-    let flag_only = args.only_matched;
+    // If no command is given, the command in TEIP_CMD is used
+    let mut cmds = match env::var("TEIP_CMD") {
+        Ok(ref v) if args.commands.is_empty() && !v.trim().is_empty() => shell_words::split(v)
+            .unwrap_or_else(|e| error_exit(&format!("Invalid format in TEIP_CMD variable: {}", e))),
+        _ => args.commands,
+    };
+    // --between selects the parts between the matches of -g, the same as -o -v
+    if args.between && (args.regex.is_none() || args.invert || args.after.is_some() || args.before.is_some() || args.center.is_some()) {
+        error_exit("--between requires -g, and cannot be used with -v, -A, -B and -C");
+    }
+    // --empty-lines and --blank-lines are the same as -g with the pattern matching such lines
+    if args.empty_lines || args.blank_lines {
+        let context = args.after.is_some() || args.before.is_some() || args.center.is_some();
+        if args.regex.is_some() || args.only_matched || args.between || context {
+            error_exit("--empty-lines and --blank-lines cannot be used with -g, -o, --between, -A, -B and -C");
+        }
+        let pattern = if args.blank_lines { "\\A\\s*\\z" } else { "\\A\\z" };
+        args.regex = Some(pattern.to_string());
+    }
+    let flag_only = args.only_matched || args.between;
     let mut flag_regex = args.regex.is_some();
-    let flag_onig = args.onig_enabled;
+    // --regex-engine option, -G is an alias of --regex-engine onig
+    let flag_onig = match (args.regex_engine.as_deref(), args.onig_enabled) {
+        (Some("rust"), true) => error_exit("-G cannot be used with --regex-engine rust"),
+        (None, false) | (Some("rust"), false) => false,
+        (None, true) | (Some("onig"), _) if cfg!(feature = "oniguruma") => true,
+        (None, true) | (Some("onig"), _) => error_exit("Regular expression engine 'onig' is not enabled in this build"),
+        (Some(e), _) => error_exit(&format!("Invalid value for --regex-engine: '{}' (rust or onig is expected)", e)),
+    };
+    let flag_line_regexp = args.line_regexp;
     let mut flag_solid = args.solid;
-    let flag_solid_chomp = args.solid_chomp;
-    let flag_replace = args.replace.is_some();
-    let flag_invert = args.invert;
-    let flag_char = args.char.is_some();
-    let flag_lines = args.line.is_some();
-    let flag_field = args.list.is_some();
-    let flag_delimiter = args.delimiter.is_some();
+    let flag_solid_chomp = args.solid_chomp && !args.solid_no_chomp;
+    // --solid-arg gives each hole as an argument of the command instead of stdin.
+    // {} in the command is replaced with the hole, otherwise the hole is appended.
+    let replace_str = if args.solid_arg {
+        if args.replace.is_some() {
+            error_exit("--solid-arg cannot be used with -I");
+        }
+        if !cmds.is_empty() && !cmds.iter().any(|c| c.contains("{}")) {
+            cmds.push("{}".to_string());
+        }
+        Some("{}".to_string())
+    } else {
+        args.replace.clone()
+    };
+    let flag_replace = replace_str.is_some();
+    let flag_invert = args.invert || args.between;
+    let flag_char = args.char.is_some() || args.bytes.is_some();
+    let flag_lines = args.line.is_some() || args.head.is_some();
+    let flag_tail = args.tail.is_some();
+    let flag_after_match = !args.after_match.is_empty();
+    let flag_field_match = args.field_match.is_some() || args.field_command.is_some();
+    let flag_field = args.list.is_some() || flag_field_match;
+    let flag_invert_fields = args.invert_fields;
+    let flag_delimiter = args.delimiter.is_some() || args.auto_delimiter;
+    let flag_reorder = args.reorder;
     let flag_csv = args.csv;
-    let delimiter = args.delimiter.as_ref().map(|s| s.as_str()).unwrap_or("");
+    let flag_jsonl = args.jsonl_key.is_some();
+    let output_delimiter = args.output_delimiter.as_deref();
+    // -d interprets escape sequences like \t unless --no-escape is given
+    let delimiter = match args.delimiter {
+        Some(ref d) if !args.no_escape => stringutils::unescape(d).unwrap_or_else(|e| error_exit(&e)),
+        Some(ref d) => d.to_string(),
+        None => "".to_string(),
+    };
+    let delimiter = delimiter.as_str();
     let flag_regex_delimiter = args.regexp_delimiter.is_some();
     let mut flag_exoffload = args.exoffload_pipeline.is_some();
     let mut exoffload_pipeline = args.exoffload_pipeline.as_ref().map(|s| s.as_str()).unwrap_or("");
 
+    // -x wraps <pattern> so that it matches only the entire line
+    let regex_pattern = args.regex.as_ref().map(|p| {
+        if flag_line_regexp {
+            format!("\\A(?:{})\\z", p)
+        } else {
+            p.to_string()
+        }
+    });
+
     let mut regex_mode = String::new();
     let mut regex_compiled = Regex::new("").unwrap();
     let mut onig_regex_raw = &String::new();
@@ -223,10 +927,86 @@ fn main() {
         std::process::exit(0);
     }
 
+    if args.features {
+        for feature in enabled_features() {
+            println!("{}", feature);
+        }
+        std::process::exit(0);
+    }
+
     if args.u {
         u();
     }
 
+    // Commands spawned by teip may run teip again recursively.
+    // TEIP_DEPTH counts the nesting, and it is given to the commands.
+    let depth = match env::var("TEIP_DEPTH") {
+        Ok(v) => v
+            .parse::<usize>()
+            .unwrap_or_else(|_| error_exit(&format!("Invalid value in TEIP_DEPTH variable: '{}'", v))),
+        Err(_) => 0,
+    };
+    if depth >= args.max_depth {
+        error_exit(&format!(
+            "teip is nested too deeply ({} levels). Recursive invocation of teip may be in the command, or raise --max-depth",
+            depth
+        ));
+    }
+    env::set_var("TEIP_DEPTH", (depth + 1).to_string());
+
+    // Files are given only by options, because all trailing arguments are the command
+    let mut input = input::Input::default();
+    if !args.input.is_empty() || args.files_from.is_some() {
+        let mut files = args.input.clone();
+        if let Some(ref path) = args.files_from {
+            files.extend(input::read_files_from(path));
+        }
+        input.set_files(files);
+    }
+    if args.zstd {
+        if !cfg!(feature = "zstd") {
+            error_exit("--zstd is not enabled in this build");
+        }
+        input.set_decompress(input::Decompress::Zstd);
+    }
+    if args.bzip2 {
+        if !cfg!(feature = "bzip2") {
+            error_exit("--bzip2 is not enabled in this build");
+        }
+        input.set_decompress(input::Decompress::Bzip2);
+    }
+    if args.auto_decompress {
+        if input.is_stdin() {
+            error_exit("--auto-decompress is available only with --input and --files-from");
+        }
+        input.set_decompress(input::Decompress::Auto);
+    }
+
+    // Running teip without piped input just waits for typing, which confuses newcomers
+    if input.is_stdin() && io::stdin().is_terminal() && !args.force_tty {
+        msg_error("reading standard input from the terminal. Pipe the input to teip, or give --force-tty to hide this message");
+    }
+
+    // --summary-file requires --summary-json
+    if args.summary_file.is_some() && !args.summary_json {
+        error_exit("--summary-file requires --summary-json");
+    }
+    let profile = if args.profile || args.summary_json {
+        Profile::new()
+    } else {
+        Profile::default()
+    };
+    let on_error = match args.on_error {
+        Some(ref mode) => Some(errors::OnError::parse(mode, &args.on_error_marker).unwrap_or_else(|e| error_exit(&e))),
+        None => None,
+    };
+    if args.strict_utf8 {
+        input.set_strict_utf8(on_error.clone());
+    }
+    if let Some(n) = args.keep_going {
+        input.set_keep_going(n);
+    }
+
     // If any of -A, -B, -C is specified, set -e option and set regex flag off
     //   "-A 1 -g pattern" => "-e 'grep -A 1 pattern'"
     //   "-B 1 -g pattern" => "-e 'grep -B 1 pattern'"
@@ -244,6 +1024,9 @@ fn main() {
             grep_args.push("-C".to_string());
             grep_args.push(n.to_string());
         }
+        if flag_line_regexp {
+            grep_args.push("-x".to_string());
+        }
         if let Some(ref pattern) = args.regex {
             grep_args.push(pattern.to_string());
         }
@@ -266,7 +1049,7 @@ fn main() {
     // -G switches regex mode
     if flag_onig && flag_regex {
         flag_regex = false;
-        onig_regex_raw = args.regex.as_ref().unwrap();
+        onig_regex_raw = regex_pattern.as_ref().unwrap();
     }
 
     // If any mandatory flags is not enabled, show help and exit.
@@ -275,7 +1058,10 @@ fn main() {
           flag_onig      ||
           flag_field     ||
           flag_char      ||
-          flag_lines )
+          flag_lines     ||
+          flag_tail      ||
+          flag_jsonl     ||
+          flag_after_match )
         // Even though --csv is specified, -f is not specified, show help and exit.
         || ( flag_csv && !flag_field)
     {
@@ -283,9 +1069,23 @@ fn main() {
         std::process::exit(1);
     }
 
+    // --zero-based interprets <list> of -c, -f and -l as zero-based numbers.
+    // "0:" or "1:" before each <list> overrides it.
+    let flag_zero_based = args.zero_based;
+    let to_list = |s: &String| {
+        list::converter::resolve_origin(s, flag_zero_based).unwrap_or_else(|e| error_exit(&e))
+    };
+    let char_arg = args.char.as_ref().or(args.bytes.as_ref()).map(to_list);
+    // --field-match and --field-command without -f select among all fields
+    let field_arg = match args.list {
+        Some(ref s) => Some(to_list(s)),
+        None if flag_field_match => Some("1-".to_string()),
+        None => None,
+    };
+    let line_arg = args.line.as_ref().map(to_list);
+
     // Parse argument of -c option if specified
-    let char_list = args
-        .char
+    let char_list = char_arg
         .as_ref()
         .and_then(|s| {
             list::converter::to_ranges(s.as_str(), flag_invert)
@@ -294,21 +1094,164 @@ fn main() {
         })
         .unwrap_or_else(|| list::converter::to_ranges("1", true).unwrap());
 
+    // -g without -o selects lines in which -f is applied.
+    // Then -v inverts the lines, and only --invert-fields inverts the fields.
+    let flag_regex_fields = flag_regex && !flag_only && flag_field && !flag_csv;
+    // With --field-match and --field-command, -v inverts the match of the fields instead of <list>.
+    let flag_field_match_invert = flag_field_match && flag_invert && !flag_regex_fields;
+    if flag_invert && flag_invert_fields && !(flag_regex_fields || flag_field_match) {
+        error_exit("-v and --invert-fields cannot be used together unless -g, --field-match or --field-command is given");
+    }
+    let flag_field_invert = if flag_regex_fields || flag_field_match {
+        flag_invert_fields
+    } else {
+        flag_invert || flag_invert_fields
+    };
+
     // Parse argument of -f option if specified
-    let field_list = args
-        .list
+    let field_list = field_arg
         .as_ref()
         .and_then(|s| {
-            list::converter::to_ranges(s.as_str(), flag_invert)
+            list::converter::to_ranges(s.as_str(), flag_field_invert)
                 .map_err(|e| error_exit(&e.to_string()))
                 .ok()
         })
         .unwrap_or_else(|| list::converter::to_ranges("1", true).unwrap());
 
+    // --slurp makes a record of the entire input
+    if args.slurp && !flag_char {
+        error_exit("--slurp is available only with -c");
+    }
+    // -b selects the characters by the bytes instead of -c
+    if args.bytes.is_some() && (args.char.is_some() || args.display_columns || args.expand_tabs.is_some()) {
+        error_exit("-b cannot be used with -c, --display-columns and --expand-tabs");
+    }
+    // --expand-tabs counts the columns of -c with the tab stops
+    match args.expand_tabs {
+        Some(_) if !flag_char => error_exit("--expand-tabs is available only with -c"),
+        Some(0) => error_exit("<n> of --expand-tabs must be greater than 0"),
+        None if args.no_reexpand => error_exit("--no-reexpand requires --expand-tabs"),
+        _ => {}
+    }
+    // --jsonl-key is a selector by itself
+    if flag_jsonl
+        && (flag_exoffload || flag_regex || flag_onig || flag_field || flag_char || flag_lines || flag_tail || flag_after_match || flag_invert)
+    {
+        error_exit("--jsonl-key cannot be used with the other selectors and -v");
+    }
+    if args.jsonl_stringify && !flag_jsonl {
+        error_exit("--jsonl-stringify requires --jsonl-key");
+    }
+    // --record-bytes makes records of fixed length instead of lines
+    if let Some(n) = args.record_bytes {
+        if !flag_char || args.slurp {
+            error_exit("--record-bytes is available only with -c, and not with --slurp");
+        }
+        if n == 0 {
+            error_exit("<n> of --record-bytes must be greater than 0");
+        }
+    }
+
+    if args.field_match_full && args.field_match.is_none() {
+        error_exit("--field-match-full requires --field-match");
+    }
+    if flag_field_match && (flag_csv || flag_reorder || args.select_delimiters) {
+        error_exit("--field-match and --field-command cannot be used with --csv, --reorder and --select-delimiters");
+    }
+    if args.field_match.is_some() && args.field_command.is_some() {
+        error_exit("--field-match and --field-command cannot be used together");
+    }
+    // --join-fields gives the fields in a line to the command at once, for row-level aggregation
+    let join_last = match args.join_placement.as_deref() {
+        Some(_) if args.join_fields.is_none() => error_exit("--join-placement requires --join-fields"),
+        None | Some("first") => false,
+        Some("last") => true,
+        Some(s) => error_exit(&format!("Invalid value for --join-placement: '{}' (first or last is expected)", s)),
+    };
+    if args.join_fields.is_some() && !flag_field {
+        error_exit("--join-fields requires -f");
+    }
+
+    if !args.delimiter_replace.is_empty()
+        && (!flag_field || flag_delimiter || flag_csv || output_delimiter.is_some() || args.delimiter_regex_captures)
+    {
+        error_exit("--delimiter-replace is available only with -f without -d, --csv, --output-delimiter and --delimiter-regex-captures");
+    }
+
+    // --max-fields stops splitting the line like awk's split with the limit
+    if let Some(n) = args.max_fields {
+        if !flag_field || flag_csv {
+            error_exit("--max-fields is available only with -f, and not with --csv");
+        }
+        if n == 0 {
+            error_exit("<n> of --max-fields must be greater than 0");
+        }
+    }
+
+    // --field-default adds the missing fields of <list> to short lines
+    if args.field_default.is_some()
+        && (!flag_field || flag_csv || flag_reorder || args.select_delimiters || args.delimiter_regex_captures)
+    {
+        error_exit("--field-default is available only with -f, and not with --csv, --reorder, --select-delimiters and --delimiter-regex-captures");
+    }
+    if args.field_default.is_some() && flag_field_invert {
+        error_exit("--field-default cannot be used with -v and --invert-fields which invert <list>");
+    }
+
+    // --after-match is a selector of lines by itself
+    if flag_after_match && (flag_exoffload || flag_regex || flag_onig || flag_field || flag_char || flag_lines || flag_tail) {
+        error_exit("--after-match cannot be used with -g, -G, -c, -l, -f, -e, --head and --tail");
+    }
+
+    // --select-delimiters makes holes of the delimiters of -d
+    if args.select_delimiters && (!flag_field || !flag_delimiter || flag_reorder) {
+        error_exit("--select-delimiters is available only with -f and -d, and not with --reorder");
+    }
+
+    // --auto-delimiter decides -d by itself
+    if args.auto_delimiter && (!flag_field || args.delimiter.is_some() || flag_regex_delimiter || args.delimiter_chars.is_some() || flag_csv) {
+        error_exit("--auto-delimiter is available only with -f, and not with -d, -D, --delimiter-chars and --csv");
+    }
+
+    if args.delimiter_chars.is_some() && (flag_delimiter || flag_regex_delimiter || flag_csv) {
+        error_exit("--delimiter-chars cannot be used with -d, -D and --csv");
+    }
+
+    if args.delimiter_regex_captures && (!flag_regex_delimiter || flag_delimiter || flag_csv || flag_reorder) {
+        error_exit("--delimiter-regex-captures is available only with -f and -D");
+    }
+    if args.delimiter_regex_captures && output_delimiter.is_some() {
+        error_exit("--delimiter-regex-captures cannot be used with --output-delimiter");
+    }
+
+    if output_delimiter.is_some() && flag_csv {
+        error_exit("--output-delimiter cannot be used with --csv");
+    }
+
+    // --reorder keeps the order and duplicates of <list>
+    if flag_reorder && !(flag_field && flag_delimiter) {
+        error_exit("--reorder is available only with -f and -d");
+    }
+    if flag_reorder && flag_field_invert {
+        error_exit("--reorder cannot be used with inverted fields");
+    }
+    let field_order = if flag_reorder {
+        list::converter::to_ordered_ranges(field_arg.as_ref().unwrap())
+            .unwrap_or_else(|e| error_exit(&e))
+    } else {
+        vec![]
+    };
+
+    if args.head == Some(0) || args.tail == Some(0) {
+        error_exit("<n> of --head and --tail must be greater than 0");
+    }
+
     // Parse argument of -l option if specified
-    let line_list = args
-        .line
+    // --head <n> is identical to -l 1-<n>
+    let head_list = args.head.map(|n| format!("1-{}", n));
+    let line_list = line_arg
         .as_ref()
+        .or(head_list.as_ref())
         .and_then(|s| {
             list::converter::to_ranges(s.as_str(), flag_invert)
                 .map_err(|e| error_exit(&e.to_string()))
@@ -321,17 +1264,61 @@ fn main() {
         regex_mode = "(?ms)".to_string();
         line_end = b'\0';
     }
+    // --input-record-sep generalizes -z to any byte
+    if let Some(ref sep) = args.input_record_sep {
+        if flag_zero {
+            error_exit("--input-record-sep cannot be used with -z");
+        }
+        line_end = match stringutils::unescape(sep).unwrap_or_else(|e| error_exit(&e)).as_bytes() {
+            &[b] => b,
+            _ => error_exit(&format!("<byte> of --input-record-sep must be a single byte: '{}'", sep)),
+        };
+        if line_end != b'\n' {
+            // Records may include newlines like -z
+            regex_mode = "(?ms)".to_string();
+        }
+    }
+    // --input-buffer-readahead reads the input while the lines read so far are processed
+    if let Some(n) = args.input_buffer_readahead {
+        input.set_readahead(n, line_end);
+    }
+    // --command-timeout-total stops reading the input when the time expires
+    if let Some(secs) = args.command_timeout_total {
+        input.set_time_limit(secs, line_end);
+    }
 
+    if let Some(ref pattern) = regex_pattern {
+        check_posix_classes(pattern);
+    }
     if flag_regex {
         // Use default regex engine
         regex_compiled =
-            Regex::new(&(regex_mode.to_owned() + args.regex.as_ref().unwrap_or(&"".to_owned())))
-                .unwrap_or_else(|e| error_exit(&e.to_string()));
+            compile_regex(&regex_mode, regex_pattern.as_ref().unwrap_or(&"".to_owned()), args.regex_size_limit, cfg!(feature = "oniguruma"));
+    }
+
+    // --group selects a capture group of -og
+    if let Some(g) = args.group {
+        if !(flag_regex && flag_only) {
+            error_exit("--group is available only with -og, and not with -G");
+        }
+        if g >= regex_compiled.captures_len() {
+            error_exit(&format!("<pattern> of -g does not have the group {}", g));
+        }
+    }
+
+    // --min-match-length ignores short matches of -og
+    if let Some(n) = args.min_match_length {
+        if !(flag_regex && flag_only) || flag_onig || flag_regex_fields {
+            error_exit("--min-match-length is available only with -og, and not with -G");
+        }
+        if n == 0 {
+            error_exit("<n> of --min-match-length must be greater than 0");
+        }
     }
 
     if flag_onig {
         // If -G option is specified, change regex engine
-        if flag_zero {
+        if line_end != b'\n' {
             onig_regex_compiled =
                 onig::new_option_multiline_regex(onig_regex_raw);
         } else {
@@ -341,87 +1328,558 @@ fn main() {
 
     // If -D option is specified, compile regex delimiter
     if flag_regex_delimiter {
+        check_posix_classes(args.regexp_delimiter.as_ref().unwrap());
         regex_delimiter =
-            Regex::new(&(regex_mode.to_string() + args.regexp_delimiter.as_ref().unwrap()))
-                .unwrap_or_else(|e| error_exit(&e.to_string()));
+            compile_regex(&regex_mode, args.regexp_delimiter.as_ref().unwrap(), args.regex_size_limit, false);
+    } else if let Some(ref chars) = args.delimiter_chars {
+        // --delimiter-chars is a character class of the chars
+        let chars = if args.no_escape {
+            chars.to_string()
+        } else {
+            stringutils::unescape(chars).unwrap_or_else(|e| error_exit(&e))
+        };
+        if chars.is_empty() {
+            error_exit("<chars> of --delimiter-chars is empty");
+        }
+        let class: String = chars.chars().map(|c| regex::escape(&c.to_string())).collect();
+        regex_delimiter = compile_regex(&regex_mode, &format!("[{}]", class), args.regex_size_limit, false);
     } else {
         regex_delimiter = REGEX_WS.clone();
     }
 
-    // If no command is specified, set dryrun mode
-    if cmds.len() > 0 {
-        flag_dryrun = false;
-    }
+    // --field-match is applied to each field of -f
+    // --field-match-full wraps <pattern> so that it matches only the entire field like -x
+    // --field-command executes <command> for each distinct field
+    let field_filter = match (&args.field_match, &args.field_command) {
+        (Some(p), _) if args.field_match_full => Some(procs::FieldFilter::Regex(
+            compile_regex(&regex_mode, &format!("\\A(?:{})\\z", p), args.regex_size_limit, false),
+        )),
+        (Some(p), _) => Some(procs::FieldFilter::Regex(compile_regex(&regex_mode, p, args.regex_size_limit, false))),
+        (None, Some(c)) => Some(procs::FieldFilter::command(c)),
+        (None, None) => None,
+    };
+    let field_match = field_filter.as_ref().map(|f| (f, flag_field_match_invert));
+
+    // --after-match <pattern> <n>
+    let after_match = match args.after_match.as_slice() {
+        [pattern, n] => Some((
+            compile_regex(&regex_mode, pattern, args.regex_size_limit, false),
+            n.parse::<usize>()
+                .unwrap_or_else(|_| error_exit(&format!("Invalid number of lines for --after-match: '{}'", n))),
+        )),
+        _ => None,
+    };
+
+    // --delimiter-replace substitutes the pattern in each delimiter of -f
+    let delimiter_replace_regex = match args.delimiter_replace.first() {
+        Some(p) => Some(compile_regex(&regex_mode, p, args.regex_size_limit, false)),
+        None => None,
+    };
+    let delimiter_replace = match (&delimiter_replace_regex, args.delimiter_replace.get(1)) {
+        (Some(re), Some(replacement)) => Some((re, replacement.as_str())),
+        _ => None,
+    };
 
-    if (!flag_only && flag_regex) || flag_lines || flag_exoffload || flag_csv {
+    if (!flag_only && flag_regex && !flag_regex_fields) || flag_lines || flag_tail || flag_exoffload || flag_csv || flag_after_match {
         // The process requires to process whole stdin, not line by line
         process_each_line = false;
     }
 
+    // --reverse-lines buffers the entire input of the line path
+    // --include-eol lets the pattern of -g see the line terminator
+    if args.include_eol && (process_each_line || flag_onig || args.reverse_lines || flag_lines || flag_tail || !flag_regex) {
+        error_exit("--include-eol is available only with -g without -o and -G");
+    }
+
+    // --continuation makes logical records of -l and -g
+    let continuation = match args.continuation {
+        Some(_) if process_each_line || flag_after_match || args.reverse_lines || flag_onig || !(flag_lines || (flag_regex && !flag_tail)) => {
+            error_exit("--continuation is available only with -l, --head and -g without -o and -G");
+        }
+        Some(ref p) => Some(compile_regex(&regex_mode, p, args.regex_size_limit, false)),
+        None => None,
+    };
+
+    if args.reverse_lines && (process_each_line || flag_onig || !(flag_lines || flag_regex)) {
+        error_exit("--reverse-lines is available only with -l, --head and -g without -o and -G");
+    }
+
     if flag_replace {
         // If -I option is specified, enable -s option
         flag_solid = true;
     }
+    // --normalize-eol option
+    let normalize_eol = args.normalize_eol.as_ref().map(|s| match s.as_str() {
+        "lf" => "\n".to_string(),
+        "crlf" => "\r\n".to_string(),
+        _ => error_exit(&format!("Invalid value for --normalize-eol: '{}' (lf or crlf is expected)", s)),
+    });
+
+    // --replace-map, --strip-prefix, --strip-suffix, --add-prefix and --add-suffix transform holes in-process
+    let mut transform = Transform {
+        replace_map: args
+            .replace_map
+            .as_ref()
+            .map(|path| Transform::read_map(path).unwrap_or_else(|e| error_exit(&e))),
+        strip_prefix: args.strip_prefix.clone(),
+        strip_suffix: args.strip_suffix.clone(),
+        add_prefix: args.add_prefix.clone(),
+        add_suffix: args.add_suffix.clone(),
+    };
+    if !transform.is_empty() && !cmds.is_empty() {
+        error_exit("--replace-map, --strip-prefix, --strip-suffix, --add-prefix and --add-suffix cannot be used with the command");
+    }
+
+    // --on-no-command decides whether the omitted command means dry run
+    let no_command_error = match args.on_no_command.as_str() {
+        "dry-run" => false,
+        "error" => true,
+        s => error_exit(&format!("Invalid value for --on-no-command: '{}' (dry-run or error is expected)", s)),
+    };
+    // --count-to stdout prints only the number of holes instead of the output
+    let count_only = match args.count_to.as_deref() {
+        Some(_) if !args.count => error_exit("--count-to requires --count"),
+        None | Some("stdout") => args.count,
+        Some("stderr") => false,
+        Some(s) => error_exit(&format!("Invalid value for --count-to: '{}' (stdout or stderr is expected)", s)),
+    };
+    if !cmds.is_empty() {
+        flag_dryrun = false;
+    } else if no_command_error && transform.is_empty() && !(args.show_both || args.show_bypassed || args.dry_run || args.annotate_count || args.only_selected || count_only) {
+        error_exit("No command is given (--on-no-command error)");
+    }
+
+    // --show-both, --show-bypassed, --dry-run, --annotate-count, --only-selected and --count never execute the command
+    if args.show_both || args.show_bypassed || args.dry_run || args.annotate_count || args.only_selected || count_only {
+        flag_dryrun = true;
+        flag_solid = false;
+    }
+
+    // Standard error of a command is associated with the hole only if the command is spawned for each hole
+    if args.keep_command_stderr_on_stdout && !flag_solid && !flag_dryrun {
+        error_exit("--keep-command-stderr-on-stdout is available only with -s, -I and --solid-arg");
+    }
+
+    // TEIP_DENY_COMMANDS refuses to spawn the listed commands unless --yes is given.
+    // The commands in the scripts of -e and --field-command are checked as well.
+    if !args.yes {
+        let mut spawned = Vec::new();
+        if !flag_dryrun {
+            spawned.extend(cmds.first().cloned());
+        }
+        if flag_exoffload {
+            spawned.extend(spawnutils::shell_commands(exoffload_pipeline));
+        }
+        if let Some(ref command) = args.field_command {
+            spawned.extend(spawnutils::shell_commands(command));
+        }
+        if let Some(name) = spawned.iter().find(|c| spawnutils::is_denied(c)) {
+            error_exit(&format!("'{}' is denied by TEIP_DENY_COMMANDS. Give --yes to run it", name));
+        }
+    }
+
+    // --dedup caches the results of the commands spawned by -s
+    if args.dedup_cache.is_some() && !args.dedup {
+        error_exit("--dedup-cache requires --dedup");
+    }
+    let dedup = if args.dedup {
+        if !flag_solid && !flag_dryrun {
+            error_exit("--dedup is available only with -s, -I and --solid-arg");
+        }
+        match args.dedup_cache.unwrap_or(DEFAULT_DEDUP_CACHE) {
+            0 => error_exit("<n> of --dedup-cache must be greater than 0"),
+            n => Some(n),
+        }
+    } else {
+        None
+    };
+
+    // Holes of --slurp may contain newlines, which split the hole in the output of the command
+    if args.slurp && !flag_dryrun && !(flag_solid || flag_zero || args.solid_nul || args.length_prefixed) {
+        error_exit("--slurp requires -s, -z, --solid-nul or --length-prefixed to execute the command");
+    }
+    if args.record_bytes.is_some() && !flag_dryrun && !(flag_solid || flag_zero || args.solid_nul || args.length_prefixed) {
+        error_exit("--record-bytes requires -s, -z, --solid-nul or --length-prefixed to execute the command");
+    }
+    // A string value of --jsonl-key may be decoded to multiple lines
+    let jsonl_multiline = flag_dryrun || flag_solid || flag_zero || args.solid_nul || args.length_prefixed;
+
+    // --stream-placeholder replaces {} in the command with the name of the input file.
+    // In solid mode, {} of --solid-arg is the hole instead.
+    if args.stream_placeholder {
+        if flag_solid {
+            error_exit("--stream-placeholder cannot be used with -s, -I and --solid-arg");
+        }
+        let name = match input.files() {
+            None => "-".to_string(),
+            Some(files) if files.len() == 1 => files[0].to_string(),
+            Some(_) => error_exit("--stream-placeholder requires a single input file"),
+        };
+        cmds = spawnutils::replace_args(&cmds, "{}", &name);
+    }
+
+    if args.buffer_lines == Some(0) {
+        error_exit("<n> of --buffer-lines must be greater than 0");
+    }
+    if args.unbuffered && args.buffer_lines.is_some() {
+        error_exit("--unbuffered cannot be used with --buffer-lines");
+    }
+    // --unbuffered flushes the output every line
+    let buffer_lines = if args.unbuffered { Some(1) } else { args.buffer_lines };
+    let mut result_writer = ResultWriter::new(io::stdout(), line_end, buffer_lines);
+    // --output-encoding re-encodes the output, while holes are matched and given to the command in UTF-8
+    // --wrap-before and --wrap-after put the strings around each result of the command
+    if args.wrap_before.is_some() || args.wrap_after.is_some() {
+        if flag_dryrun && transform.is_empty() {
+            error_exit("--wrap-before and --wrap-after require the command or the built-in transforms");
+        }
+        let before = args.wrap_before.clone().unwrap_or_default();
+        let after = args.wrap_after.clone().unwrap_or_default();
+        // The built-in transforms print the results as they are, so they add the strings by themselves
+        if !transform.is_empty() {
+            transform.add_prefix = Some(before.clone() + transform.add_prefix.as_deref().unwrap_or(""));
+            transform.add_suffix = Some(transform.add_suffix.as_deref().unwrap_or("").to_string() + &after);
+        }
+        result_writer.set_wrap(before, after);
+    }
+    // --jsonl-key gives the decoded string values to the command, and the results are encoded back
+    if flag_jsonl {
+        result_writer.set_json_string();
+    }
+    if let Some(ref e) = args.output_encoding {
+        result_writer.set_encoding(OutputEncoding::parse(e).unwrap_or_else(|e| error_exit(&e)));
+    }
+    // --output-record-sep replaces the line terminators in the output
+    if let Some(ref sep) = args.output_record_sep {
+        let sep = stringutils::unescape(sep).unwrap_or_else(|e| error_exit(&e));
+        if sep.is_empty() {
+            error_exit("<bytes> of --output-record-sep is empty");
+        }
+        result_writer.set_record_sep(sep.into_bytes());
+    }
+
+    // --length-prefixed is another framing of the single command
+    if args.length_prefixed && (flag_solid || args.solid_nul || args.command_per_line) {
+        error_exit("--length-prefixed cannot be used with -s, -I, --solid-arg, --solid-nul and --command-per-line");
+    }
+    // --command-per-line is between the single command and -s
+    if args.command_per_line && flag_solid {
+        error_exit("--command-per-line cannot be used with -s, -I and --solid-arg");
+    }
+    // --keep-selection-order checks the results of the single command
+    if args.keep_selection_order && (flag_solid || args.command_per_line) {
+        error_exit("--keep-selection-order cannot be used with -s, -I, --solid-arg and --command-per-line");
+    }
+
     if flag_solid {
         ch =
-            PipeIntercepter::start_solid_output(cmds, line_end, flag_dryrun, flag_solid_chomp, args.replace)
+            PipeIntercepter::start_solid_output(cmds, line_end, flag_dryrun, flag_solid_chomp, replace_str, normalize_eol, args.require_output, args.trim_eol_preserve, args.group_separator.clone(), dedup, args.keep_command_stderr_on_stdout, on_error, input.deadline(), profile.clone(), result_writer)
                 .unwrap_or_else(|e| error_exit(&e.to_string()));
     } else {
-        ch = PipeIntercepter::start_output(cmds, line_end, flag_dryrun)
-            .unwrap_or_else(|e| error_exit(&e.to_string()));
+        // --solid-nul frames holes with NUL instead of line_end
+        let frame_end = if args.solid_nul { b'\0' } else { line_end };
+        if args.command_per_line && !flag_dryrun {
+            ch = PipeIntercepter::start_line_output(cmds, line_end, frame_end, args.null_if_empty, input.deadline(), profile.clone(), result_writer)
+                .unwrap_or_else(|e| error_exit(&e.to_string()));
+        } else {
+            ch = PipeIntercepter::start_output(cmds, frame_end, flag_dryrun, args.length_prefixed, args.keep_selection_order, profile.clone(), result_writer)
+                .unwrap_or_else(|e| error_exit(&e.to_string()));
+        }
+    }
+
+    ch.set_highlight(hl_start, hl_end);
+    if args.profile {
+        ch.set_print_profile();
+    }
+    // --null-if-empty keeps holes emptied by the command
+    if args.null_if_empty {
+        if flag_solid {
+            error_exit("--null-if-empty cannot be used with -s, -I and --solid-arg");
+        }
+        ch.set_null_if_empty();
+    }
+    if args.unbuffered {
+        ch.set_unbuffered();
+    }
+    if args.show_both {
+        ch.set_show_both();
+    }
+    // Previews of --dry-run and the others show the holes before the transforms
+    let preview = args.show_both || args.show_bypassed || args.dry_run || args.annotate_count || args.only_selected;
+    if !transform.is_empty() && !preview {
+        ch.set_transform(transform);
+    }
+    // --escape-output keeps the preview from corrupting the terminal
+    if args.escape_output {
+        ch.set_escape_output();
+    }
+    // --annotate-count prints lines as they are with the number of holes
+    if args.annotate_count {
+        if args.show_both || args.show_bypassed {
+            error_exit("--annotate-count cannot be used with --show-both and --show-bypassed");
+        }
+        ch.set_annotate(args.annotate_separator.clone(), args.annotate_suffix);
+    } else if args.annotate_suffix || args.annotate_separator != "\t" {
+        error_exit("--annotate-suffix and --annotate-separator require --annotate-count");
+    }
+    // --only-selected extracts the holes like grep -o
+    if args.only_selected {
+        if args.show_both || args.show_bypassed || args.annotate_count {
+            error_exit("--only-selected cannot be used with --show-both, --show-bypassed and --annotate-count");
+        }
+        ch.set_only_selected(if args.only_selected_null { "\0" } else { "\n" }.to_string());
+    } else if args.only_selected_null {
+        error_exit("-0 requires --only-selected");
+    }
+    if count_only {
+        if args.show_both || args.show_bypassed || args.annotate_count || args.only_selected {
+            error_exit("--count cannot be used with --show-both, --show-bypassed, --annotate-count and --only-selected unless --count-to stderr is given");
+        }
+        ch.set_discard();
+    }
+    if args.show_bypassed {
+        let (start, end) = highlight_bypass();
+        ch.set_highlight_keep(start, end);
+    }
+    if args.dry_run {
+        ch.set_summary();
+    }
+    if args.stats {
+        ch.set_stats();
+    }
+    if args.summary_json {
+        ch.collect_stats();
+    }
+    if args.trace {
+        ch.set_trace();
+    }
+    if flag_jsonl {
+        ch.set_json_string();
+    }
+    // <separator> of --join-fields interprets escape sequences like -d
+    if let Some(ref separator) = args.join_fields {
+        let separator = if args.no_escape {
+            separator.clone()
+        } else {
+            stringutils::unescape(separator).unwrap_or_else(|e| error_exit(&e))
+        };
+        ch.set_join(separator, join_last).unwrap_or_else(|e| error_exit(&e));
+    }
+    if let Some(ref pattern) = args.exclude_pattern {
+        ch.set_exclude(compile_regex(&regex_mode, pattern, args.regex_size_limit, false));
+    }
+
+    // Parse argument of --nth option if specified.
+    // With --reverse-lines, the holes are counted from the bottom by reverse_line_proc instead.
+    let mut nth_list = args.nth.as_ref().map(|s| {
+        list::converter::to_ranges(s.as_str(), false).unwrap_or_else(|e| error_exit(&e.to_string()))
+    });
+    if !args.reverse_lines {
+        if let Some(l) = nth_list.take() {
+            ch.set_nth(l);
+        }
+    }
+    // --every selects holes periodically like --nth
+    if args.offset.is_some() && args.every.is_none() {
+        error_exit("--offset requires --every");
+    }
+    if let Some(n) = args.every {
+        if args.reverse_lines {
+            error_exit("--every cannot be used with --reverse-lines");
+        }
+        let offset = args.offset.unwrap_or(n);
+        if n == 0 || offset == 0 {
+            error_exit("<n> of --every and <k> of --offset must be greater than 0");
+        }
+        ch.set_every(n, offset);
     }
 
     // ***** Start processing *****
     if process_each_line {
-        let stdin = io::stdin();
+        let mut reader = input.open();
+        // --auto-delimiter detects the delimiter from the first line, which is read again by the loop
+        let delimiter = if args.auto_delimiter {
+            let mut first = Vec::new();
+            reader
+                .read_until(line_end, &mut first)
+                .unwrap_or_else(|e| error_exit(&e.to_string()));
+            let detected = stringutils::detect_delimiter(&first);
+            if args.verbose {
+                msg_error(&format!("--auto-delimiter: {:?} is detected", detected));
+            }
+            reader = Box::new(io::Read::chain(io::Cursor::new(first), reader));
+            detected
+        } else {
+            delimiter
+        };
         loop {
             let mut buf = Vec::with_capacity(DEFAULT_CAP);
-            match stdin.lock().read_until(line_end, &mut buf) {
+            let read = if args.slurp {
+                stringutils::read_all(&mut reader, line_end, &mut buf)
+            } else if let Some(n) = args.record_bytes {
+                stringutils::read_record(&mut reader, n, &mut buf)
+            } else {
+                stringutils::read_line(&mut reader, line_end, &mut buf)
+            };
+            match read {
                 Ok(0) => {
-                    ch.send_eof().unwrap_or_else(|e| msg_error(&e.to_string()));
+                    ch.send_eof().unwrap_or_else(check_send_error);
                     break;
                 }
                 Ok(_) => {},
-                Err(e) => msg_error(&e.to_string()),
+                Err(e) => error_exit(&e),
             };
-            let eol = stringutils::trim_eol(&mut buf);
-            if flag_regex {
-                procs::regex_proc(&mut ch, &buf, &regex_compiled, flag_invert)
+            let eol = if args.slurp || args.record_bytes.is_some() {
+                "".to_string()
+            } else {
+                stringutils::trim_eol(&mut buf, line_end)
+            };
+            let line = input.decode(&buf);
+            if let Some(ref key) = args.jsonl_key {
+                procs::jsonl_proc(&mut ch, &line, key, args.jsonl_stringify, jsonl_multiline)
+                    .unwrap_or_else(|e| error_exit(&e.to_string()));
+            } else if flag_regex_fields
+                && regex_compiled.is_match(&line) == flag_invert
+            {
+                // Lines not selected by -g are not processed by -f
+                ch.send_keep(line)
+                    .unwrap_or_else(|e| error_exit(&e.to_string()));
+            } else if flag_regex && !flag_regex_fields {
+                procs::regex_proc(&mut ch, &line, &regex_compiled, flag_invert, args.group, args.min_match_length.unwrap_or(0))
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
             } else if flag_onig {
-                onig::regex_onig_proc(&mut ch, &buf, &onig_regex_compiled, flag_invert)
+                onig::regex_onig_proc(&mut ch, &line, &onig_regex_compiled, flag_invert)
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
             } else if flag_char {
-                procs::char_proc(&mut ch, &buf, &char_list)
+                // --no-reexpand expands the tabs in advance, so the output has the spaces
+                match args.expand_tabs {
+                    Some(n) if args.no_reexpand => {
+                        let expanded = stringutils::expand_tabs(&line, n, args.display_columns);
+                        procs::char_proc(&mut ch, &expanded, &char_list, !args.no_merge, args.display_columns, None, false)
+                    }
+                    tab_stop => procs::char_proc(&mut ch, &line, &char_list, !args.no_merge, args.display_columns, tab_stop, args.bytes.is_some()),
+                }
+                    .unwrap_or_else(|e| error_exit(&e.to_string()));
+            } else if flag_field && flag_reorder {
+                procs::field_reorder_proc(&mut ch, &line, delimiter, &field_order, output_delimiter, args.max_fields)
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
             } else if flag_field && flag_delimiter {
-                procs::field_proc(&mut ch, &buf, delimiter, &field_list)
+                procs::field_proc(&mut ch, &line, delimiter, &field_list, output_delimiter, field_match, args.select_delimiters, args.field_default.as_deref(), args.max_fields)
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
             } else if flag_field {
-                procs::field_regex_proc(&mut ch, &buf, &regex_delimiter, &field_list)
+                procs::field_regex_proc(&mut ch, &line, &regex_delimiter, &field_list, output_delimiter, delimiter_replace, field_match, args.delimiter_regex_captures, args.field_default.as_deref(), args.max_fields)
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
             }
+            // --join-fields gives the joined hole to the command at the end of the line
             ch.send_keep(eol)
-                .unwrap_or_else(|e| msg_error(&e.to_string()));
+                .unwrap_or_else(check_send_error);
         }
     } else {
-        if flag_lines {
-            procs::line_line_proc(&mut ch, &line_list, line_end)
+        if let Some((ref re, n)) = after_match {
+            procs::after_match_proc(&mut ch, &input, re, n, flag_invert, line_end)
+                .unwrap_or_else(|e| error_exit(&e.to_string()));
+        } else if args.reverse_lines && flag_lines {
+            let mut ranges = list::ranges::Ranges::new(&line_list);
+            procs::reverse_line_proc(&mut ch, &input, |n, _| ranges.contains(n), nth_list.as_ref(), line_end)
+                .unwrap_or_else(|e| error_exit(&e.to_string()));
+        } else if args.reverse_lines {
+            procs::reverse_line_proc(&mut ch, &input, |_, line| regex_compiled.is_match(line) != flag_invert, nth_list.as_ref(), line_end)
+                .unwrap_or_else(|e| error_exit(&e.to_string()));
+        } else if flag_lines {
+            procs::line_line_proc(&mut ch, &input, &line_list, line_end, continuation.as_ref())
+                .unwrap_or_else(|e| error_exit(&e.to_string()));
+        } else if let Some(n) = args.tail {
+            procs::line_tail_proc(&mut ch, &input, n, flag_invert, line_end)
                 .unwrap_or_else(|e| error_exit(&e.to_string()));
         } else if flag_regex {
             if flag_onig {
-                onig::regex_onig_line_proc(&mut ch, &onig_regex_compiled, flag_invert, line_end)
+                onig::regex_onig_line_proc(&mut ch, &input, &onig_regex_compiled, flag_invert, line_end)
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
             } else {
-                procs::regex_line_proc(&mut ch, &regex_compiled, flag_invert, line_end)
+                procs::regex_line_proc(&mut ch, &input, &regex_compiled, flag_invert, line_end, args.include_eol, continuation.as_ref())
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
             }
         } else if flag_exoffload {
-            procs::exoffload_proc(&mut ch, exoffload_pipeline, flag_invert, line_end)
+            procs::exoffload_proc(&mut ch, &input, exoffload_pipeline, flag_invert, line_end, args.mask_delimiter.clone(), args.mask_bitmap)
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
         } else if flag_csv {
-            procs::csv_proc(&mut ch, &field_list, line_end, flag_solid)
+            procs::csv_proc(&mut ch, &input, &field_list, line_end, flag_solid || args.solid_nul || args.length_prefixed)
                     .unwrap_or_else(|e| error_exit(&e.to_string()));
         }
     }
+
+    // --exit-nomatch option, exit with 1 like grep if no hole is made
+    let selected = ch.has_selected();
+    let n_selected = ch.n_selected();
+    // Wait for the output to be finished
+    ch.finish();
+    let summary = ch.summary();
+    drop(ch);
+    // --count option, the number follows the output
+    if count_only {
+        println!("{}", n_selected);
+    } else if args.count {
+        eprintln!("{}", n_selected);
+    }
+    // --command-timeout-total option, exit with 124 like timeout(1)
+    let status = if input.time_limit_exceeded() {
+        124
+    } else if args.exit_nomatch && !selected {
+        1
+    } else {
+        0
+    };
+    if args.summary_json {
+        if let Some(ref summary) = summary {
+            write_summary(summary, &profile, status, args.summary_file.as_deref());
+        }
+    }
+    if status == 124 {
+        msg_error("--command-timeout-total expired, the rest of the input is not processed");
+    }
+    if status != 0 {
+        std::process::exit(status);
+    }
+}
+
+/// Error in sending the end of a line or the input from main().
+/// The closed channel has been reported by the output thread, and the other errors stop teip like the procs.
+fn check_send_error(e: errors::ChunkSendError) {
+    match e {
+        errors::ChunkSendError::Channel(_) => msg_error(&e.to_string()),
+        _ => error_exit(&e.to_string()),
+    }
+}
+
+/// Print the report of --summary-json. Fields may be added without changing "version", but never removed or renamed
+fn write_summary(summary: &pipeintercepter::Summary, profile: &Profile, status: i32, path: Option<&str>) {
+    let seconds = profile.seconds();
+    let secs = |f: fn(&profile::Seconds) -> f64| seconds.as_ref().map_or(0.0, f);
+    let report = serde_json::json!({
+        "version": 1,
+        "holes": {
+            "chunks": summary.hole_chunks,
+            "bytes": summary.hole_bytes,
+            "lines": summary.hole_lines,
+        },
+        "others": {
+            "chunks": summary.keep_chunks,
+            "bytes": summary.keep_bytes,
+        },
+        "lines": summary.lines,
+        "commands": summary.commands,
+        "exit_status": status,
+        "seconds": {
+            "matching": secs(|s| s.matching),
+            "pipe_write": secs(|s| s.pipe_write),
+            "pipe_read": secs(|s| s.pipe_read),
+            "command": secs(|s| s.command),
+            "total": secs(|s| s.total),
+        },
+    });
+    match path {
+        Some(p) => std::fs::write(p, format!("{}\n", report))
+            .unwrap_or_else(|e| error_exit(&format!("{}: {}", p, e))),
+        None => eprintln!("{}", report),
+    }
 }
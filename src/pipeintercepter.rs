@@ -1,22 +1,299 @@
 use super::chunk::Chunk;
+use super::profile::{Phase, Profile};
 use super::spawnutils;
-use super::stringutils::trim_eol;
+use super::stringutils::{self, trim_eol};
+use super::transform::Transform;
 use super::{errors,errors::*};
-use super::{HL,DEFAULT_CAP};
+use super::input;
+use super::DEFAULT_CAP;
+use super::list::ranges::Range;
 
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
 use std::sync::mpsc::{self, Sender};
 use std::thread::{self, JoinHandle};
+use std::time::Instant;
 use log::debug;
+use regex::Regex;
 
 /// struct for bypassing input and its interface
 pub struct PipeIntercepter {
     tx: Sender<Chunk>,
     pipe_writer: BufWriter<Box<dyn Write + Send + 'static>>, // Not used when -s
-    handler: Option<JoinHandle<()>>,                         // "option dance"
+    handler: Option<JoinHandle<usize>>,                      // "option dance", returns the number of --dedup hits
     line_end: u8,
     solid: bool,
     dryrun: bool,
+    highlight: (String, String), // Strings put around holes in dry run mode
+    highlight_keep: Option<(String, String)>, // --show-bypassed
+    nth: Option<Vec<Range>>, // --nth
+    every: Option<(usize, usize)>, // --every and --offset
+    n_byps: usize,           // number of holes given so far
+    show_both: bool,         // --show-both
+    only_selected: Option<String>, // --only-selected, the separator of the holes
+    stats: Option<Stats>,    // --stats, --dry-run
+    print_stats: bool,       // --stats
+    print_summary: bool,     // --dry-run
+    exclude: Option<Regex>,  // --exclude-pattern
+    n_selected: usize,       // number of holes made so far
+    discard: bool,           // --count, nothing is printed
+    unbuffered: bool,        // --unbuffered
+    null_if_empty: bool,     // --null-if-empty
+    annotate: Option<Annotate>, // --annotate-count
+    per_line: bool,          // --command-per-line
+    escape: bool,            // --escape-output
+    transform: Option<Transform>, // --strip-prefix, --add-prefix and the others
+    length_prefixed: bool,   // --length-prefixed
+    trace: Option<Trace>,    // --trace
+    join: Option<Join>,      // --join-fields
+    json_string: bool,       // --jsonl-key
+    profile: Profile,        // --profile, --summary-json
+    print_profile: bool,     // --profile
+    dedup_hits: usize,       // number of holes whose result is reused by --dedup
+}
+
+/// Numbers reported by --stats and --dry-run
+#[derive(Default)]
+struct Stats {
+    hole_chunks: usize,
+    hole_bytes: usize,
+    keep_chunks: usize,
+    keep_bytes: usize,
+    lines: usize,                   // number of line terminators given so far
+    hole_lines: usize,              // number of lines including holes
+    last_hole_line: Option<usize>,
+}
+
+/// Totals of the run reported by --summary-json
+pub struct Summary {
+    pub hole_chunks: usize,
+    pub hole_bytes: usize,
+    pub hole_lines: usize,
+    pub keep_chunks: usize,
+    pub keep_bytes: usize,
+    pub lines: usize,
+    pub commands: usize,
+}
+
+/// Holes in each line joined into a single hole ( --join-fields )
+struct Join {
+    separator: String,
+    last: bool,                  // --join-placement last
+    chunks: Vec<(String, bool)>, // chunks of the current line, true if it is a hole
+}
+
+/// Position of the next chunk printed by --trace
+struct Trace {
+    line: usize,   // line number starting with 1
+    offset: usize, // bytes from the start of the line
+}
+
+/// Results of the command spawned by -s keyed by the hole ( --dedup ).
+/// The oldest result is evicted when the cache is full.
+struct DedupCache {
+    capacity: usize,
+    results: HashMap<String, String>,
+    order: VecDeque<String>, // holes in the order of insertion
+}
+
+impl DedupCache {
+    fn new(capacity: usize) -> DedupCache {
+        DedupCache { capacity, results: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn get(&self, hole: &str) -> Option<String> {
+        self.results.get(hole).cloned()
+    }
+
+    fn insert(&mut self, hole: String, result: String) {
+        if self.results.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.results.remove(&oldest);
+            }
+        }
+        self.order.push_back(hole.clone());
+        self.results.insert(hole, result);
+    }
+}
+
+/// Number of holes in each line put before or after the line ( --annotate-count )
+struct Annotate {
+    separator: String,
+    suffix: bool,
+    line: String,  // the current line given so far
+    count: usize,  // number of holes in the current line
+}
+
+impl Annotate {
+    // Annotate the current line and start the next one
+    fn take_line(&mut self) -> String {
+        let line = std::mem::take(&mut self.line);
+        // CR of CRLF stays at the end of the line
+        let (body, cr) = match line.strip_suffix('\r') {
+            Some(body) => (body, "\r"),
+            None => (line.as_str(), ""),
+        };
+        let annotated = if self.suffix {
+            format!("{}{}{}{}", body, self.separator, self.count, cr)
+        } else {
+            format!("{}{}{}{}", self.count, self.separator, body, cr)
+        };
+        self.count = 0;
+        annotated
+    }
+}
+
+/// Writer of the final output.
+/// By default, it writes out every DEFAULT_BUF_SIZE bytes like BufWriter.
+/// With --buffer-lines <n>, it accumulates the output and flushes it every n records.
+pub struct ResultWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    line_end: u8,
+    buffer_lines: Option<usize>,
+    lines: usize,
+    encoding: OutputEncoding,
+    bom_pending: bool, // true until the byte order mark of --output-encoding utf-16 is written
+    record_sep: Option<Vec<u8>>, // --output-record-sep
+    wrap: Option<(String, String)>, // --wrap-before and --wrap-after
+    json_string: bool, // --jsonl-key
+}
+
+/// Encoding of the output ( --output-encoding ). The input is always read as UTF-8.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputEncoding {
+    Utf8,
+    Utf16,   // Little endian with the byte order mark
+    Utf16Le, // Without the byte order mark
+    Utf16Be, // Without the byte order mark
+}
+
+impl OutputEncoding {
+    pub fn parse(s: &str) -> Result<OutputEncoding, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(OutputEncoding::Utf8),
+            "utf-16" | "utf16" => Ok(OutputEncoding::Utf16),
+            "utf-16le" | "utf16le" => Ok(OutputEncoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(OutputEncoding::Utf16Be),
+            _ => Err(format!(
+                "Invalid value for --output-encoding: '{}' (utf-8, utf-16, utf-16le or utf-16be is expected)",
+                s
+            )),
+        }
+    }
+}
+
+const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+impl<W: Write> ResultWriter<W> {
+    pub fn new(inner: W, line_end: u8, buffer_lines: Option<usize>) -> ResultWriter<W> {
+        ResultWriter {
+            inner,
+            buf: Vec::with_capacity(DEFAULT_BUF_SIZE),
+            line_end,
+            buffer_lines,
+            lines: 0,
+            encoding: OutputEncoding::Utf8,
+            bom_pending: false,
+            record_sep: None,
+            wrap: None,
+            json_string: false,
+        }
+    }
+
+    /// Put the strings around each result of the command ( --wrap-before, --wrap-after )
+    pub fn set_wrap(&mut self, before: String, after: String) {
+        self.wrap = Some((before, after));
+    }
+
+    /// Encode each result as the contents of a JSON string ( --jsonl-key )
+    pub fn set_json_string(&mut self) {
+        self.json_string = true;
+    }
+
+    /// Write the result of the command for a hole
+    pub fn write_result(&mut self, msg: &[u8]) -> io::Result<()> {
+        let result = match self.wrap {
+            Some((ref before, ref after)) => Cow::Owned([before.as_bytes(), msg, after.as_bytes()].concat()),
+            None => Cow::Borrowed(msg),
+        };
+        let result = match self.json_string {
+            true => Cow::Owned(stringutils::escape_json(&String::from_utf8_lossy(&result)).into_bytes()),
+            false => result,
+        };
+        self.write(&result)
+    }
+
+    /// Replace each line terminator in the output with the separator ( --output-record-sep )
+    pub fn set_record_sep(&mut self, sep: Vec<u8>) {
+        self.record_sep = Some(sep);
+    }
+
+    /// Re-encode each string before writing ( --output-encoding )
+    pub fn set_encoding(&mut self, encoding: OutputEncoding) {
+        self.encoding = encoding;
+        self.bom_pending = encoding == OutputEncoding::Utf16;
+    }
+
+    pub fn write(&mut self, msg: &[u8]) -> io::Result<()> {
+        let records = msg.iter().filter(|&&b| b == self.line_end).count();
+        let replaced;
+        let msg = match self.record_sep {
+            Some(ref sep) if records > 0 => {
+                replaced = msg.split(|&b| b == self.line_end).collect::<Vec<_>>().join(&sep[..]);
+                &replaced[..]
+            }
+            _ => msg,
+        };
+        if self.encoding == OutputEncoding::Utf8 {
+            self.buf.extend_from_slice(msg);
+        } else {
+            let s = String::from_utf8_lossy(msg);
+            let mut s = s.as_ref();
+            if self.bom_pending && !s.is_empty() {
+                // The byte order mark of the input is replaced with the one of the output
+                s = s.strip_prefix('\u{feff}').unwrap_or(s);
+                self.buf.extend_from_slice(&[0xff, 0xfe]);
+                self.bom_pending = false;
+            }
+            for u in s.encode_utf16() {
+                match self.encoding {
+                    OutputEncoding::Utf16Be => self.buf.extend_from_slice(&u.to_be_bytes()),
+                    _ => self.buf.extend_from_slice(&u.to_le_bytes()),
+                }
+            }
+        }
+        match self.buffer_lines {
+            Some(n) => {
+                self.lines += records;
+                if self.lines >= n {
+                    self.lines = 0;
+                    self.flush()?;
+                }
+            }
+            None => {
+                if self.buf.len() >= DEFAULT_BUF_SIZE {
+                    self.inner.write_all(&self.buf)?;
+                    self.buf.clear();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+        self.inner.flush()
+    }
+}
+
+// Flush the rest at the end like BufWriter
+impl<W: Write> Drop for ResultWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
 }
 
 impl PipeIntercepter {
@@ -55,14 +332,19 @@ impl PipeIntercepter {
         cmds: Vec<String>,
         line_end: u8,
         dryrun: bool,
+        length_prefixed: bool,
+        keep_order: bool,
+        profile: Profile,
+        mut result_writer: ResultWriter<io::Stdout>,
     ) -> Result<PipeIntercepter, errors::SpawnError> {
         let (tx, rx) = mpsc::channel();
         let (child_stdin, child_stdout, _) = spawnutils::exec_cmd(&cmds)?;
         let pipe_writer = BufWriter::new(child_stdin);
+        let output_profile = profile.clone();
         let handler = thread::spawn(move || {
             debug!("thread: spawn");
             let mut pipe_reader = BufReader::new(child_stdout);
-            let mut result_writer = BufWriter::new(io::stdout());
+            let mut n_holes = 0; // --keep-selection-order
             loop {
                 let chunk = match rx.recv() {
                     Ok(t) => t,
@@ -78,23 +360,59 @@ impl PipeIntercepter {
                             .write(msg.as_bytes())
                             .unwrap_or_else(|e| exit_silently(&e.to_string()));
                     }
-                    Chunk::Hole => {
+                    Chunk::Hole(original) => {
                         debug!("thread: rx.recv <= Hole");
-                        match PipeIntercepter::read_pipe(&mut pipe_reader, line_end) {
+                        n_holes += 1;
+                        let timer = output_profile.timer();
+                        let result = if length_prefixed {
+                            PipeIntercepter::read_frame(&mut pipe_reader)
+                        } else {
+                            PipeIntercepter::read_pipe(&mut pipe_reader, line_end)
+                        };
+                        output_profile.record(Phase::PipeRead, timer);
+                        match result {
                             Ok(msg) => {
+                                // --null-if-empty option, an empty result is the original string
+                                let msg = match original {
+                                    Some(original) if msg.is_empty() => original,
+                                    _ => msg,
+                                };
                                 result_writer
-                                    .write(msg.as_bytes())
+                                    .write_result(msg.as_bytes())
                                     .unwrap_or_else(|e| exit_silently(&e.to_string()));
                             }
+                            Err(errors::PipeReceiveError::EndOfFd) if keep_order => {
+                                result_writer.flush().unwrap_or_else(|e| exit_silently(&e.to_string()));
+                                error_exit(&format!(
+                                    "The command printed fewer results than the holes: {} results for {} or more holes (--keep-selection-order)",
+                                    n_holes - 1,
+                                    n_holes
+                                ))
+                            }
                             Err(e) => {
                                 // pipe may be exhausted
-                                result_writer.flush().unwrap();
+                                result_writer.flush().unwrap_or_else(|e| exit_silently(&e.to_string()));
                                 error_exit(&e.to_string())
                             }
                         }
                     }
                     Chunk::EOF => {
                         debug!("thread: rx.recv <= EOF");
+                        // --keep-selection-order option, the rest of the output means the results are misaligned.
+                        // The command finishes once the pipe to it is closed by the drop of PipeIntercepter.
+                        if keep_order {
+                            let mut rest = Vec::new();
+                            pipe_reader
+                                .read_to_end(&mut rest)
+                                .unwrap_or_else(|e| error_exit(&e.to_string()));
+                            if !rest.is_empty() {
+                                result_writer.flush().unwrap_or_else(|e| exit_silently(&e.to_string()));
+                                error_exit(&format!(
+                                    "The command printed more results than the {} holes (--keep-selection-order)",
+                                    n_holes
+                                ));
+                            }
+                        }
                         break;
                     }
                     _ => {
@@ -102,6 +420,7 @@ impl PipeIntercepter {
                     }
                 };
             }
+            0
         });
         Ok(PipeIntercepter {
             tx,
@@ -110,6 +429,32 @@ impl PipeIntercepter {
             line_end,
             solid: false,
             dryrun,
+            highlight: (String::new(), String::new()),
+            highlight_keep: None,
+            nth: None,
+            every: None,
+            n_byps: 0,
+            show_both: false,
+            only_selected: None,
+            stats: None,
+            print_stats: false,
+            print_summary: false,
+            exclude: None,
+            n_selected: 0,
+            discard: false,
+            unbuffered: false,
+            null_if_empty: false,
+            annotate: None,
+            per_line: false,
+            escape: false,
+            transform: None,
+            length_prefixed,
+            trace: None,
+            join: None,
+            json_string: false,
+            profile,
+            print_profile: false,
+            dedup_hits: 0,
         })
     }
 
@@ -149,19 +494,38 @@ impl PipeIntercepter {
     ///                                             │                                                    │     └───────────────┘         │  │
     ///                                             │                                                    └───────────────────────────────┘  │
     ///                                             └───────────────────────────────────────────────────────────────────────────────────────┘
+    #[allow(clippy::too_many_arguments)]
     pub fn start_solid_output(
         cmds: Vec<String>,
         line_end: u8,
         dryrun: bool,
         chomp: bool,
         replace_str: Option<String>,
+        normalize_eol: Option<String>,
+        require_output: bool,
+        preserve_eol: bool,
+        group_separator: Option<String>,
+        dedup: Option<usize>,
+        stderr_on_stdout: bool,
+        on_error: Option<OnError>,
+        deadline: Option<Instant>,
+        profile: Profile,
+        mut writer: ResultWriter<io::Stdout>,
     ) -> Result<PipeIntercepter, errors::SpawnError> {
         let (tx, rx) = mpsc::channel();
         let is_replace = replace_str.is_some();
         let replace_str = replace_str.unwrap_or_else(|| "".to_string());
+        let output_profile = profile.clone();
         let handler = thread::spawn(move || {
             debug!("thread: spawn");
-            let mut writer = BufWriter::new(io::stdout());
+            // Whether the result of the previous hole spans multiple lines ( --group-separator )
+            let mut last_multiline = false;
+            // --dedup option, results of the holes given so far, up to the size of the cache
+            let mut cache = dedup.map(DedupCache::new);
+            // Number of holes whose result is reused by --dedup, which is not counted as a command by --stats
+            let mut dedup_hits = 0;
+            // --command-timeout-total option, the rest of the output is discarded after it expires
+            let mut stopped = false;
             loop {
                 let chunk = match rx.recv() {
                     Ok(t) => t,
@@ -170,6 +534,12 @@ impl PipeIntercepter {
                         break;
                     }
                 };
+                if let Chunk::SHole(_) = chunk {
+                    stopped = stopped || input::is_expired(deadline);
+                }
+                if stopped && !matches!(chunk, Chunk::EOF) {
+                    continue;
+                }
                 match chunk {
                     Chunk::Keep(msg) => {
                         debug!("thread: rx.recv <= Keep:[{:?}]", msg);
@@ -179,18 +549,85 @@ impl PipeIntercepter {
                     }
                     Chunk::SHole(msg) => {
                         debug!("thread: rx.recv <= SHole:[{:?}]", msg);
-                        // -I option
-                        if is_replace {
-                            let result = spawnutils::exec_cmd_sync_replace(msg, &cmds, line_end, chomp, replace_str.as_ref());
-                            writer
-                                .write(result.as_bytes())
-                                .unwrap_or_else(|e| exit_silently(&e.to_string()));
-                        } else {
-                            let result = spawnutils::exec_cmd_sync(msg, &cmds, line_end, chomp);
-                            writer
-                                .write(result.as_bytes())
-                                .unwrap_or_else(|e| exit_silently(&e.to_string()));
+                        let cached = cache.as_ref().and_then(|c| c.get(&msg));
+                        // Identical holes reuse the first result instead of spawning the command
+                        let result = match cached {
+                            Some(r) => {
+                                dedup_hits += 1;
+                                r
+                            }
+                            None => {
+                                let key = cache.as_ref().map(|_| msg.clone());
+                                // --trim-eol-preserve option, the terminator at the end of the hole
+                                // is removed here and put after the result as it is
+                                let (msg, eol) = if preserve_eol {
+                                    let mut buf = msg.into_bytes();
+                                    let eol = trim_eol(&mut buf, b'\n');
+                                    (String::from_utf8_lossy(&buf).to_string(), eol)
+                                } else {
+                                    (msg, String::new())
+                                };
+                                // -I option
+                                let timer = output_profile.timer();
+                                let result = if is_replace {
+                                    spawnutils::exec_cmd_sync_replace(msg.to_string(), &cmds, line_end, chomp, replace_str.as_ref(), stderr_on_stdout)
+                                } else {
+                                    spawnutils::exec_cmd_sync(msg.to_string(), &cmds, line_end, chomp, stderr_on_stdout)
+                                };
+                                output_profile.record(Phase::Command, timer);
+                                let mut result = match (result, on_error.as_ref()) {
+                                    (Ok(r), _) => r,
+                                    // The exit status is ignored by default
+                                    (Err(errors::SpawnError::Exit { stdout, .. }), None) => stdout,
+                                    // --on-error option
+                                    (Err(e), policy) => match policy.and_then(|p| p.apply(&msg)) {
+                                        Some(r) => {
+                                            debug!("thread: on-error: {}", e);
+                                            r
+                                        }
+                                        None => {
+                                            writer.flush().unwrap_or_else(|e| exit_silently(&e.to_string()));
+                                            error_exit(&e.to_string())
+                                        }
+                                    },
+                                };
+                                // --require-output option
+                                if require_output && result.is_empty() && !msg.is_empty() {
+                                    writer.flush().unwrap_or_else(|e| exit_silently(&e.to_string()));
+                                    error_exit(&format!("'{}' produced no output for the chunk {:?}", cmds[0], msg))
+                                }
+                                // --normalize-eol option
+                                if let Some(ref eol) = normalize_eol {
+                                    result = stringutils::normalize_eol(&result, eol);
+                                }
+                                if preserve_eol {
+                                    let mut buf = result.into_bytes();
+                                    trim_eol(&mut buf, b'\n');
+                                    if buf.ends_with(b"\r") {
+                                        buf.pop();
+                                    }
+                                    result = String::from_utf8_lossy(&buf).to_string() + &eol;
+                                }
+                                if let (Some(cache), Some(key)) = (cache.as_mut(), key) {
+                                    cache.insert(key, result.clone());
+                                }
+                                result
+                            }
+                        };
+                        // --group-separator option, a line put between consecutive multi-line results
+                        if let Some(ref sep) = group_separator {
+                            let eol = line_end as char;
+                            let multiline = result.trim_end_matches(eol).contains(eol);
+                            if multiline && last_multiline {
+                                writer
+                                    .write(format!("{}{}", sep, eol).as_bytes())
+                                    .unwrap_or_else(|e| exit_silently(&e.to_string()));
+                            }
+                            last_multiline = multiline;
                         }
+                        writer
+                            .write_result(result.as_bytes())
+                            .unwrap_or_else(|e| exit_silently(&e.to_string()));
                     }
                     Chunk::EOF => {
                         debug!("thread: rx.recv <= EOF");
@@ -201,6 +638,7 @@ impl PipeIntercepter {
                     }
                 };
             }
+            dedup_hits
         });
         let dummy = Box::new(io::sink());
         Ok(PipeIntercepter {
@@ -210,9 +648,198 @@ impl PipeIntercepter {
             line_end,
             solid: true,
             dryrun,
+            highlight: (String::new(), String::new()),
+            highlight_keep: None,
+            nth: None,
+            every: None,
+            n_byps: 0,
+            show_both: false,
+            only_selected: None,
+            stats: None,
+            print_stats: false,
+            print_summary: false,
+            exclude: None,
+            n_selected: 0,
+            discard: false,
+            unbuffered: false,
+            null_if_empty: false,
+            annotate: None,
+            per_line: false,
+            escape: false,
+            transform: None,
+            length_prefixed: false,
+            trace: None,
+            join: None,
+            json_string: false,
+            profile,
+            print_profile: false,
+            dedup_hits: 0,
         })
     }
 
+    /// Spawn an external process for each line including holes ( --command-per-line )
+    /// Holes in the line are given to the command at once, one hole per line,
+    /// and the n-th line of the output replaces the n-th hole.
+    /// Lines without holes are printed as they are without spawning the command.
+    pub fn start_line_output(
+        cmds: Vec<String>,
+        line_end: u8,
+        frame_end: u8,
+        null_if_empty: bool,
+        deadline: Option<Instant>,
+        profile: Profile,
+        mut writer: ResultWriter<io::Stdout>,
+    ) -> Result<PipeIntercepter, errors::SpawnError> {
+        let (tx, rx) = mpsc::channel();
+        let output_profile = profile.clone();
+        let handler = thread::spawn(move || {
+            debug!("thread: spawn");
+            // Chunks of the current line given after the first hole
+            let mut pending: Vec<Chunk> = Vec::new();
+            // --command-timeout-total option, the rest of the output is discarded after it expires
+            let mut stopped = false;
+            loop {
+                let chunk = match rx.recv() {
+                    Ok(t) => t,
+                    Err(e) => {
+                        msg_error(&e.to_string());
+                        break;
+                    }
+                };
+                if let Chunk::LHole(_) = chunk {
+                    stopped = stopped || input::is_expired(deadline);
+                }
+                if stopped && !matches!(chunk, Chunk::EOF) {
+                    continue;
+                }
+                match chunk {
+                    Chunk::Keep(msg) => {
+                        debug!("thread: rx.recv <= Keep:[{:?}]", msg);
+                        if pending.is_empty() {
+                            writer
+                                .write(msg.as_bytes())
+                                .unwrap_or_else(|e| exit_silently(&e.to_string()));
+                            continue;
+                        }
+                        // The line is completed by the terminator, the rest belongs to the next line
+                        match msg.rfind(line_end as char) {
+                            Some(i) => {
+                                pending.push(Chunk::Keep(msg[..=i].to_string()));
+                                PipeIntercepter::exec_line(&mut pending, &cmds, frame_end, null_if_empty, &output_profile, &mut writer);
+                                writer
+                                    .write(&msg.as_bytes()[i + 1..])
+                                    .unwrap_or_else(|e| exit_silently(&e.to_string()));
+                            }
+                            None => pending.push(Chunk::Keep(msg)),
+                        }
+                    }
+                    Chunk::LHole(msg) => {
+                        debug!("thread: rx.recv <= LHole:[{:?}]", msg);
+                        pending.push(Chunk::LHole(msg));
+                    }
+                    Chunk::EOF if stopped => break,
+                    Chunk::EOF => {
+                        debug!("thread: rx.recv <= EOF");
+                        // The last line may not end with the terminator
+                        PipeIntercepter::exec_line(&mut pending, &cmds, frame_end, null_if_empty, &output_profile, &mut writer);
+                        break;
+                    }
+                    _ => {
+                        error_exit("Exit with bug.");
+                    }
+                };
+            }
+            0
+        });
+        let dummy = Box::new(io::sink());
+        Ok(PipeIntercepter {
+            tx,
+            pipe_writer: BufWriter::new(dummy),
+            handler: Some(handler),
+            line_end,
+            solid: false,
+            dryrun: false,
+            highlight: (String::new(), String::new()),
+            highlight_keep: None,
+            nth: None,
+            every: None,
+            n_byps: 0,
+            show_both: false,
+            only_selected: None,
+            stats: None,
+            print_stats: false,
+            print_summary: false,
+            exclude: None,
+            n_selected: 0,
+            discard: false,
+            unbuffered: false,
+            null_if_empty,
+            annotate: None,
+            per_line: true,
+            escape: false,
+            transform: None,
+            length_prefixed: false,
+            trace: None,
+            join: None,
+            json_string: false,
+            profile,
+            print_profile: false,
+            dedup_hits: 0,
+        })
+    }
+
+    /// Execute the command with the holes of the line, and print the line with the results
+    fn exec_line(
+        pending: &mut Vec<Chunk>,
+        cmds: &Vec<String>,
+        frame_end: u8,
+        null_if_empty: bool,
+        profile: &Profile,
+        writer: &mut ResultWriter<io::Stdout>,
+    ) {
+        let holes: Vec<&str> = pending
+            .iter()
+            .filter_map(|c| match c {
+                Chunk::LHole(msg) => Some(msg.as_str()),
+                _ => None,
+            })
+            .collect();
+        if holes.is_empty() {
+            return;
+        }
+        let input = holes.join(&(frame_end as char).to_string());
+        let timer = profile.timer();
+        let result = spawnutils::exec_cmd_sync(input, cmds, frame_end, false, false);
+        profile.record(Phase::Command, timer);
+        let output = match result {
+            Ok(r) => r,
+            // The exit status is ignored as well as the streaming command
+            Err(errors::SpawnError::Exit { stdout, .. }) => stdout,
+            Err(e) => {
+                writer.flush().unwrap_or_else(|e| exit_silently(&e.to_string()));
+                error_exit(&e.to_string())
+            }
+        };
+        let mut results = output.split(frame_end as char);
+        for chunk in pending.drain(..) {
+            let written = match chunk {
+                Chunk::Keep(msg) => writer.write(msg.as_bytes()),
+                Chunk::LHole(original) => match results.next() {
+                    // --null-if-empty option, an empty result is the original string
+                    Some("") if null_if_empty => writer.write_result(original.as_bytes()),
+                    Some(r) => writer.write_result(r.as_bytes()),
+                    None => {
+                        // The command printed fewer lines than the holes
+                        writer.flush().unwrap_or_else(|e| exit_silently(&e.to_string()));
+                        error_exit(&errors::PipeReceiveError::EndOfFd.to_string())
+                    }
+                },
+                _ => error_exit("Exit with bug."),
+            };
+            written.unwrap_or_else(|e| exit_silently(&e.to_string()));
+        }
+    }
+
     fn read_pipe<R: BufRead + ?Sized>(
         reader: &mut R,
         line_end: u8,
@@ -227,13 +854,82 @@ impl PipeIntercepter {
             return Err(errors::PipeReceiveError::EndOfFd);
         }
         // Remove line_end from buf.
-        trim_eol(&mut buf);
+        trim_eol(&mut buf, line_end);
+        Ok(String::from_utf8_lossy(&buf).to_string())
+    }
+
+    /// Read a frame of --length-prefixed, the length in bytes as a decimal number and a newline,
+    /// followed by the bytes of the result
+    fn read_frame<R: BufRead + ?Sized>(reader: &mut R) -> Result<String, errors::PipeReceiveError> {
+        debug!("thread: read_frame");
+        let mut header = Vec::new();
+        let n = reader
+            .read_until(b'\n', &mut header)
+            .map_err(errors::PipeReceiveError::Io)?;
+        if n == 0 {
+            return Err(errors::PipeReceiveError::EndOfFd);
+        }
+        let header = String::from_utf8_lossy(&header);
+        let len: usize = header.trim_end_matches(['\r', '\n']).parse().map_err(|_| {
+            let msg = format!("Invalid length prefix {:?} in the output of the command", header);
+            errors::PipeReceiveError::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+        })?;
+        let mut buf = vec![0; len];
+        reader.read_exact(&mut buf).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => errors::PipeReceiveError::EndOfFd,
+            _ => errors::PipeReceiveError::Io(e),
+        })?;
         Ok(String::from_utf8_lossy(&buf).to_string())
     }
 
     /// Print string as is, that means it outputs to stdout without any modifications.
     /// This is data "under the masking tape".
-    pub fn send_keep(&self, msg: String) -> Result<(), errors::ChunkSendError> {
+    pub fn send_keep(&mut self, msg: String) -> Result<(), errors::ChunkSendError> {
+        if let Some(ref mut join) = self.join {
+            let eol = msg.contains(self.line_end as char);
+            join.chunks.push((msg, false));
+            return if eol { self.send_joined() } else { Ok(()) };
+        }
+        if self.trace.is_some() {
+            self.trace_chunk("BYPASS", &msg);
+        }
+        let line_end = self.line_end;
+        if let Some(ref mut stats) = self.stats {
+            if !msg.is_empty() {
+                stats.keep_chunks += 1;
+                stats.keep_bytes += msg.len();
+                stats.lines += msg.bytes().filter(|&b| b == line_end).count();
+            }
+        }
+        let msg = self.escape_output(msg);
+        if self.annotate.is_some() {
+            return self.send_annotated(&msg);
+        }
+        if self.discard || self.only_selected.is_some() {
+            return Ok(());
+        }
+        if self.show_both {
+            if msg.is_empty() {
+                return Ok(());
+            }
+            let msg = format!("B:{}\n", msg.escape_debug());
+            debug!("tx.send => Channle({:?})", msg);
+            self.tx
+                .send(Chunk::Keep(msg))
+                .map_err(errors::ChunkSendError::Channel)?;
+            return Ok(());
+        }
+        if let Some((ref start, ref end)) = self.highlight_keep {
+            // Line terminators are not highlighted
+            if !msg.trim_matches(|c| c == '\r' || c == '\n' || c == '\0').is_empty() {
+                let msg_highlighted = start.to_string() + &msg + end;
+                debug!("tx.send => Channle({:?})", msg_highlighted);
+                self.tx
+                    .send(Chunk::Keep(msg_highlighted))
+                    .map_err(errors::ChunkSendError::Channel)?;
+                return Ok(());
+            }
+        }
         debug!("tx.send => Channle({:?})", msg);
         self.tx
             .send(Chunk::Keep(msg))
@@ -244,16 +940,115 @@ impl PipeIntercepter {
     /// Bypassing strings to the pipe and will be modified by the targeted command.
     /// This is data is in the hole on the masking tape".
     pub fn send_byps(&mut self, msg: String) -> Result<(), errors::ChunkSendError> {
+        if let Some(ref mut join) = self.join {
+            join.chunks.push((msg, true));
+            return Ok(());
+        }
+        let re = match self.exclude {
+            Some(ref re) => re.clone(),
+            None => return self.send_hole(msg),
+        };
+        // --exclude-pattern option, matched parts in the hole are kept as is
+        let mut left_index = 0;
+        let mut matched = false;
+        for cap in re.find_iter(&msg) {
+            matched = true;
+            if left_index < cap.start() {
+                self.send_hole(msg[left_index..cap.start()].to_string())?;
+            }
+            let kept = self.encode_hole(cap.as_str().to_string());
+            self.send_keep(kept)?;
+            left_index = cap.end();
+        }
+        if !matched || left_index < msg.len() {
+            self.send_hole(msg[left_index..].to_string())?;
+        }
+        Ok(())
+    }
+
+    fn send_hole(&mut self, msg: String) -> Result<(), errors::ChunkSendError> {
+        self.n_byps += 1;
+        if let Some(ref nth) = self.nth {
+            // --nth option, holes out of the list are kept as is
+            let n = self.n_byps;
+            if !nth.iter().any(|r| r.contains(n)) {
+                let msg = self.encode_hole(msg);
+                return self.send_keep(msg);
+            }
+        }
+        if let Some((every, offset)) = self.every {
+            // --every option, only every <n>-th hole from the <k>-th one is kept as a hole
+            let n = self.n_byps;
+            if n < offset || (n - offset) % every != 0 {
+                let msg = self.encode_hole(msg);
+                return self.send_keep(msg);
+            }
+        }
+        if self.trace.is_some() {
+            self.trace_chunk("SELECT", &msg);
+        }
+        self.n_selected += 1;
+        let line_end = self.line_end;
+        if let Some(ref mut stats) = self.stats {
+            stats.hole_chunks += 1;
+            stats.hole_bytes += msg.len();
+            if stats.last_hole_line != Some(stats.lines) {
+                stats.hole_lines += 1;
+                stats.last_hole_line = Some(stats.lines);
+            }
+            stats.lines += msg.bytes().filter(|&b| b == line_end).count();
+        }
+        if self.discard {
+            return Ok(());
+        }
+        let msg = self.escape_output(msg);
+        if let Some(ref mut annotate) = self.annotate {
+            annotate.count += 1;
+            let msg = self.encode_hole(msg);
+            return self.send_annotated(&msg);
+        }
+        if let Some(ref separator) = self.only_selected {
+            let msg = msg + separator;
+            debug!("tx.send => Channle({:?})", msg);
+            self.tx
+                .send(Chunk::Keep(msg))
+                .map_err(errors::ChunkSendError::Channel)?;
+            return Ok(());
+        }
+        if self.show_both {
+            let msg = format!("S:{}\n", msg.escape_debug());
+            debug!("tx.send => Channle({:?})", msg);
+            self.tx
+                .send(Chunk::Keep(msg))
+                .map_err(errors::ChunkSendError::Channel)?;
+            return Ok(());
+        }
+        if let Some(ref transform) = self.transform {
+            // Built-in transforms instead of the command
+            let msg = self.encode_hole(transform.apply(&msg));
+            debug!("tx.send => Channle({:?})", msg);
+            self.tx
+                .send(Chunk::Keep(msg))
+                .map_err(errors::ChunkSendError::Channel)?;
+            return Ok(());
+        }
         if self.dryrun {
             // Highlight the string instead of bypassing
             let msg_highlighted: String;
-            msg_highlighted = HL[0].to_string() + &msg + HL[1];
+            msg_highlighted = self.highlight.0.to_string() + &self.encode_hole(msg) + &self.highlight.1;
             debug!("tx.send => Channle({:?})", msg_highlighted);
             self.tx
                 .send(Chunk::Keep(msg_highlighted))
                 .map_err(|e| errors::ChunkSendError::Channel(e))?;
             return Ok(());
         }
+        if self.per_line {
+            debug!("tx.send => LHole({:?})", msg);
+            self.tx
+                .send(Chunk::LHole(msg))
+                .map_err(errors::ChunkSendError::Channel)?;
+            return Ok(());
+        }
         if self.solid {
             debug!("tx.send => Solid({:?})", msg);
             self.tx
@@ -261,38 +1056,443 @@ impl PipeIntercepter {
                 .map_err(|e| errors::ChunkSendError::Channel(e))?;
             Ok(())
         } else {
+            // The terminator in the hole would split it into two results of the command.
+            // Other bytes like NUL in the newline mode are given as they are.
+            if !self.length_prefixed && msg.as_bytes().contains(&self.line_end) {
+                return Err(errors::ChunkSendError::Terminator(self.line_end));
+            }
             debug!("tx.send => Hole");
+            let original = if self.null_if_empty { Some(msg.clone()) } else { None };
             self.tx
-                .send(Chunk::Hole)
+                .send(Chunk::Hole(original))
                 .map_err(|e| errors::ChunkSendError::Channel(e))?;
             debug!("stdin => {}[line_end]", msg);
             // FIXME: Marging line_end to the end of the string may improve the performance.
             //        Need benchmarking.
+            let timer = self.profile.timer();
+            // --length-prefixed option, the length of the hole is put before it instead of line_end after it
+            if self.length_prefixed {
+                self.pipe_writer
+                    .write_all(format!("{}\n", msg.len()).as_bytes())
+                    .map_err(errors::ChunkSendError::Pipe)?;
+            }
             self.pipe_writer
-                .write(msg.as_bytes())
-                .map_err(|e| errors::ChunkSendError::Pipe(e))?;
-            self.pipe_writer
-                .write(&[self.line_end])
+                .write_all(msg.as_bytes())
                 .map_err(|e| errors::ChunkSendError::Pipe(e))?;
+            if !self.length_prefixed {
+                self.pipe_writer
+                    .write(&[self.line_end])
+                    .map_err(|e| errors::ChunkSendError::Pipe(e))?;
+            }
+            // --unbuffered option, the command receives the hole immediately
+            if self.unbuffered {
+                self.pipe_writer.flush().map_err(errors::ChunkSendError::Pipe)?;
+            }
+            self.profile.record(Phase::PipeWrite, timer);
             Ok(())
         }
     }
 
+    /// Send the current line of --join-fields. The holes are joined with the separator and put at
+    /// the first (or last) hole, and the other holes are removed.
+    fn send_joined(&mut self) -> Result<(), errors::ChunkSendError> {
+        let mut join = match self.join.take() {
+            Some(j) => j,
+            None => return Ok(()),
+        };
+        let chunks = std::mem::take(&mut join.chunks);
+        let holes: Vec<&str> = chunks.iter().filter(|(_, hole)| *hole).map(|(s, _)| s.as_str()).collect();
+        let joined = holes.join(&join.separator);
+        let place = if join.last { holes.len().saturating_sub(1) } else { 0 };
+        let mut n = 0; // number of holes seen so far
+        let result = chunks.into_iter().try_for_each(|(msg, hole)| {
+            if !hole {
+                return self.send_keep(msg);
+            }
+            n += 1;
+            if n == place + 1 {
+                self.send_byps(joined.clone())
+            } else {
+                Ok(())
+            }
+        });
+        self.join = Some(join);
+        result
+    }
+
+    /// Add the string to the current line of --annotate-count, and print the completed lines
+    fn send_annotated(&mut self, msg: &str) -> Result<(), errors::ChunkSendError> {
+        let line_end = self.line_end as char;
+        let annotate = self.annotate.as_mut().unwrap();
+        let mut out = String::new();
+        let mut rest = msg;
+        while let Some(i) = rest.find(line_end) {
+            annotate.line.push_str(&rest[..i]);
+            out.push_str(&annotate.take_line());
+            out.push(line_end);
+            rest = &rest[i + 1..];
+        }
+        annotate.line.push_str(rest);
+        if out.is_empty() {
+            return Ok(());
+        }
+        debug!("tx.send => Channle({:?})", out);
+        self.tx
+            .send(Chunk::Keep(out))
+            .map_err(errors::ChunkSendError::Channel)?;
+        Ok(())
+    }
+
+    /// Print the decision of the chunk and its position to stderr ( --trace )
+    fn trace_chunk(&mut self, decision: &str, msg: &str) {
+        let line_end = self.line_end as char;
+        let trace = self.trace.as_mut().unwrap();
+        if msg.is_empty() {
+            return;
+        }
+        eprintln!(
+            "{} line={} offset={} bytes={} {:?}",
+            decision,
+            trace.line,
+            trace.offset,
+            msg.len(),
+            msg
+        );
+        // A chunk may span multiple lines
+        match msg.rfind(line_end) {
+            Some(i) => {
+                trace.line += msg.matches(line_end).count();
+                trace.offset = msg.len() - i - 1;
+            }
+            None => trace.offset += msg.len(),
+        }
+    }
+
+    /// Make control characters visible in dry run mode ( --escape-output )
+    /// --show-both escapes the chunks by itself.
+    fn escape_output(&self, msg: String) -> String {
+        if self.escape && self.dryrun && !self.show_both && self.transform.is_none() {
+            stringutils::escape_control(&msg, self.line_end)
+        } else {
+            msg
+        }
+    }
+
+    /// Set strings put before and after each hole in dry run mode
+    pub fn set_highlight(&mut self, start: String, end: String) {
+        self.highlight = (start, end);
+    }
+
+    /// Highlight the strings out of holes instead of holes ( --show-bypassed )
+    pub fn set_highlight_keep(&mut self, start: String, end: String) {
+        self.highlight = (String::new(), String::new());
+        self.highlight_keep = Some((start, end));
+    }
+
+    /// Bypassing only n-th holes counted through the entire input ( --nth )
+    pub fn set_nth(&mut self, ranges: Vec<Range>) {
+        self.nth = Some(ranges);
+    }
+
+    /// Bypassing only every n-th hole from the offset-th one counted through the entire input ( --every, --offset )
+    pub fn set_every(&mut self, every: usize, offset: usize) {
+        self.every = Some((every, offset));
+    }
+
+    /// Print each chunk in a line with "S:" (hole) or "B:" (others) prefix
+    /// instead of executing the command ( --show-both )
+    pub fn set_show_both(&mut self) {
+        self.show_both = true;
+    }
+
+    /// Print only the holes followed by the separator, and discard the others
+    /// instead of executing the command ( --only-selected )
+    pub fn set_only_selected(&mut self, separator: String) {
+        self.only_selected = Some(separator);
+    }
+
+    /// Discard every chunk to print only the number of holes ( --count )
+    pub fn set_discard(&mut self) {
+        self.discard = true;
+    }
+
+    /// Number of holes made so far ( --count )
+    pub fn n_selected(&self) -> usize {
+        self.n_selected
+    }
+
+    /// Whether at least one hole has been made so far
+    pub fn has_selected(&self) -> bool {
+        self.n_selected > 0
+    }
+
+    /// Keep the parts of holes matching the regular expression ( --exclude-pattern )
+    pub fn set_exclude(&mut self, re: Regex) {
+        self.exclude = Some(re);
+    }
+
+    /// Give each hole to the command without buffering ( --unbuffered )
+    pub fn set_unbuffered(&mut self) {
+        self.unbuffered = true;
+    }
+
+    /// Print the number of holes in each line instead of executing the command ( --annotate-count )
+    pub fn set_annotate(&mut self, separator: String, suffix: bool) {
+        self.annotate = Some(Annotate { separator, suffix, line: String::new(), count: 0 });
+    }
+
+    /// Print the time spent in each phase to stderr at the end ( --profile )
+    pub fn set_print_profile(&mut self) {
+        self.print_profile = true;
+    }
+
+    /// Holes are the decoded contents of JSON strings ( --jsonl-key ).
+    /// The holes written without the command are encoded back like ResultWriter does for the results.
+    pub fn set_json_string(&mut self) {
+        self.json_string = true;
+    }
+
+    fn encode_hole(&self, msg: String) -> String {
+        if self.json_string {
+            stringutils::escape_json(&msg)
+        } else {
+            msg
+        }
+    }
+
+    /// Join the holes in each line with the separator into a single hole ( --join-fields )
+    pub fn set_join(&mut self, separator: String, last: bool) -> Result<(), String> {
+        // The separator would split the joined hole into two results of the command, like send_hole refuses
+        let terminated = !(self.solid || self.dryrun || self.per_line || self.length_prefixed || self.transform.is_some());
+        if terminated && separator.as_bytes().contains(&self.line_end) {
+            return Err(format!(
+                "<separator> of --join-fields includes {:?}, which terminates each hole given to the command",
+                self.line_end as char
+            ));
+        }
+        self.join = Some(Join { separator, last, chunks: Vec::new() });
+        Ok(())
+    }
+
+    /// Transform holes in-process instead of executing the command ( --strip-prefix and the others )
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = Some(transform);
+    }
+
+    /// Show control characters as escape sequences in dry run mode ( --escape-output )
+    pub fn set_escape_output(&mut self) {
+        self.escape = true;
+    }
+
+    /// Print each chunk given to the intercepter with SELECT (hole) or BYPASS (others) to stderr ( --trace )
+    pub fn set_trace(&mut self) {
+        self.trace = Some(Trace { line: 1, offset: 0 });
+    }
+
+    /// Print the original hole if the command prints an empty line for it ( --null-if-empty )
+    pub fn set_null_if_empty(&mut self) {
+        self.null_if_empty = true;
+    }
+
+    /// Count chunks and bytes, and print them to stderr at the end ( --stats )
+    pub fn set_stats(&mut self) {
+        self.stats.get_or_insert_with(Stats::default);
+        self.print_stats = true;
+    }
+
+    /// Print the summary of holes to stderr at the end ( --dry-run )
+    pub fn set_summary(&mut self) {
+        self.stats.get_or_insert_with(Stats::default);
+        self.print_summary = true;
+    }
+
+    /// Count holes and the others without printing them ( --summary-json )
+    pub fn collect_stats(&mut self) {
+        self.stats.get_or_insert_with(Stats::default);
+    }
+
+    /// Totals counted since collect_stats, set_stats or set_summary. Call after finish
+    pub fn summary(&self) -> Option<Summary> {
+        self.stats.as_ref().map(|stats| Summary {
+            hole_chunks: stats.hole_chunks,
+            hole_bytes: stats.hole_bytes,
+            hole_lines: stats.hole_lines,
+            keep_chunks: stats.keep_chunks,
+            keep_bytes: stats.keep_bytes,
+            lines: stats.lines,
+            commands: self.invocations(stats),
+        })
+    }
+
+    /// Number of commands executed
+    fn invocations(&self, stats: &Stats) -> usize {
+        // -s spawns a command for each hole, --command-per-line for each line including holes, otherwise a single command handles all holes
+        if self.dryrun || self.show_both {
+            0
+        } else if self.solid {
+            stats.hole_chunks - self.dedup_hits
+        } else if self.per_line {
+            stats.hole_lines
+        } else {
+            1
+        }
+    }
+
+    /// Print the table of --stats
+    fn print_stats(&self, stats: &Stats) {
+        let invocations = self.invocations(stats);
+        eprintln!("{:<10}{:>12}{:>16}", "", "chunks", "bytes");
+        eprintln!("{:<10}{:>12}{:>16}", "holes", stats.hole_chunks, stats.hole_bytes);
+        eprintln!("{:<10}{:>12}{:>16}", "others", stats.keep_chunks, stats.keep_bytes);
+        eprintln!(
+            "{:<10}{:>12}{:>16}",
+            "total",
+            stats.hole_chunks + stats.keep_chunks,
+            stats.hole_bytes + stats.keep_bytes
+        );
+        eprintln!("{:<10}{:>12}", "commands", invocations);
+    }
+
     /// Notify PipeIntercepter the end of file to exit process
-    pub fn send_eof(&self) -> Result<(), errors::ChunkSendError> {
+    pub fn send_eof(&mut self) -> Result<(), errors::ChunkSendError> {
+        // The last line of --join-fields may not end with the terminator
+        self.send_joined()?;
+        // The last line of --annotate-count may not end with the terminator
+        if let Some(ref mut annotate) = self.annotate {
+            if !annotate.line.is_empty() || annotate.count > 0 {
+                let out = annotate.take_line();
+                self.tx
+                    .send(Chunk::Keep(out))
+                    .map_err(errors::ChunkSendError::Channel)?;
+            }
+        }
         debug!("tx.send => EOF");
+        self.profile.finish_input();
         self.tx
             .send(Chunk::EOF)
             .map_err(|e| errors::ChunkSendError::Channel(e))?;
         Ok(())
     }
-}
 
-impl Drop for PipeIntercepter {
-    fn drop(&mut self) {
+    /// Close the pipe and wait for the output to be finished. Dropping PipeIntercepter calls this as well
+    pub fn finish(&mut self) {
+        let handler = match self.handler.take() {
+            Some(h) => h,
+            None => return,
+        };
         debug!("close pipe");
         // Replace the writer with a dummy object to close the pipe.
+        // Writing the rest of the buffer to the command is also a part of pipe write.
+        let timer = self.profile.timer();
         self.pipe_writer = BufWriter::new(Box::new(io::sink()));
-        self.handler.take().unwrap().join().unwrap();
+        self.profile.record(Phase::PipeWrite, timer);
+        self.dedup_hits = handler.join().unwrap();
+        if self.print_profile {
+            self.profile.report();
+        }
+        if let Some(ref stats) = self.stats {
+            if self.print_stats {
+                self.print_stats(stats);
+            }
+            if self.print_summary {
+                eprintln!(
+                    "selected {} regions across {} lines ({} bytes)",
+                    stats.hole_chunks, stats.hole_lines, stats.hole_bytes
+                );
+            }
+        }
+    }
+}
+
+impl Drop for PipeIntercepter {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    // Writer which counts the number of flushes
+    struct FlushCounter {
+        out: Vec<u8>,
+        flushes: usize,
+    }
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.out.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+    fn write_lines(buffer_lines: Option<usize>) -> (Vec<u8>, usize) {
+        let mut counter = FlushCounter { out: vec![], flushes: 0 };
+        {
+            let mut writer = ResultWriter::new(&mut counter, b'\n', buffer_lines);
+            for _ in 0..100 {
+                writer.write(b"AAA").unwrap();
+                writer.write(b"\n").unwrap();
+            }
+        }
+        (counter.out, counter.flushes)
+    }
+    #[test]
+    fn test_result_writer_buffer_lines() {
+        let (out, flushes) = write_lines(Some(1));
+        assert_eq!(out, b"AAA\n".repeat(100));
+        assert_eq!(flushes, 100 + 1);
+        let (out, flushes) = write_lines(Some(30));
+        assert_eq!(out, b"AAA\n".repeat(100));
+        assert_eq!(flushes, 3 + 1);
+        let (out, flushes) = write_lines(None);
+        assert_eq!(out, b"AAA\n".repeat(100));
+        assert_eq!(flushes, 1);
+    }
+    #[test]
+    fn test_dedup_cache() {
+        let mut cache = DedupCache::new(2);
+        cache.insert("a".to_string(), "A".to_string());
+        cache.insert("b".to_string(), "B".to_string());
+        assert_eq!(cache.get("a"), Some("A".to_string()));
+        // The oldest one is evicted
+        cache.insert("c".to_string(), "C".to_string());
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), Some("B".to_string()));
+        assert_eq!(cache.get("c"), Some("C".to_string()));
+    }
+
+    #[test]
+    fn test_result_writer_record_sep() {
+        let mut out = Vec::new();
+        {
+            let mut writer = ResultWriter::new(&mut out, b'\x1e', Some(2));
+            writer.set_record_sep(b"\r\n".to_vec());
+            writer.write(b"a\x1eb").unwrap();
+            writer.write(b"\x1e\x1e").unwrap();
+        }
+        assert_eq!(out, b"a\r\nb\r\n\r\n");
+    }
+
+    #[test]
+    fn test_result_writer_encoding() {
+        let encode = |encoding: OutputEncoding, msgs: &[&str]| {
+            let mut out = vec![];
+            {
+                let mut writer = ResultWriter::new(&mut out, b'\n', None);
+                writer.set_encoding(encoding);
+                for msg in msgs {
+                    writer.write(msg.as_bytes()).unwrap();
+                }
+            }
+            out
+        };
+        assert_eq!(encode(OutputEncoding::Utf8, &["aあ\n"]), "aあ\n".as_bytes());
+        assert_eq!(encode(OutputEncoding::Utf16Le, &["aあ", "\n"]), b"a\x00\x42\x30\n\x00");
+        assert_eq!(encode(OutputEncoding::Utf16Be, &["aあ", "\n"]), b"\x00a\x30\x42\x00\n");
+        assert_eq!(encode(OutputEncoding::Utf16, &["", "\u{feff}a", "b"]), b"\xff\xfea\x00b\x00");
+        assert!(OutputEncoding::parse("sjis").is_err());
     }
 }
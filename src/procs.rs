@@ -1,101 +1,295 @@
 use super::pipeintercepter::PipeIntercepter;
 use super::spawnutils;
 use super::list;
+use super::list::ranges::{Ranges, UNBOUNDED};
 use super::{errors,errors::*};
 use super::stringutils;
 use regex::Regex;
+use serde_json::value::RawValue;
 use super::DEFAULT_CAP;
-use std::io::{self, BufRead};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use super::input::Input;
+use std::io::BufRead;
+use unicode_width::UnicodeWidthChar;
+
+/// Logical records made of the lines of the input ( --continuation )
+///
+/// A line matching the pattern is joined to the previous line, like an indented line
+/// following a log entry. Each record is a list of the lines and their terminators.
+/// Without the pattern, every record is a single line.
+struct Records<'a> {
+    input: &'a Input,
+    reader: Box<dyn BufRead + Send>,
+    line_end: u8,
+    continuation: Option<Regex>,
+    next: Option<(String, String)>,
+}
+
+impl<'a> Records<'a> {
+    fn new(input: &'a Input, line_end: u8, continuation: Option<&Regex>) -> Records<'a> {
+        Records { input, reader: input.open(), line_end, continuation: continuation.cloned(), next: None }
+    }
+
+    fn read_line(&mut self) -> Option<(String, String)> {
+        let mut buf = Vec::with_capacity(DEFAULT_CAP);
+        match stringutils::read_line(&mut self.reader, self.line_end, &mut buf) {
+            Ok(0) => None,
+            Ok(_) => {
+                let eol = stringutils::trim_eol(&mut buf, self.line_end);
+                Some((self.input.decode(&buf), eol))
+            }
+            Err(e) => error_exit(&e),
+        }
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Vec<(String, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.next.take() {
+            Some(line) => line,
+            None => self.read_line()?,
+        };
+        let mut record = vec![first];
+        // The next line is read ahead only if it may be a continuation
+        if let Some(re) = self.continuation.clone() {
+            while let Some(line) = self.read_line() {
+                if re.is_match(&line.0) {
+                    record.push(line);
+                } else {
+                    self.next = Some(line);
+                    break;
+                }
+            }
+        }
+        Some(record)
+    }
+}
+
+// Send each line of the record as a hole or not, so that the command receives the lines as they are
+fn send_record(ch: &mut PipeIntercepter, record: Vec<(String, String)>, hole: bool) -> Result<(), errors::ChunkSendError> {
+    for (line, eol) in record {
+        if hole {
+            ch.send_byps(line)?;
+        } else {
+            ch.send_keep(line)?;
+        }
+        ch.send_keep(eol)?;
+    }
+    Ok(())
+}
 
 /// Bypassing particular lines based on given list ( -l )
+///
+/// `ranges` must be sorted and must not overlap (see list::converter::to_ranges).
+/// With --continuation, lines are counted as logical records.
 pub fn line_line_proc(
     ch: &mut PipeIntercepter,
+    input: &Input,
     ranges: &Vec<list::ranges::Range>,
     line_end: u8,
+    continuation: Option<&Regex>,
 ) -> Result<(), errors::ChunkSendError> {
-    let mut i: usize = 0;
-    let mut ri: usize = 0;
-    let stdin = io::stdin();
+    let mut ranges = Ranges::new(ranges);
+    for (i, record) in Records::new(input, line_end, continuation).enumerate() {
+        let hole = ranges.contains(i + 1);
+        send_record(ch, record, hole)?;
+    }
+    ch.send_eof()
+}
+
+/// Bypassing last n lines ( --tail )
+///
+/// The last n lines are not known until EOF, so they are kept in a ring buffer.
+/// A line is sent as unmatched once it is pushed out of the buffer.
+pub fn line_tail_proc(
+    ch: &mut PipeIntercepter,
+    input: &Input,
+    n: usize,
+    invert: bool,
+    line_end: u8,
+) -> Result<(), errors::ChunkSendError> {
+    let mut ring: VecDeque<(String, String)> = VecDeque::new();
+    let mut reader = input.open();
     loop {
         let mut buf = Vec::with_capacity(DEFAULT_CAP);
-        match stdin.lock().read_until(line_end, &mut buf) {
-            Ok(n) => {
-                let eol = stringutils::trim_eol(&mut buf);
-                let line = String::from_utf8_lossy(&buf).to_string();
-                if n == 0 {
-                    ch.send_eof()?;
-                    break;
-                }
-                if ranges[ri].high < (i + 1) && (ri + 1) < ranges.len() {
-                    ri += 1;
-                }
-                if ranges[ri].low <= (i + 1) && (i + 1) <= ranges[ri].high {
-                    ch.send_byps(line.to_string())?;
-                } else {
-                    ch.send_keep(line.to_string())?;
+        match stringutils::read_line(&mut reader, line_end, &mut buf) {
+            Ok(0) => break,
+            Ok(_) => {
+                let eol = stringutils::trim_eol(&mut buf, line_end);
+                let line = input.decode(&buf);
+                ring.push_back((line, eol));
+                if ring.len() > n {
+                    let (line, eol) = ring.pop_front().unwrap();
+                    if invert {
+                        ch.send_byps(line)?;
+                    } else {
+                        ch.send_keep(line)?;
+                    }
+                    ch.send_keep(eol)?;
                 }
-                ch.send_keep(eol)?;
             }
-            Err(e) => msg_error(&e.to_string()),
+            Err(e) => error_exit(&e),
         }
-        i += 1;
     }
+    for (line, eol) in ring {
+        if invert {
+            ch.send_keep(line)?;
+        } else {
+            ch.send_byps(line)?;
+        }
+        ch.send_keep(eol)?;
+    }
+    ch.send_eof()?;
     Ok(())
 }
 
-/// Bypassing particular lines based on Regular Expression ( -g )
-pub fn regex_line_proc(
+/// Bypassing lines selected from the bottom up ( --reverse-lines )
+///
+/// The entire input is buffered to count lines from the end.
+/// `select` receives each line with its number counted from the last line, starting with 1.
+/// `nth` selects the holes counted from the last one as well.
+pub fn reverse_line_proc<F: FnMut(usize, &str) -> bool>(
     ch: &mut PipeIntercepter,
+    input: &Input,
+    mut select: F,
+    nth: Option<&Vec<list::ranges::Range>>,
+    line_end: u8,
+) -> Result<(), errors::ChunkSendError> {
+    let mut lines: Vec<(String, String)> = Vec::new();
+    let mut reader = input.open();
+    loop {
+        let mut buf = Vec::with_capacity(DEFAULT_CAP);
+        match stringutils::read_line(&mut reader, line_end, &mut buf) {
+            Ok(0) => break,
+            Ok(_) => {
+                let eol = stringutils::trim_eol(&mut buf, line_end);
+                lines.push((input.decode(&buf), eol));
+            }
+            Err(e) => error_exit(&e),
+        }
+    }
+    let mut selected = vec![false; lines.len()];
+    let mut nth = nth.map(|r| Ranges::new(r));
+    let mut holes = 0;
+    for (i, (line, _)) in lines.iter().enumerate().rev() {
+        if select(lines.len() - i, line) {
+            holes += 1;
+            selected[i] = nth.as_mut().map_or(true, |r| r.contains(holes));
+        }
+    }
+    // Emit the lines in the original order
+    for ((line, eol), is_selected) in lines.into_iter().zip(selected) {
+        if is_selected {
+            ch.send_byps(line)?;
+        } else {
+            ch.send_keep(line)?;
+        }
+        ch.send_keep(eol)?;
+    }
+    ch.send_eof()?;
+    Ok(())
+}
+
+/// Bypassing <n> lines after each line matching the regular expression ( --after-match )
+/// The matched line itself is not bypassed, and it restarts the count.
+pub fn after_match_proc(
+    ch: &mut PipeIntercepter,
+    input: &Input,
     re: &Regex,
+    n: usize,
     invert: bool,
     line_end: u8,
 ) -> Result<(), errors::ChunkSendError> {
-    let stdin = io::stdin();
+    let mut remaining = 0; // number of lines to be bypassed after the last anchor
+    let mut reader = input.open();
     loop {
         let mut buf = Vec::with_capacity(DEFAULT_CAP);
-        match stdin.lock().read_until(line_end, &mut buf) {
-            Ok(n) => {
-                let eol = stringutils::trim_eol(&mut buf);
-                if n == 0 {
-                    ch.send_eof()?;
-                    break;
-                }
-                let line = String::from_utf8_lossy(&buf).to_string();
-                if re.is_match(&line) {
-                    if invert {
-                        ch.send_keep(line.to_string())?;
-                    } else {
-                        ch.send_byps(line.to_string())?;
-                    }
+        match stringutils::read_line(&mut reader, line_end, &mut buf) {
+            Ok(0) => break,
+            Ok(_) => {
+                let eol = stringutils::trim_eol(&mut buf, line_end);
+                let line = input.decode(&buf);
+                let selected = if re.is_match(&line) {
+                    remaining = n;
+                    false
+                } else if remaining > 0 {
+                    remaining -= 1;
+                    true
                 } else {
-                    if invert {
-                        ch.send_byps(line.to_string())?;
-                    } else {
-                        ch.send_keep(line.to_string())?;
-                    }
+                    false
+                };
+                if selected != invert {
+                    ch.send_byps(line)?;
+                } else {
+                    ch.send_keep(line)?;
                 }
                 ch.send_keep(eol)?;
             }
-            Err(e) => msg_error(&e.to_string()),
+            Err(e) => error_exit(&e),
         }
     }
+    ch.send_eof()?;
     Ok(())
 }
 
+/// Bypassing particular lines based on Regular Expression ( -g )
+pub fn regex_line_proc(
+    ch: &mut PipeIntercepter,
+    input: &Input,
+    re: &Regex,
+    invert: bool,
+    line_end: u8,
+    include_eol: bool,
+    continuation: Option<&Regex>,
+) -> Result<(), errors::ChunkSendError> {
+    for record in Records::new(input, line_end, continuation) {
+        // --continuation option, the pattern is matched against the entire record.
+        // --include-eol option, the pattern sees the last terminator while the hole does not include it.
+        let mut text = String::new();
+        let last = record.len() - 1;
+        for (i, (line, eol)) in record.iter().enumerate() {
+            text.push_str(line);
+            if i < last || include_eol {
+                text.push_str(eol);
+            }
+        }
+        let matched = re.is_match(&text);
+        send_record(ch, record, matched != invert)?;
+    }
+    ch.send_eof()
+}
+
 /// Bypassing particular strings based on Regular Expression ( -o -g )
 pub fn regex_proc(
     ch: &mut PipeIntercepter,
-    line: &Vec<u8>,
+    line: &str,
     re: &Regex,
     invert: bool,
+    group: Option<usize>,
+    min_len: usize,
 ) -> Result<(), errors::ChunkSendError> {
-    let line = String::from_utf8_lossy(&line).to_string();
+    // --group option, only the group of each match is bypassed.
+    // The other parts of the match are handled like unmatched parts.
+    let holes: Box<dyn Iterator<Item = (usize, usize)>> = match group {
+        Some(g) => Box::new(
+            re.captures_iter(line)
+                .filter_map(move |cap| cap.get(g))
+                .map(|m| (m.start(), m.end())),
+        ),
+        None => Box::new(re.find_iter(line).map(|m| (m.start(), m.end()))),
+    };
+    // --min-match-length option, shorter matches are handled like unmatched parts
+    let line_ref = &line;
+    let holes = holes.filter(move |&(start, end)| min_len == 0 || line_ref[start..end].chars().count() >= min_len);
     let mut left_index = 0;
     let mut right_index;
-    for cap in re.find_iter(&line) {
-        right_index = cap.start();
+    for (start, end) in holes {
+        right_index = start;
         let unmatched = &line[left_index..right_index];
-        let matched = &line[cap.start()..cap.end()];
+        let matched = &line[start..end];
         // Ignore empty string.
         // Regex "*" matches empty, but , in most situations,
         // handling empty string is not helpful for users.
@@ -111,7 +305,7 @@ pub fn regex_proc(
         } else {
             ch.send_keep(matched.to_string())?;
         }
-        left_index = cap.end();
+        left_index = end;
     }
     if left_index < line.len() {
         let unmatched = &line[left_index..line.len()];
@@ -127,23 +321,51 @@ pub fn regex_proc(
 /// Bypassing character range ( -c )
 pub fn char_proc(
     ch: &mut PipeIntercepter,
-    line: &Vec<u8>,
+    line: &str,
     ranges: &Vec<list::ranges::Range>,
+    merge: bool,
+    display_columns: bool,
+    tab_stop: Option<usize>,
+    bytes: bool,
 ) -> Result<(), errors::ChunkSendError> {
-    let line = String::from_utf8_lossy(&line).to_string();
     let cs = line.chars();
     let mut str_in = String::new();
     let mut str_out = String::new();
-    let mut ri = 0;
+    let mut ranges = Ranges::new(ranges);
     let mut is_in;
     let mut last_is_in = false;
+    let mut column = 0; // display columns of the characters so far
+    let mut line_start = 0; // column after the last newline, for the tab stops
     // Merge consequent characters' range to execute commands as few times as possible.
     for (i, c) in cs.enumerate() {
-        if ranges[ri].high < (i + 1) && (ri + 1) < ranges.len() {
-            ri += 1;
+        // --display-columns option, a wide character occupies two columns and it is selected
+        // if either of them is in the list. Zero-width characters go with the previous one.
+        // --expand-tabs option, a tab occupies the columns up to the next tab stop in the same way.
+        // -b option, a character occupies its bytes, so a partially selected character is selected entirely.
+        let width = match tab_stop {
+            Some(n) if c == '\t' => n - (column - line_start) % n,
+            _ if bytes => c.len_utf8(),
+            _ if display_columns => UnicodeWidthChar::width(c).unwrap_or(0),
+            _ => 1,
+        };
+        if c == '\n' {
+            line_start = column + width;
         }
-        if ranges[ri].low <= (i + 1) && (i + 1) <= ranges[ri].high {
+        let selected = if !display_columns && tab_stop.is_none() && !bytes {
+            ranges.contains(i + 1)
+        } else if width == 0 {
+            last_is_in
+        } else {
+            (column + 1..=column + width).any(|n| ranges.contains(n))
+        };
+        column += width;
+        if selected {
             is_in = true;
+            // --no-merge option, each character is a hole
+            if !merge && last_is_in && width > 0 {
+                ch.send_byps(str_in.to_string())?;
+                str_in.clear();
+            }
             str_in.push(c);
         } else {
             is_in = false;
@@ -166,68 +388,185 @@ pub fn char_proc(
     Ok(())
 }
 
+/// Condition of the fields to be bypassed ( --field-match, --field-command )
+pub enum FieldFilter {
+    Regex(Regex),
+    // The command is executed once for each distinct field, and the result is cached
+    Command { command: String, cache: RefCell<HashMap<String, bool>> },
+}
+
+impl FieldFilter {
+    pub fn command(command: &str) -> FieldFilter {
+        FieldFilter::Command { command: command.to_string(), cache: RefCell::new(HashMap::new()) }
+    }
+
+    fn is_match(&self, field: &str) -> bool {
+        match self {
+            FieldFilter::Regex(re) => re.is_match(field),
+            FieldFilter::Command { command, cache } => {
+                if let Some(&matched) = cache.borrow().get(field) {
+                    return matched;
+                }
+                let matched = spawnutils::exec_predicate(command, field).unwrap_or_else(|e| error_exit(&e.to_string()));
+                cache.borrow_mut().insert(field.to_string(), matched);
+                matched
+            }
+        }
+    }
+}
+
+/// Bypassing i-th field if it is in the list and it matches --field-match <pattern> or --field-command <command>.
+/// `field_match` has the condition and whether the match is inverted (-v).
+fn send_field(
+    ch: &mut PipeIntercepter,
+    ranges: &mut Ranges,
+    i: usize,
+    field: &str,
+    field_match: Option<(&FieldFilter, bool)>,
+) -> Result<(), errors::ChunkSendError> {
+    if ranges.contains(i) && field_match.map_or(true, |(f, invert)| f.is_match(field) != invert) {
+        ch.send_byps(field.to_string())
+    } else {
+        ch.send_keep(field.to_string())
+    }
+}
+
+// --field-default option, fields missing in the line are added up to the last one of <list>.
+// Fields in <list> are the default value, and the others are empty.
+#[allow(clippy::too_many_arguments)]
+fn send_default_fields(
+    ch: &mut PipeIntercepter,
+    ranges: &mut Ranges,
+    list: &[list::ranges::Range],
+    next: usize,
+    delim: &str,
+    default: &str,
+    field_match: Option<(&FieldFilter, bool)>,
+) -> Result<(), errors::ChunkSendError> {
+    let last = match list.last() {
+        // Open-ended range like 5- adds fields up to its start
+        Some(r) if r.high == UNBOUNDED => r.low,
+        Some(r) => r.high,
+        None => return Ok(()),
+    };
+    for i in next..=last {
+        ch.send_keep(delim.to_string())?;
+        if ranges.contains(i) {
+            send_field(ch, ranges, i, default, field_match)?;
+        } else {
+            ch.send_keep(String::new())?;
+        }
+    }
+    Ok(())
+}
+
 /// Bypassing white space separation ( -f )
+#[allow(clippy::too_many_arguments)]
 pub fn field_regex_proc(
     ch: &mut PipeIntercepter,
-    line: &Vec<u8>,
+    line: &str,
     re: &Regex,
     ranges: &Vec<list::ranges::Range>,
+    out_delim: Option<&str>,
+    delim_replace: Option<(&Regex, &str)>,
+    field_match: Option<(&FieldFilter, bool)>,
+    captures: bool,
+    default: Option<&str>,
+    max_fields: Option<usize>,
 ) -> Result<(), errors::ChunkSendError> {
-    let line = String::from_utf8_lossy(&line).to_string();
+    let list = ranges;
     let mut i = 1; // current field index
-    let mut ri = 0;
+    let mut ranges = Ranges::new(ranges);
     let mut left_index = 0;
     let mut right_index;
-    for cap in re.find_iter(&line) {
+    let mut locs = re.capture_locations();
+    let mut last_delim = " ".to_string(); // delimiter put before the fields of --field-default
+    for cap in re.find_iter(line) {
+        // --max-fields option, the rest of the line is the last field
+        if max_fields.is_some_and(|n| i >= n) {
+            break;
+        }
         right_index = cap.start();
         let field = &line[left_index..right_index]; // This can be empty string
         let spaces = &line[cap.start()..cap.end()];
         left_index = cap.end();
-        if ranges[ri].high < i && (ri + 1) < ranges.len() {
-            ri += 1;
+        send_field(ch, &mut ranges, i, field, field_match)?;
+        i += 1;
+        if !captures {
+            // --output-delimiter replaces the matched delimiter,
+            // and --delimiter-replace substitutes the pattern in it
+            let delim = match delim_replace {
+                Some((pattern, replacement)) => pattern.replace_all(spaces, replacement).to_string(),
+                None => out_delim.unwrap_or(spaces).to_string(),
+            };
+            if default.is_some() {
+                last_delim = delim.clone();
+            }
+            ch.send_keep(delim)?;
+            continue;
         }
-        if ranges[ri].low <= i && i <= ranges[ri].high {
-            ch.send_byps(field.to_string())?;
-        } else {
-            ch.send_keep(field.to_string())?;
+        // --delimiter-regex-captures option, each capture group in the delimiter is a field
+        // numbered next to the previous field. The other parts of the delimiter are kept.
+        re.captures_read_at(&mut locs, line, cap.start());
+        let mut pos = cap.start();
+        for g in 1..locs.len() {
+            if let Some((start, end)) = locs.get(g) {
+                // Nested groups are a part of the outer group
+                if start >= pos {
+                    ch.send_keep(line[pos..start].to_string())?;
+                    send_field(ch, &mut ranges, i, &line[start..end], field_match)?;
+                    pos = end;
+                }
+            }
+            i += 1;
         }
-        ch.send_keep(spaces.to_string())?;
-        i += 1;
+        ch.send_keep(line[pos..cap.end()].to_string())?;
     }
     // If line ends with delimiter, empty fields must be handled.
     if left_index <= line.len() {
-        if ranges[ri].high < i && (ri + 1) < ranges.len() {
-            ri += 1;
-        }
         // filed is empty if line ends with delimiter
         let field = &line[left_index..line.len()];
-        if ranges[ri].low <= i && i <= ranges[ri].high {
-            ch.send_byps(field.to_string())?;
-        } else {
-            ch.send_keep(field.to_string())?;
-        }
+        send_field(ch, &mut ranges, i, field, field_match)?;
+    }
+    if let Some(default) = default {
+        let delim = out_delim.unwrap_or(&last_delim);
+        send_default_fields(ch, &mut ranges, list, i + 1, delim, default, field_match)?;
     }
     Ok(())
 }
 
 /// Bypassing field separation ( -f -d )
+#[allow(clippy::too_many_arguments)]
 pub fn field_proc(
     ch: &mut PipeIntercepter,
-    line: &Vec<u8>,
+    line: &str,
     delim: &str,
     ranges: &Vec<list::ranges::Range>,
+    out_delim: Option<&str>,
+    field_match: Option<(&FieldFilter, bool)>,
+    select_delims: bool,
+    default: Option<&str>,
+    max_fields: Option<usize>,
 ) -> Result<(), errors::ChunkSendError> {
-    let line = String::from_utf8_lossy(&line).to_string();
-    let chunks = line.split(delim);
-    let mut ri = 0;
+    // --max-fields option, the rest of the line is the last field
+    let chunks = line.splitn(max_fields.unwrap_or(usize::MAX), delim);
+    let out_delim = out_delim.unwrap_or(delim);
+    let list = ranges;
+    let mut ranges = Ranges::new(ranges);
+    let mut n_fields = 0;
     for (i, chunk) in chunks.enumerate() {
+        n_fields = i + 1;
         if i > 0 {
-            ch.send_keep(delim.to_string())?;
-        }
-        if ranges[ri].high < (i + 1) && (ri + 1) < ranges.len() {
-            ri += 1;
+            // --select-delimiters option, the i-th delimiter is bypassed instead of fields
+            if select_delims && ranges.contains(i) {
+                ch.send_byps(out_delim.to_string())?;
+            } else {
+                ch.send_keep(out_delim.to_string())?;
+            }
         }
-        if ranges[ri].low <= (i + 1) && (i + 1) <= ranges[ri].high {
+        if select_delims {
+            ch.send_keep(chunk.to_string())?;
+        } else {
             // Should empty filed sent as empty string ? Discussion is needed.
             // But author(@greymd) believes empty string is good to be sent.
             // Because teip can be used as simple CSV file editor if it is allowed!
@@ -237,9 +576,40 @@ pub fn field_proc(
             // 5,6,7,8
             // 9,10,11,12
             // ```
-            ch.send_byps(chunk.to_string())?;
-        } else {
-            ch.send_keep(chunk.to_string())?;
+            send_field(ch, &mut ranges, i + 1, chunk, field_match)?;
+        }
+    }
+    if let Some(default) = default {
+        send_default_fields(ch, &mut ranges, list, n_fields + 1, out_delim, default, field_match)?;
+    }
+    Ok(())
+}
+
+/// Bypassing fields in the order of the list, instead of the original order ( --reorder )
+/// Fields out of the list are removed from the output.
+///  Example:
+///  ``````````````````````````````````````````````````````````````````
+///  $ echo "a,b,c" | teip -d, -f 3,1,2 --reorder
+///  [c],[a],[b]
+///  ``````````````````````````````````````````````````````````````````
+pub fn field_reorder_proc(
+    ch: &mut PipeIntercepter,
+    line: &str,
+    delim: &str,
+    ranges: &[list::ranges::Range],
+    out_delim: Option<&str>,
+    max_fields: Option<usize>,
+) -> Result<(), errors::ChunkSendError> {
+    let chunks: Vec<&str> = line.splitn(max_fields.unwrap_or(usize::MAX), delim).collect();
+    let out_delim = out_delim.unwrap_or(delim);
+    let mut first = true;
+    for range in ranges {
+        for i in range.low..=range.high.min(chunks.len()) {
+            if !first {
+                ch.send_keep(out_delim.to_string())?;
+            }
+            first = false;
+            ch.send_byps(chunks[i - 1].to_string())?;
         }
     }
     Ok(())
@@ -303,16 +673,22 @@ pub fn field_proc(
 ///                         └─────────────────────────────────┘  └─────────────────────┘
 pub fn exoffload_proc(
     ch: &mut PipeIntercepter,
+    input: &Input,
     exoffload_pipeline: &str,
     invert: bool,
     line_end: u8,
+    mask_delimiter: Option<String>,
+    mask_bitmap: bool,
 ) -> Result<(), errors::ChunkSendError> {
-    let stdin = io::stdin();
-    let (rx_stdin1, rx_stdin2, _tee_thread) = spawnutils::tee(stdin, line_end)
+    let (rx_stdin1, rx_stdin2, _tee_thread) = spawnutils::tee(input.open(), line_end)
             .unwrap_or_else(|e| error_exit(&e.to_string()));
     let (rx_messy_numbers, _ex_thread) = spawnutils::exec_pipeline_mpsc_input(exoffload_pipeline, rx_stdin1)
             .unwrap_or_else(|e| error_exit(&e.to_string()));
-    let (rx_numbers, _num_thread) = spawnutils::clean_numbers(rx_messy_numbers, line_end);
+    let (rx_numbers, _num_thread) = if mask_bitmap {
+        spawnutils::bitmap_numbers(rx_messy_numbers, line_end)
+    } else {
+        spawnutils::clean_numbers(rx_messy_numbers, line_end, mask_delimiter)
+    };
     let mut nr: u64 = 0;     // number of read
     let mut pos: u64 = 0;    // position of printable numbers
     let mut last_pos: u64 = pos;
@@ -327,8 +703,8 @@ pub fn exoffload_proc(
                 break;
             },
         };
-        let eol = stringutils::trim_eol(&mut buf);
-        let line = String::from_utf8_lossy(&buf).to_string();
+        let eol = stringutils::trim_eol(&mut buf, line_end);
+        let line = input.decode(&buf);
         // Try to detect printable line numbers which is bigger than current read line
         while expect_new_numbers && pos < nr {
             pos = match rx_numbers.recv() {
@@ -362,11 +738,53 @@ pub fn exoffload_proc(
     Ok(())
 }
 
+/// Bypassing the value of the top-level key in each line of JSON Lines ( --jsonl-key )
+///
+/// The hole is the decoded string value, and the result is encoded back between the double quotes of the value,
+/// so the rest of the line keeps the original order of the keys and the formatting.
+/// With --jsonl-stringify, a number, boolean or null is also a hole, and it is surrounded by double quotes.
+/// Lines without the key, objects and arrays, and lines which are not a JSON object are kept as they are.
+/// A value with a newline is an error unless multiline is true, because the command gives a result per line.
+pub fn jsonl_proc(
+    ch: &mut PipeIntercepter,
+    line: &str,
+    key: &str,
+    stringify: bool,
+    multiline: bool,
+) -> Result<(), errors::ChunkSendError> {
+    let value = serde_json::from_str::<HashMap<String, &RawValue>>(line)
+        .ok()
+        .and_then(|map| map.get(key).map(|v| v.get()));
+    let raw = match value {
+        Some(raw) => raw,
+        None => return ch.send_keep(line.to_string()),
+    };
+    // The raw value is a slice of the line
+    let start = raw.as_ptr() as usize - line.as_ptr() as usize;
+    let end = start + raw.len();
+    if raw.starts_with('"') {
+        let value = serde_json::from_str::<String>(raw).unwrap_or_else(|e| error_exit(&e.to_string()));
+        if value.contains('\n') && !multiline {
+            error_exit("The value of --jsonl-key contains a newline. Give -s, -z, --solid-nul or --length-prefixed to execute the command");
+        }
+        ch.send_keep(line[..start + 1].to_string())?;
+        ch.send_byps(value)?;
+        ch.send_keep(line[end - 1..].to_string())
+    } else if stringify && !raw.starts_with(['{', '[']) {
+        ch.send_keep(line[..start].to_string() + "\"")?;
+        ch.send_byps(raw.to_string())?;
+        ch.send_keep("\"".to_string() + &line[end..])
+    } else {
+        ch.send_keep(line.to_string())
+    }
+}
+
 /// Process CSV align with RFC 4180 (--csv)
 ///
 /// This function is called from main() when --csv option is specified.
 pub fn csv_proc(
     ch: &mut PipeIntercepter,
+    input: &Input,
     ranges: &Vec<list::ranges::Range>,
     line_end: u8,
     flag_solid: bool,
@@ -378,13 +796,13 @@ pub fn csv_proc(
     let mut is_byps;
     let line_end_char = line_end as char;
     let mut last_is_byps = false;
-    let mut ri = 0;
-    let stdin = io::stdin();
+    let mut ranges = Ranges::new(ranges);
+    let mut reader = input.open();
     loop {
         let mut buf = Vec::with_capacity(DEFAULT_CAP);
-        match stdin.lock().read_until(line_end, &mut buf) {
+        match stringutils::read_line(&mut reader, line_end, &mut buf) {
             Ok(n) => {
-                let line = String::from_utf8_lossy(&buf).to_string();
+                let line = input.decode(&buf);
                 let cs = line.chars();
                 // Check each byte in the line
                 for (_, c) in cs.enumerate() {
@@ -392,10 +810,7 @@ pub fn csv_proc(
                     if parser.is_in_field() && ( flag_solid || c != line_end_char ) {
                         let field = parser.field() as usize;
                         // check if the field is in the range
-                        if ranges[ri].high < field && (ri + 1) < ranges.len() {
-                            ri += 1;
-                        }
-                        if ranges[ri].low <= field && field <= ranges[ri].high {
+                        if ranges.contains(field) {
                             is_byps = true;
                             str_byps.push(c);
                         } else {
@@ -415,7 +830,6 @@ pub fn csv_proc(
                     }
                     last_is_byps = is_byps;
                 }
-                ri = 0;
                 if n == 0 {
                     // If end of file does not have line feed, this part sends the remaining chunk
                     if last_is_byps && !str_byps.is_empty() {
@@ -428,7 +842,7 @@ pub fn csv_proc(
                     break;
                 }
             }
-            Err(e) => msg_error(&e.to_string()),
+            Err(e) => error_exit(&e),
         }
     }
     Ok(())
@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Where the time is spent
+pub enum Phase {
+    PipeWrite, // Main thread is blocked on writing holes to the command
+    PipeRead,  // Output thread is blocked on reading the results of the command
+    Command,   // Command spawned by -s is running
+}
+
+/// Seconds spent in each phase
+pub struct Seconds {
+    pub matching: f64,
+    pub pipe_write: f64,
+    pub pipe_read: f64,
+    pub command: f64,
+    pub total: f64,
+}
+
+/// Time spent in each phase, shared by the main thread and the output thread
+/// ( --profile, --summary-json ). Nothing is measured by the default one.
+#[derive(Clone, Default)]
+pub struct Profile {
+    counters: Option<Arc<Counters>>,
+}
+
+struct Counters {
+    start: Instant,
+    input_end: AtomicU64, // Nanoseconds from the start to the end of the input
+    pipe_write: AtomicU64,
+    pipe_read: AtomicU64,
+    command: AtomicU64,
+}
+
+impl Profile {
+    // Measure the time spent in each phase from now
+    pub fn new() -> Profile {
+        Profile {
+            counters: Some(Arc::new(Counters {
+                start: Instant::now(),
+                input_end: AtomicU64::new(0),
+                pipe_write: AtomicU64::new(0),
+                pipe_read: AtomicU64::new(0),
+                command: AtomicU64::new(0),
+            })),
+        }
+    }
+
+    // Start a timer of a phase. None if the time is not measured.
+    pub fn timer(&self) -> Option<Instant> {
+        self.counters.as_ref().map(|_| Instant::now())
+    }
+
+    // Add the time elapsed since the timer to the phase
+    pub fn record(&self, phase: Phase, timer: Option<Instant>) {
+        if let (Some(c), Some(t)) = (self.counters.as_ref(), timer) {
+            let counter = match phase {
+                Phase::PipeWrite => &c.pipe_write,
+                Phase::PipeRead => &c.pipe_read,
+                Phase::Command => &c.command,
+            };
+            counter.fetch_add(t.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+
+    // Main thread has read the entire input
+    pub fn finish_input(&self) {
+        if let Some(c) = self.counters.as_ref() {
+            c.input_end.store(c.start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+
+    // Breakdown of the time measured so far. None if it is not measured.
+    pub fn seconds(&self) -> Option<Seconds> {
+        let c = self.counters.as_ref()?;
+        let secs = |n: u64| n as f64 / 1e9;
+        let pipe_write = c.pipe_write.load(Ordering::Relaxed);
+        // Time of the main thread except for writing holes, i.e reading and matching the input
+        let matching = c.input_end.load(Ordering::Relaxed).saturating_sub(pipe_write);
+        Some(Seconds {
+            matching: secs(matching),
+            pipe_write: secs(pipe_write),
+            pipe_read: secs(c.pipe_read.load(Ordering::Relaxed)),
+            command: secs(c.command.load(Ordering::Relaxed)),
+            total: c.start.elapsed().as_secs_f64(),
+        })
+    }
+
+    // Print the breakdown of the time in seconds
+    pub fn report(&self) {
+        let s = match self.seconds() {
+            Some(s) => s,
+            None => return,
+        };
+        eprintln!("{:<12}{:>14}", "", "seconds");
+        eprintln!("{:<12}{:>14.6}", "matching", s.matching);
+        eprintln!("{:<12}{:>14.6}", "pipe write", s.pipe_write);
+        eprintln!("{:<12}{:>14.6}", "pipe read", s.pipe_read);
+        eprintln!("{:<12}{:>14.6}", "command", s.command);
+        eprintln!("{:<12}{:>14.6}", "total", s.total);
+    }
+}
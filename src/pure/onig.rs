@@ -1,5 +1,6 @@
 pub type Regex = i64;
 use super::super::{errors, PipeIntercepter};
+use super::super::input::Input;
 use super::super::CMD;
 
 pub fn new_regex() -> Regex {
@@ -17,7 +18,7 @@ pub fn new_option_none_regex(_s: &str) -> Regex {
 /// Bypassing multiple strings in a line based on Oniguruma Regular Expression ( -g -G -o )
 pub fn regex_onig_proc(
     _ch: &mut PipeIntercepter,
-    _line: &Vec<u8>,
+    _line: &str,
     _re: &Regex,
     _invert: bool,
 ) -> Result<(), errors::ChunkSendError> {
@@ -28,6 +29,7 @@ pub fn regex_onig_proc(
 /// Bypassing particular lines based on Oniguruma Regular Expression ( -g -G )
 pub fn regex_onig_line_proc(
     _ch: &mut PipeIntercepter,
+    _input: &Input,
     _re: &Regex,
     _invert: bool,
     _line_end: u8,
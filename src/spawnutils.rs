@@ -6,8 +6,20 @@ use std::io::{self, BufRead, BufWriter, BufReader, Read, Write};
 use std::thread;
 use std::process::{Command, Stdio};
 use std::sync::mpsc::{self,Receiver};
+use std::env;
+use std::path::Path;
 use log::debug;
 
+// Standard error of the command spawned by -s.
+// It is put after the output if stderr_on_stdout is true ( --keep-command-stderr-on-stdout ).
+fn sync_stderr(stderr_on_stdout: bool) -> Stdio {
+    if stderr_on_stdout {
+        Stdio::piped()
+    } else {
+        Stdio::inherit()
+    }
+}
+
 /// Execute command and return two pipes, stdin and stdout of the new process.
 pub fn exec_cmd(
     cmds: &Vec<String>,
@@ -29,7 +41,7 @@ pub fn exec_cmd(
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .spawn()
-        .map_err(|e| errors::SpawnError::Io(e))?;
+        .map_err(|e| errors::SpawnError::Command { name: cmds[0].to_string(), source: e })?;
     let first = &cmds[0];
     let child_stdin = child.stdin.ok_or(errors::SpawnError::StdinOpenFailed)?;
     let child_stdout = child.stdout.ok_or(errors::SpawnError::StdoutOpenFailed)?;
@@ -40,52 +52,109 @@ pub fn exec_cmd(
     ))
 }
 
-/// Execute single command and return the stdout of the command as String synchronously with
-/// placeholder replacement (-I option)
-pub fn exec_cmd_sync_replace(input: String, cmds: &Vec<String>, line_end: u8, chomp: bool, replace_str: &str) -> String {
-    debug!("thread: exec_cmd_sync: {:?}", &cmds);
-    // check each element of cmds. If it contains replace_str, replace it with input
-    let mut cmds_new = Vec::new();
-    for cmd in cmds {
-        if cmd.contains(replace_str) {
-            cmds_new.push(cmd.replace(replace_str, &input));
-        } else {
-            cmds_new.push(cmd.to_string());
+/// Whether the command is listed in TEIP_DENY_COMMANDS separated by commas or white spaces.
+/// The base name is compared, so /bin/rm is denied by rm.
+pub fn is_denied(cmd: &str) -> bool {
+    let name = Path::new(cmd).file_name().and_then(|n| n.to_str()).unwrap_or(cmd);
+    match env::var("TEIP_DENY_COMMANDS") {
+        Ok(list) => list
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .any(|d| !d.is_empty() && d == name),
+        Err(_) => false,
+    }
+}
+
+/// Commands in the command position of the shell script ( -e, --field-command ).
+/// e.g "rm -f x; echo $(date)" has rm, echo and date.
+/// The script is split by the control operators, so the commands built at run time are not found.
+pub fn shell_commands(script: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut quote = None;
+    let mut escaped = false;
+    for c in script.chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\\') => {}
+            (_, '\\') => escaped = true,
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, ';') | (None, '|') | (None, '&') | (None, '\n') | (None, '(') | (None, ')') | (_, '`') => {
+                segments.push(String::new());
+                continue;
+            }
+            _ => {}
         }
+        segments.last_mut().unwrap().push(c);
     }
+    segments
+        .iter()
+        .filter_map(|segment| {
+            let words = shell_words::split(segment)
+                .unwrap_or_else(|_| segment.split_whitespace().map(|w| w.to_string()).collect());
+            // Skip reserved words and variable assignments like "FOO=1 cmd"
+            words.into_iter().find(|w| {
+                !matches!(w.as_str(), "!" | "{" | "}" | "if" | "then" | "else" | "elif" | "fi" | "do" | "done" | "while" | "until" | "time" | "exec" | "command")
+                    && !w.split_once('=').is_some_and(|(name, _)| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+            })
+        })
+        .collect()
+}
+
+/// Replace the placeholder in each argument of the command with the value.
+/// -I and --solid-arg give the hole, and --stream-placeholder gives the name of the input file.
+pub fn replace_args(cmds: &[String], placeholder: &str, value: &str) -> Vec<String> {
+    cmds.iter().map(|cmd| cmd.replace(placeholder, value)).collect()
+}
+
+/// Execute single command and return the stdout of the command as String synchronously with
+/// placeholder replacement (-I option).
+/// If the command fails, SpawnError::Exit has the stdout as well.
+pub fn exec_cmd_sync_replace(input: String, cmds: &Vec<String>, line_end: u8, chomp: bool, replace_str: &str, stderr_on_stdout: bool) -> Result<String, errors::SpawnError> {
+    debug!("thread: exec_cmd_sync: {:?}", &cmds);
+    let cmds_new = replace_args(cmds, replace_str, &input);
     let child = Command::new(&cmds_new[0])
         .args(&cmds_new[1..])
         .stdout(Stdio::piped())
+        .stderr(sync_stderr(stderr_on_stdout))
         .spawn()
-        .expect("Failed to spawn child process");
-    let mut output = child
+        .map_err(|e| errors::SpawnError::Command { name: cmds_new[0].to_string(), source: e })?;
+    let output = child
         .wait_with_output()
-        .expect("Failed to read stdout")
-        .stdout;
+        .map_err(errors::SpawnError::Io)?;
+    let mut stdout = output.stdout;
+    // --keep-command-stderr-on-stdout option, empty unless it is given
+    stdout.extend_from_slice(&output.stderr);
     if !chomp {
         // Remove training new line.
         // In the vast majority of cases,
         // this new line is likely added by this function (see ADD NEW LINE)
-        if output.ends_with(&[line_end]) {
-            output.pop();
+        if stdout.ends_with(&[line_end]) {
+            stdout.pop();
         }
     }
-    String::from_utf8_lossy(&output).to_string()
+    let stdout = String::from_utf8_lossy(&stdout).to_string();
+    if !output.status.success() {
+        // The caller decides whether the output is used ( --on-error )
+        return Err(errors::SpawnError::Exit { name: cmds_new[0].to_string(), status: output.status, stdout });
+    }
+    Ok(stdout)
 }
 
 
 
-/// Execute single command and return the stdout of the command as String synchronously
-pub fn exec_cmd_sync(input: String, cmds: &Vec<String>, line_end: u8, chomp: bool) -> String {
+/// Execute single command and return the stdout of the command as String synchronously.
+/// If the command fails, SpawnError::Exit has the stdout as well.
+pub fn exec_cmd_sync(input: String, cmds: &Vec<String>, line_end: u8, chomp: bool, stderr_on_stdout: bool) -> Result<String, errors::SpawnError> {
     debug!("thread: exec_cmd_sync: {:?}", &cmds);
     let mut child = Command::new(&cmds[0])
         .args(&cmds[1..])
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
+        .stderr(sync_stderr(stderr_on_stdout))
         .spawn()
-        .expect("Failed to spawn child process");
+        .map_err(|e| errors::SpawnError::Command { name: cmds[0].to_string(), source: e })?;
     {
-        let stdin = child.stdin.as_mut().expect("Failed to open stdin");
+        let stdin = child.stdin.as_mut().ok_or(errors::SpawnError::StdinOpenFailed)?;
         let mut vec = Vec::new();
         if chomp {
             vec.extend_from_slice(input.as_bytes());
@@ -100,21 +169,54 @@ pub fn exec_cmd_sync(input: String, cmds: &Vec<String>, line_end: u8, chomp: boo
         }
         stdin
             .write_all(vec.as_slice())
-            .expect("Failed to write to stdin");
+            .map_err(errors::SpawnError::Io)?;
     }
-    let mut output = child
+    let output = child
         .wait_with_output()
-        .expect("Failed to read stdout")
-        .stdout;
+        .map_err(errors::SpawnError::Io)?;
+    let mut stdout = output.stdout;
+    // --keep-command-stderr-on-stdout option, empty unless it is given
+    stdout.extend_from_slice(&output.stderr);
     if !chomp {
         // Remove training new line.
         // In the vast majority of cases,
         // this new line is likely added by this function (see ADD NEW LINE)
-        if output.ends_with(&[line_end]) {
-            output.pop();
+        if stdout.ends_with(&[line_end]) {
+            stdout.pop();
         }
     }
-    String::from_utf8_lossy(&output).to_string()
+    let stdout = String::from_utf8_lossy(&stdout).to_string();
+    if !output.status.success() {
+        // The caller decides whether the output is used ( --on-error )
+        return Err(errors::SpawnError::Exit { name: cmds[0].to_string(), status: output.status, stdout });
+    }
+    Ok(stdout)
+}
+
+/// Execute a single string command (pipeline) with the input as stdin, and return whether it exits with 0.
+/// The stdout of the command is discarded ( --field-command ).
+pub fn exec_predicate(command: &str, input: &str) -> Result<bool, errors::SpawnError> {
+    debug!("thread: exec_predicate: {:?}", command);
+    cfg_if::cfg_if! {
+        if #[cfg(windows)] {
+            let (shell, opt) = ("cmd", "/C");
+        } else {
+            let (shell, opt) = ("sh", "-c");
+        }
+    }
+    let mut child = Command::new(shell)
+        .args([opt, command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| errors::SpawnError::Command { name: shell.to_string(), source: e })?;
+    {
+        let stdin = child.stdin.as_mut().ok_or(errors::SpawnError::StdinOpenFailed)?;
+        // Ignore error because the command may not read standard input
+        let _ = stdin.write_all(input.as_bytes());
+    }
+    let status = child.wait().map_err(errors::SpawnError::Io)?;
+    Ok(status.success())
 }
 
 /// Spawn process with a single string command (pipeline) and keep getting input from mpsc::Receiver as stdin.
@@ -173,7 +275,7 @@ pub fn tee(
             let mut stdin = BufReader::new(input);
             loop {
                 let mut buf = Vec::with_capacity(DEFAULT_CAP);
-                match stdin.read_until(line_end, &mut buf) {
+                match stringutils::read_line(&mut stdin, line_end, &mut buf) {
                     Ok(0) => {
                         // Finish to read entire input, discard channels
                         drop(tx1);
@@ -186,8 +288,9 @@ pub fn tee(
                         let _ = tx1.send(buf.clone());
                         let _ = tx2.send(buf.clone());
                     },
-                    Err(_) => {
+                    Err(e) => {
                         debug!("tee_chain: Got error while loading from stdin");
+                        errors::error_exit(&e);
                     },
                 };
             }
@@ -196,9 +299,11 @@ pub fn tee(
 }
 
 /// Extract numbers from noisey strings.
+/// If delimiter is given, each line is split by it and a number is extracted from each token ( --mask-delimiter ).
 pub fn clean_numbers (
     mut input: BufReader<Box<dyn Read + Send>>,
-    line_end: u8
+    line_end: u8,
+    delimiter: Option<String>,
 ) -> (Receiver<u64>, JoinHandle<()>) {
     let (tx, rx) = mpsc::channel();
     let handler = thread::spawn(move || {
@@ -211,17 +316,17 @@ pub fn clean_numbers (
                 Err(_) => break,
             };
             let line = String::from_utf8_lossy(&buf).to_string();
-            match stringutils::extract_number(line) {
-                Some(i) => {
-                    match tx.send(i) {
-                        Ok(_) => (),
-                        Err(_) => {
-                            break;
-                        },
-                    }
-                },
-                None => {},
+            let tokens: Vec<String> = match delimiter {
+                Some(ref d) => line.split(d.as_str()).map(|t| t.to_string()).collect(),
+                None => vec![line],
             };
+            for token in tokens {
+                if let Some(i) = stringutils::extract_number(token) {
+                    if tx.send(i).is_err() {
+                        break;
+                    }
+                }
+            }
         }
         drop(tx);
         debug!("clean_numbers: thread: end");
@@ -229,10 +334,50 @@ pub fn clean_numbers (
     return (rx, handler)
 }
 
+/// Convert a bitmap to line numbers ( --mask-bitmap ).
+/// n-th line of the bitmap starting with '1' means the n-th line is selected.
+pub fn bitmap_numbers (
+    mut input: BufReader<Box<dyn Read + Send>>,
+    line_end: u8,
+) -> (Receiver<u64>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let handler = thread::spawn(move || {
+        debug!("bitmap_numbers: thread: start");
+        let mut nr: u64 = 0;
+        loop {
+            let mut buf = Vec::with_capacity(DEFAULT_CAP);
+            match input.read_until(line_end, &mut buf) {
+                Ok(0) => break,
+                Ok(_) => {},
+                Err(_) => break,
+            };
+            nr += 1;
+            if buf.first() == Some(&b'1') && tx.send(nr).is_err() {
+                break;
+            }
+        }
+        drop(tx);
+        debug!("bitmap_numbers: thread: end");
+    });
+    (rx, handler)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     #[test]
+    fn test_shell_commands() {
+        assert_eq!(shell_commands("rm -f x; echo 1"), vec!["rm", "echo"]);
+        assert_eq!(shell_commands("grep -n a | LC_ALL=C sort && (cd /tmp && ls) || echo $(date)"), vec!["grep", "sort", "cd", "ls", "echo", "date"]);
+        assert_eq!(shell_commands("if true; then /bin/rm x; fi"), vec!["true", "/bin/rm"]);
+        assert_eq!(shell_commands("echo 'a; rm' \"b|rm\" c\\;rm"), vec!["echo"]);
+    }
+    #[test]
+    fn test_replace_args() {
+        let cmds: Vec<String> = vec!["echo".to_string(), "[{}]".to_string(), "{}{}".to_string(), "x".to_string()];
+        assert_eq!(replace_args(&cmds, "{}", "AB"), vec!["echo", "[AB]", "ABAB", "x"]);
+    }
+    #[test]
     fn test_tee() {
         let input = b"AAA\nBBB\nCCC\n";
         let expected = [b"AAA\n", b"BBB\n", b"CCC\n"];
@@ -307,7 +452,7 @@ mod test {
         let input = b"abcdef\n".to_vec();
         tx.send(input).unwrap();
         drop(tx);
-        let (numbers, _) = clean_numbers(rx_messy_numbers, b'\n');
+        let (numbers, _) = clean_numbers(rx_messy_numbers, b'\n', None);
         match numbers.recv() {
             Ok(n) => {
                 assert_eq!(n, 3);
@@ -317,4 +462,18 @@ mod test {
             },
         };
     }
+
+    #[test]
+    fn test_clean_numbers_delimiter() {
+        let input: Box<dyn Read + Send> = Box::new(&b"1, 3,x\n5\n"[..]);
+        let (numbers, _) = clean_numbers(BufReader::new(input), b'\n', Some(",".to_string()));
+        assert_eq!(numbers.iter().collect::<Vec<u64>>(), vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn test_bitmap_numbers() {
+        let input: Box<dyn Read + Send> = Box::new(&b"0\n1\n0\n1\n"[..]);
+        let (numbers, _) = bitmap_numbers(BufReader::new(input), b'\n');
+        assert_eq!(numbers.iter().collect::<Vec<u64>>(), vec![2, 4]);
+    }
 }
@@ -1,6 +1,39 @@
 use regex::Regex;
+use std::io::{BufRead, Read};
 
-pub fn trim_eol(buf: &mut Vec<u8>) -> String {
+// Read a line of the input into buf
+pub fn read_line<R: BufRead>(reader: &mut R, line_end: u8, buf: &mut Vec<u8>) -> Result<usize, String> {
+    reader.read_until(line_end, buf).map_err(|e| e.to_string())
+}
+
+// Read the rest of the input into buf ( --slurp )
+pub fn read_all<R: BufRead>(reader: &mut R, line_end: u8, buf: &mut Vec<u8>) -> Result<usize, String> {
+    let mut total = 0;
+    loop {
+        match read_line(reader, line_end, buf)? {
+            0 => return Ok(total),
+            n => total += n,
+        }
+    }
+}
+
+// Read a record of n bytes into buf ( --record-bytes ).
+// The last record may be shorter than n bytes.
+pub fn read_record<R: BufRead>(reader: &mut R, n: usize, buf: &mut Vec<u8>) -> Result<usize, String> {
+    reader.take(n as u64).read_to_end(buf).map_err(|e| e.to_string())
+}
+
+// Remove the line terminator at the end of buf and return it.
+// If line_end is NUL (-z), only NUL is removed because CR and LF are a part of the record.
+pub fn trim_eol(buf: &mut Vec<u8>, line_end: u8) -> String {
+    // -z and --input-record-sep
+    if line_end != b'\n' {
+        if buf.ends_with(&[line_end]) {
+            buf.pop();
+            return (line_end as char).to_string();
+        }
+        return "".to_string();
+    }
     if buf.ends_with(&[b'\r', b'\n']) {
         buf.pop();
         buf.pop();
@@ -10,13 +43,82 @@ pub fn trim_eol(buf: &mut Vec<u8>) -> String {
         buf.pop();
         return "\n".to_string();
     }
-    if buf.ends_with(&[b'\0']) {
-        buf.pop();
-        return "\0".to_string();
-    }
     "".to_string()
 }
 
+// Replace every line terminator (CRLF or LF) in the string with given eol
+pub fn normalize_eol(s: &str, eol: &str) -> String {
+    s.replace("\r\n", "\n").replace('\n', eol)
+}
+
+// Interpret escape sequences (\t, \n, \r, \0, \xNN and \\) in the string.
+// Unknown sequences are left as they are.
+pub fn unescape(s: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some('\\') => result.push('\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(n) if hex.len() == 2 && n.is_ascii() => result.push(n as char),
+                    _ => return Err(format!("Invalid escape sequence '\\x{}': \\xNN must be 00-7F", hex)),
+                }
+            }
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    Ok(result)
+}
+
+// Replace control characters in the string with \xNN (or \u{NN} if not ASCII),
+// except for TAB and the line terminator.
+pub fn escape_control(s: &str, line_end: u8) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        if !c.is_control() || c == '\t' || c == line_end as char {
+            result.push(c);
+        } else if c.is_ascii() {
+            result.push_str(&format!("\\x{:02X}", c as u8));
+        } else {
+            result.push_str(&c.escape_unicode().to_string());
+        }
+    }
+    result
+}
+
+// Encode the string as the contents between the double quotes of a JSON string ( --jsonl-key )
+pub fn escape_json(s: &str) -> String {
+    let quoted = serde_json::to_string(s).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+// Delimiter of -d which appears the most in the line among , TAB ; and | ( --auto-delimiter ).
+// The earlier one wins a tie, and , is used if none of them appears.
+pub fn detect_delimiter(line: &[u8]) -> &'static str {
+    let mut best = (",", 0);
+    for &d in &[",", "\t", ";", "|"] {
+        let n = line.iter().filter(|&&b| b == d.as_bytes()[0]).count();
+        if n > best.1 {
+            best = (d, n);
+        }
+    }
+    best.0
+}
+
 // Extract number from string line
 pub fn extract_number(line: String) -> Option<u64> {
     lazy_static! {
@@ -31,15 +133,172 @@ pub fn extract_number(line: String) -> Option<u64> {
     None
 }
 
+// Replace each tab with spaces up to the next tab stop of every n columns like expand(1) ( --expand-tabs ).
+// With --display-columns, a wide character occupies two columns.
+pub fn expand_tabs(s: &str, n: usize, display_columns: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut column = 0;
+    for c in s.chars() {
+        match c {
+            '\t' => {
+                let width = n - column % n;
+                out.extend(std::iter::repeat(' ').take(width));
+                column += width;
+                continue;
+            }
+            '\n' => column = 0,
+            _ if display_columns => column += unicode_width::UnicodeWidthChar::width(c).unwrap_or(0),
+            _ => column += 1,
+        }
+        out.push(c);
+    }
+    out
+}
+
+// POSIX character classes available in both the regex crate and Oniguruma
+const POSIX_CLASSES: [&str; 14] = [
+    "alnum", "alpha", "ascii", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space", "upper", "word",
+    "xdigit",
+];
+
+// Check POSIX character classes like [[:space:]] in the regular expression.
+// The regex crate silently takes an unknown class or a class out of brackets for a set of characters,
+// so they are rejected regardless of the engine.
+pub fn check_posix_classes(pattern: &str) -> Result<(), String> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut depth = 0; // nest of brackets
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => {
+                // [:name:] or [:^name:]
+                let start = if chars.get(i + 2) == Some(&'^') { i + 3 } else { i + 2 };
+                let end = (start..chars.len()).find(|&j| !chars[j].is_ascii_alphabetic()).unwrap_or(chars.len());
+                if chars.get(i + 1) == Some(&':') && end > start && chars[end..].starts_with(&[':', ']']) {
+                    let name: String = chars[start..end].iter().collect();
+                    if depth == 0 {
+                        return Err(format!(
+                            "POSIX character class [:{0}:] must be in brackets like [[:{0}:]]",
+                            name
+                        ));
+                    }
+                    if !POSIX_CLASSES.contains(&name.as_str()) {
+                        return Err(format!(
+                            "POSIX character class [:{}:] is not supported. Available classes are {}",
+                            name,
+                            POSIX_CLASSES.join(", ")
+                        ));
+                    }
+                    i = end + 1;
+                } else {
+                    depth += 1;
+                    // ] right after [ or [^ is a literal
+                    if chars.get(i + 1) == Some(&'^') {
+                        i += 1;
+                    }
+                    if chars.get(i + 1) == Some(&']') {
+                        i += 1;
+                    }
+                }
+            }
+            ']' if depth > 0 => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     #[test]
+    fn test_expand_tabs() {
+        assert_eq!(expand_tabs("a\tbc\td", 4, false), "a   bc  d");
+        assert_eq!(expand_tabs("\tabcd\te", 4, false), "    abcd    e");
+        assert_eq!(expand_tabs("ab\n\tc", 4, false), "ab\n    c");
+        assert_eq!(expand_tabs("名\tc", 4, false), "名   c");
+        assert_eq!(expand_tabs("名\tc", 4, true), "名  c");
+    }
+    #[test]
+    fn test_check_posix_classes() {
+        assert!(check_posix_classes("[[:space:]]+").is_ok());
+        assert!(check_posix_classes("[^[:alpha:][:digit:]_]").is_ok());
+        assert!(check_posix_classes("[[:^space:]]").is_ok());
+        assert!(check_posix_classes("[]:space:]").is_ok());
+        assert!(check_posix_classes("\\[:space:]").is_ok());
+        assert!(check_posix_classes("[-: ]").is_ok());
+        assert_eq!(
+            check_posix_classes("a[:space:]b").unwrap_err(),
+            "POSIX character class [:space:] must be in brackets like [[:space:]]"
+        );
+        assert!(check_posix_classes("[[:spaces:]]").unwrap_err().starts_with("POSIX character class [:spaces:] is not supported"));
+    }
+    #[test]
     fn test_trim_eol() {
         let mut buf = vec![b'\x61', b'\x62', b'\n'];
-        let end = trim_eol(&mut buf);
+        let end = trim_eol(&mut buf, b'\n');
         assert_eq!(String::from_utf8_lossy(&buf).to_string(), "ab");
         assert_eq!(end, "\n");
+        let mut buf = b"ab\r\n".to_vec();
+        let end = trim_eol(&mut buf, b'\n');
+        assert_eq!(String::from_utf8_lossy(&buf).to_string(), "ab");
+        assert_eq!(end, "\r\n");
+        let mut buf = b"ab\0".to_vec();
+        let end = trim_eol(&mut buf, b'\n');
+        assert_eq!(String::from_utf8_lossy(&buf).to_string(), "ab\0");
+        assert_eq!(end, "");
+    }
+    #[test]
+    fn test_trim_eol_null() {
+        let mut buf = b"ab\n".to_vec();
+        let end = trim_eol(&mut buf, b'\0');
+        assert_eq!(String::from_utf8_lossy(&buf).to_string(), "ab\n");
+        assert_eq!(end, "");
+        let mut buf = b"a\r\nb\r\n\0".to_vec();
+        let end = trim_eol(&mut buf, b'\0');
+        assert_eq!(String::from_utf8_lossy(&buf).to_string(), "a\r\nb\r\n");
+        assert_eq!(end, "\0");
+    }
+    #[test]
+    fn test_normalize_eol() {
+        assert_eq!(normalize_eol("a\nb\r\nc", "\r\n"), "a\r\nb\r\nc");
+        assert_eq!(normalize_eol("a\nb\r\nc", "\n"), "a\nb\nc");
+        assert_eq!(normalize_eol("abc", "\r\n"), "abc");
+    }
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("\\t").unwrap(), "\t");
+        assert_eq!(unescape("a\\nb\\r\\0").unwrap(), "a\nb\r\0");
+        assert_eq!(unescape("\\x2C\\x7c").unwrap(), ",|");
+        assert_eq!(unescape("\\\\t").unwrap(), "\\t");
+        assert_eq!(unescape("\\d\\").unwrap(), "\\d\\");
+        assert!(unescape("\\xZZ").is_err());
+        assert!(unescape("\\x8").is_err());
+        assert!(unescape("\\xFF").is_err());
+    }
+
+    #[test]
+    fn test_escape_control() {
+        assert_eq!(escape_control("a\x01b\x1b[31m", b'\n'), "a\\x01b\\x1B[31m");
+        assert_eq!(escape_control("a\tb\r\n", b'\n'), "a\tb\\x0D\n");
+        assert_eq!(escape_control("a\n\0", b'\0'), "a\\x0A\0");
+        assert_eq!(escape_control("\u{85}あ\x7f", b'\n'), "\\u{85}あ\\x7F");
+    }
+
+    #[test]
+    fn test_escape_json() {
+        assert_eq!(escape_json("a\"b\\c"), "a\\\"b\\\\c");
+        assert_eq!(escape_json("\n\u{1}é"), "\\n\\u0001é");
+    }
+    #[test]
+    fn test_detect_delimiter() {
+        assert_eq!(detect_delimiter(b"a,b,c\n"), ",");
+        assert_eq!(detect_delimiter(b"a\tb,c\td\n"), "\t");
+        assert_eq!(detect_delimiter(b"a;b|c\n"), ";");
+        assert_eq!(detect_delimiter(b"a|b|c;d\n"), "|");
+        assert_eq!(detect_delimiter(b"abc\n"), ",");
     }
     #[test]
     fn test_extract_number() {
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::fs;
+
+/// Built-in transforms applied to each hole in-process instead of the command
+/// ( --replace-map, --strip-prefix, --strip-suffix, --add-prefix, --add-suffix )
+#[derive(Default, Debug)]
+pub struct Transform {
+    pub replace_map: Option<HashMap<String, String>>,
+    pub strip_prefix: Option<String>,
+    pub strip_suffix: Option<String>,
+    pub add_prefix: Option<String>,
+    pub add_suffix: Option<String>,
+}
+
+impl Transform {
+    // Whether no transform is given
+    pub fn is_empty(&self) -> bool {
+        self.replace_map.is_none()
+            && self.strip_prefix.is_none()
+            && self.strip_suffix.is_none()
+            && self.add_prefix.is_none()
+            && self.add_suffix.is_none()
+    }
+
+    // Read the table of "from<TAB>to" lines for --replace-map. Empty lines are ignored.
+    pub fn read_map(path: &str) -> Result<HashMap<String, String>, String> {
+        let table = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        parse_map(&table).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Replace the entire hole by the map, strip the affixes at most once, then add the affixes
+    pub fn apply(&self, s: &str) -> String {
+        let mut s = match self.replace_map {
+            Some(ref map) => map.get(s).map(|v| v.as_str()).unwrap_or(s),
+            None => s,
+        };
+        if let Some(ref p) = self.strip_prefix {
+            s = s.strip_prefix(p.as_str()).unwrap_or(s);
+        }
+        if let Some(ref p) = self.strip_suffix {
+            s = s.strip_suffix(p.as_str()).unwrap_or(s);
+        }
+        let prefix = self.add_prefix.as_deref().unwrap_or("");
+        let suffix = self.add_suffix.as_deref().unwrap_or("");
+        format!("{}{}{}", prefix, s, suffix)
+    }
+}
+
+fn parse_map(table: &str) -> Result<HashMap<String, String>, String> {
+    let mut map = HashMap::new();
+    for (i, line) in table.lines().enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        match line.split_once('\t') {
+            // The first one wins if the same key appears again
+            Some((from, to)) => {
+                map.entry(from.to_string()).or_insert_with(|| to.to_string());
+            }
+            None => return Err(format!("line {}: TAB is expected between the strings", i + 1)),
+        }
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let t = Transform {
+            strip_prefix: Some("http://".to_string()),
+            strip_suffix: Some("/".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(t.apply("http://example.com/"), "example.com");
+        assert_eq!(t.apply("http://http://a//"), "http://a/");
+        assert_eq!(t.apply("https://a"), "https://a");
+        // Affixes are added after stripping
+        let t = Transform {
+            strip_prefix: Some("a".to_string()),
+            add_prefix: Some("a".to_string()),
+            add_suffix: Some(")".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(t.apply("abc"), "abc)");
+        assert_eq!(t.apply("bc"), "abc)");
+        assert!(Transform::default().is_empty());
+    }
+
+    #[test]
+    fn test_replace_map() {
+        let map = parse_map("a\tA\r\n\nbb\tB\tB\na\tX\n").unwrap();
+        assert_eq!(map.get("a").unwrap(), "A");
+        assert_eq!(map.get("bb").unwrap(), "B\tB");
+        assert_eq!(parse_map("a\tA\nb\n").unwrap_err(), "line 2: TAB is expected between the strings");
+        // The map is applied before the affixes
+        let t = Transform {
+            replace_map: Some(map),
+            add_prefix: Some("<".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(t.apply("a"), "<A");
+        assert_eq!(t.apply("c"), "<c");
+        assert_eq!(t.apply("aa"), "<aa");
+    }
+}
@@ -1,4 +1,6 @@
 mod cmdtest {
+    use predicates::prelude::*;
+
     cfg_if::cfg_if! {
         if #[cfg(windows)] {
             static SED_CMD: &str = "C:\\Program Files\\Git\\usr\\bin\\sed.exe";
@@ -35,6 +37,46 @@ mod cmdtest {
             .code(1);
     }
 
+    #[test]
+    #[cfg(feature = "oniguruma")]
+    fn test_features_onig() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--features"])
+            .assert()
+            .stdout(predicate::str::contains("oniguruma\n"))
+            .code(0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "oniguruma"))]
+    fn test_features_no_onig() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--features"])
+            .assert()
+            .stdout(predicate::str::contains("oniguruma").not())
+            .code(0);
+    }
+
+    #[test]
+    fn test_command_not_found() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--", "teip_no_such_command"])
+            .write_stdin("AAA BBB\n")
+            .assert()
+            .stderr(predicate::str::contains("failed to run 'teip_no_such_command'"))
+            .code(1);
+    }
+
+    #[test]
+    fn test_solid_command_not_found() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-f", "1", "--", "teip_no_such_command"])
+            .write_stdin("AAA BBB\n")
+            .assert()
+            .stderr(predicate::str::contains("failed to run 'teip_no_such_command'"))
+            .code(1);
+    }
+
     #[test]
     fn test_exoffload_grep() {
         let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
@@ -123,6 +165,65 @@ mod cmdtest {
             .stdout("111\n@22\n333\n@44\n@55\n666\n");
     }
 
+    #[test]
+    fn test_line_head() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--head", "2", SED_CMD, "s/./@/"])
+            .write_stdin("111\n222\n333\n444\n555\n")
+            .assert()
+            .stdout("@11\n@22\n333\n444\n555\n");
+    }
+
+    #[test]
+    fn test_line_head_invert() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-v", "--head", "2", SED_CMD, "s/./@/"])
+            .write_stdin("111\n222\n333\n444\n555\n")
+            .assert()
+            .stdout("111\n222\n@33\n@44\n@55\n");
+    }
+
+    #[test]
+    fn test_line_tail() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--tail", "2", SED_CMD, "s/./@/"])
+            .write_stdin("111\n222\n333\n444\n555\n")
+            .assert()
+            .stdout("111\n222\n333\n@44\n@55\n");
+    }
+
+    #[test]
+    fn test_line_tail_huge() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--tail", "18446744073709551615", SED_CMD, "s/./@/"])
+            .write_stdin("111\n222\n")
+            .assert()
+            .stdout("@11\n@22\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--tail", "1000000000000", SED_CMD, "s/./@/"])
+            .write_stdin("111\n")
+            .assert()
+            .stdout("@11\n");
+    }
+
+    #[test]
+    fn test_line_tail_invert() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-v", "--tail", "2", SED_CMD, "s/./@/"])
+            .write_stdin("111\n222\n333\n444\n555\n")
+            .assert()
+            .stdout("@11\n@22\n@33\n444\n555\n");
+    }
+
+    #[test]
+    fn test_solid_line_tail() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "--tail", "2", SED_CMD, "s/./@/"])
+            .write_stdin("111\n222\n333")
+            .assert()
+            .stdout("111\n@22\n@33");
+    }
+
     #[test]
     fn test_regex_line() {
         let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
@@ -132,6 +233,36 @@ mod cmdtest {
             .stdout("@BC\nDFE\n@CC\n@CA\n");
     }
 
+    #[test]
+    fn test_regex_size_limit() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--regex-size-limit", "16", "-g", "a{100}", SED_CMD, "s/./@/"])
+            .write_stdin("aaa\n")
+            .assert()
+            .stderr(predicate::str::contains("'a{100}' exceeds the size limit"))
+            .stderr(predicate::str::contains("--regex-size-limit"))
+            .code(1);
+    }
+
+    #[test]
+    fn test_regex_size_limit_enough() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--regex-size-limit", "1000000", "-og", "a{2}", SED_CMD, "s/./@/"])
+            .write_stdin("aaa\n")
+            .assert()
+            .stdout("@aa\n");
+    }
+
+    #[test]
+    fn test_regex_invalid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "a(?=b)", SED_CMD, "s/./@/"])
+            .write_stdin("ab\n")
+            .assert()
+            .stderr(predicate::str::contains("Invalid regular expression 'a(?=b)'"))
+            .code(1);
+    }
+
     #[test]
     fn test_regex_only() {
         let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
@@ -188,6 +319,33 @@ mod cmdtest {
             .stdout("AAAAAA\nAAAAAA\n");
     }
 
+    #[test]
+    fn test_regex_only_nth() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d", "--nth", "2,4", SED_CMD, "s/./@/"])
+            .write_stdin("a1b2\nc3d4\ne5f6\n")
+            .assert()
+            .stdout("a1b@\nc3d@\ne5f6\n");
+    }
+
+    #[test]
+    fn test_regex_only_nth_open() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d", "--nth", "2-", SED_CMD, "s/./@/"])
+            .write_stdin("a1b2\nc3d4\n")
+            .assert()
+            .stdout("a1b@\nc@d@\n");
+    }
+
+    #[test]
+    fn test_solid_regex_only_nth() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-og", "\\d", "--nth", "2,4", SED_CMD, "s/./@/"])
+            .write_stdin("a1b2\nc3d4\ne5f6\n")
+            .assert()
+            .stdout("a1b@\nc3d@\ne5f6\n");
+    }
+
     #[test]
     fn test_solid_line() {
         let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
@@ -287,6 +445,21 @@ mod cmdtest {
             .stdout("ABC@@@DEF456\n");
     }
 
+    #[test]
+    #[cfg(feature = "oniguruma")]
+    fn test_onig_posix_classes() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-Gog", "[[:space:]]+", SED_CMD, "s/./@/g"])
+            .write_stdin("a  b\tc\n")
+            .assert()
+            .stdout("a@@b@c\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-G", "-D", "[[:space:]]+", "-f", "2", SED_CMD, "s/./@/g"])
+            .write_stdin("a  b\tc\n")
+            .assert()
+            .stdout("a  @\tc\n");
+    }
+
     #[test]
     #[cfg(feature = "oniguruma")]
     fn test_onig_invert() {
@@ -849,127 +1022,2358 @@ mod cmdtest {
             .stdout("AAA111BBB\nCCC222DDD\nEEE333FFF");
     }
 
-    // test for multi-byte characters, executed on UNIX environment (non Windows)
-    cfg_if::cfg_if! {
-        if #[cfg(not(windows))] {
-            #[test]
-            fn test_csv_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                // test for UTF-8 as well to test the CSV parser
-                // Do not use "sed" here because it does not support UTF-8 on Windows environment on GitHub.
-                cmd.args(&["--csv", "-f", "2", "--", SED_CMD, "s/./@/g"])
-                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと\"\n")
-                    .assert()
-                    .stdout("名前,@@,ノート\n１レコード目,@@@@\n@@@,かきく\n２レコード目,@@@@\n@@@,\"たちつ\nてと\"\n");
-            }
+    #[test]
+    fn test_solid_no_chomp() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "--no-chomp", "-o", "-g", "\\d+", "--", TR_CMD, "\\n", "@"])
+            .write_stdin("AAA111BBB\nCCC222DDD\nEEE333FFF")
+            .assert()
+            .stdout("AAA111@BBB\nCCC222@DDD\nEEE333@FFF");
+    }
 
-            #[test]
-            fn test_csv_end_nolf_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-f", "3", "--", SED_CMD, "s/./@/g"])
-                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと\"")
-                    .assert()
-                    .stdout("名前,作者,@@@\n１レコード目,\"あいう\nえお\",@@@\n２レコード目,\"さしす\nせそ\",@@@@\n@@@");
-            }
+    #[test]
+    fn test_solid_chomp_override() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "--chomp", "--no-chomp", "-o", "-g", "\\d+", "--", TR_CMD, "\\n", "@"])
+            .write_stdin("AAA111BBB\n")
+            .assert()
+            .stdout("AAA111@BBB\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "--no-chomp", "--chomp", "-o", "-g", "\\d+", "--", TR_CMD, "\\n", "@"])
+            .write_stdin("AAA111BBB\n")
+            .assert()
+            .stdout("AAA111BBB\n");
+    }
 
-            #[test]
-            fn test_csv_non_delim_comma_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-f", "1", "--", SED_CMD, "s/./@/g"])
-                    .write_stdin("\"名,前\",作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n\"２レコード,目\",\"さしす\nせそ\",\"たちつ\nてと\"")
-                    .assert()
-                    .stdout("@@@@@,作者,ノート\n@@@@@@,\"あいう\nえお\",かきく\n@@@@@@@@@,\"さしす\nせそ\",\"たちつ\nてと\"");
-            }
+    #[test]
+    fn test_solid_multiline_crlf() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-g", "A", "--", TR_CMD, " ", "\\n"])
+            .write_stdin("A B\r\nC D\r\n")
+            .assert()
+            .stdout("A\nB\r\nC D\r\n");
+    }
 
-            #[test]
-            fn test_csv_double_quotation_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-f", "2", "--", SED_CMD, "s/./@/g"])
-                    .write_stdin("名前,\"作\"\"者\",ノート\n１レコード目,\"あ\"\"いう\"\"\nえお\",かきく\n２レコード目,\"\"\"さしす\n\"\"せそ\",\"たちつ\nてと\"\n")
-                    .assert()
-                    .stdout("名前,@@@@@@,ノート\n１レコード目,@@@@@@@@\n@@@,かきく\n２レコード目,@@@@@@\n@@@@@,\"たちつ\nてと\"\n");
-            }
+    #[test]
+    fn test_solid_normalize_eol_crlf() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-g", "A", "--normalize-eol", "crlf", "--", TR_CMD, " ", "\\n"])
+            .write_stdin("A B\r\nC D\r\n")
+            .assert()
+            .stdout("A\r\nB\r\nC D\r\n");
+    }
 
-            #[test]
-            fn test_solid_csv_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-s", "-f", "2", "--", SED_CMD, "s/./@/g"])
-                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと\"\n")
-                    .assert()
-                    .stdout("名前,@@,ノート\n１レコード目,@@@@\n@@@,かきく\n２レコード目,@@@@\n@@@,\"たちつ\nてと\"\n");
-            }
-            #[test]
-            fn test_solid_csv_end_nolf_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-s", "-f", "3", "--", SED_CMD, "s/./@/g"])
-                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと\"")
-                    .assert()
-                    .stdout("名前,作者,@@@\n１レコード目,\"あいう\nえお\",@@@\n２レコード目,\"さしす\nせそ\",@@@@\n@@@");
-            }
+    #[test]
+    fn test_solid_normalize_eol_invalid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-g", "A", "--normalize-eol", "cr", "--", TR_CMD, " ", "\\n"])
+            .write_stdin("A B\r\n")
+            .assert()
+            .code(1);
+    }
 
-            #[test]
-            fn test_solid_csv_non_delim_comma_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-s", "-f", "1", "--", SED_CMD, "s/./@/g"])
-                    .write_stdin("\"名,前\",作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n\"２レコード,目\",\"さしす\nせそ\",\"たちつ\nてと\"")
-                    .assert()
-                    .stdout("@@@@@,作者,ノート\n@@@@@@,\"あいう\nえお\",かきく\n@@@@@@@@@,\"さしす\nせそ\",\"たちつ\nてと\"");
-            }
+    #[test]
+    fn test_highlight_flags() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "A", "--highlight-start", "<", "--highlight-end", ">"])
+            .env_remove("TEIP_HIGHLIGHT")
+            .write_stdin("ABAB\n")
+            .assert()
+            .stdout("<A>B<A>B\n");
+    }
 
-            #[test]
-            fn test_solid_csv_double_quotation_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-s", "-f", "2", "--", SED_CMD, "s/./@/g"])
-                    .write_stdin("名前,\"作\"\"者\",ノート\n１レコード目,\"あ\"\"いう\"\"\nえお\",かきく\n２レコード目,\"\"\"さしす\n\"\"せそ\",\"たちつ\nてと\"\n")
-                    .assert()
-                    .stdout("名前,@@@@@@,ノート\n１レコード目,@@@@@@@@\n@@@,かきく\n２レコード目,@@@@@@\n@@@@@,\"たちつ\nてと\"\n");
-            }
+    #[test]
+    fn test_highlight_start_end_env() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "A"])
+            .env_remove("TEIP_HIGHLIGHT")
+            .env("TEIP_HIGHLIGHT_START", "<<")
+            .env("TEIP_HIGHLIGHT_END", ">>")
+            .write_stdin("ABAB\n")
+            .assert()
+            .stdout("<<A>>B<<A>>B\n");
+    }
 
-            #[test]
-            fn test_solid_csv_crlf_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-s", "-f", "3", "--", TR_CMD, "\\r", "@"])
-                    .write_stdin("AAA,BBB,CCC\r\n1AAA,\"1BBB\r\nBB\",1CCC\r\n2AAA,2BBB,\"2CCC\r\nCC\"\r\n")
-                    .assert()
-                         .stdout("AAA,BBB,CCC\r\n1AAA,\"1BBB\r\nBB\",1CCC\r\n2AAA,2BBB,\"2CCC@\nCC\"\r\n");
-            }
+    #[test]
+    fn test_highlight_env_precedence() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "A", "--highlight-end", ")"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .env("TEIP_HIGHLIGHT_START", "<<")
+            .env("TEIP_HIGHLIGHT_END", ">>")
+            .write_stdin("ABAB\n")
+            .assert()
+            .stdout("[A)B[A)B\n");
+    }
 
-            #[test]
-            fn test_solid_csv_remove_newlines_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-s", "-f", "2", "--", TR_CMD, "-d", "\\n"])
-                    .write_stdin("名前,作者,ノート\n１レコード目,\"あい\nうえお\",かきく\n２レコード目,\"\"\"さしす\nせそ\",\"たちつ\nてと\"\n")
-                    .assert()
-                    .stdout("名前,作者,ノート\n１レコード目,\"あいうえお\",かきく\n２レコード目,\"\"\"さしすせそ\",\"たちつ\nてと\"\n");
-            }
+    #[test]
+    fn test_highlight_env_invalid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "A"])
+            .env("TEIP_HIGHLIGHT", "[{}]{}")
+            .write_stdin("ABAB\n")
+            .assert()
+            .code(1);
+    }
 
-            #[test]
-            fn test_solid_csv_chomp_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-s", "--chomp", "-f", "2,3", "--", TR_CMD, "\\n", "@"])
-                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと,\n")
-                    .assert()
-                    .stdout("名前,作者,ノート\n１レコード目,\"あいう@えお\",かきく\n２レコード目,\"さしす@せそ\",\"たちつ@てと,");
-            }
+    #[test]
+    fn test_regex_field() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "a", "-f", "2", "--", SED_CMD, "s/./_/"])
+            .write_stdin("a 1 2\nb 3 4\n")
+            .assert()
+            .stdout("a _ 2\nb 3 4\n");
+    }
 
-            #[test]
-            fn test_solid_csv_nochomp_utf8() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                cmd.args(&["--csv", "-s", "-f", "2,3", "--", TR_CMD, "\\n", "@"])
-                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと,\n")
-                    .assert()
-                    .stdout("名前,作者@,ノート@\n１レコード目,\"あいう@えお\"@,かきく@\n２レコード目,\"さしす@せそ\"@,\"たちつ@てと,@@");
-            }
+    #[test]
+    fn test_regex_field_invert_lines() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-v", "-g", "a", "-f", "2", "--", SED_CMD, "s/./_/"])
+            .write_stdin("a 1 2\nb 3 4\n")
+            .assert()
+            .stdout("a 1 2\nb _ 4\n");
+    }
 
-            #[test]
-            fn test_solid_replace() {
-                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
-                // separaate arguments -I @
-                 cmd.args(&["-I", "@", "-s", "-f", "2", "--", _ECHO_CMD, ">>@<<"])
-                     .write_stdin("AAA BBB CCC\nDDD EEE FFF\n")
-                     .assert()
-                     .stdout("AAA >>BBB<< CCC\nDDD >>EEE<< FFF\n");
-            }
+    #[test]
+    fn test_regex_field_invert_fields() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "a", "-f", "2", "--invert-fields", "--", SED_CMD, "s/./_/"])
+            .write_stdin("a 1 2\nb 3 4\n")
+            .assert()
+            .stdout("_ 1 _\nb 3 4\n");
+    }
+
+    #[test]
+    fn test_invert_fields_without_regex() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--invert-fields", "--", SED_CMD, "s/./_/"])
+            .write_stdin("a 1 2\nb 3 4\n")
+            .assert()
+            .stdout("_ 1 _\n_ 3 _\n");
+    }
+
+    #[test]
+    fn test_invert_fields_with_invert() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-v", "-f", "2", "--invert-fields", "--", SED_CMD, "s/./_/"])
+            .write_stdin("a 1 2\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("-v and --invert-fields cannot be used together"));
+    }
+
+    #[test]
+    fn test_line_null() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-z", "-l", "2", "--", TR_CMD, "[:lower:]", "[:upper:]"])
+            .write_stdin("a\nb\0c\nd\0e\nf\0")
+            .assert()
+            .stdout("a\nb\0C\nD\0e\nf\0");
+    }
+
+    #[test]
+    fn test_line_null_no_terminator() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-z", "-l", "2", "--highlight-start", "<", "--highlight-end", ">"])
+            .write_stdin("abc\0de\n")
+            .assert()
+            .stdout("abc\0<de\n>");
+    }
+
+    #[test]
+    fn test_line_tail_null() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-z", "--tail", "1", "--", TR_CMD, "[:lower:]", "[:upper:]"])
+            .write_stdin("a\nb\0c\nd\0")
+            .assert()
+            .stdout("a\nb\0C\nD\0");
+    }
+
+    #[test]
+    fn test_show_both_regex_only() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--show-both", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ABC123DEF\n456\n")
+            .assert()
+            .stdout("B:ABC\nS:123\nB:DEF\nB:\\n\nS:456\nB:\\n\n");
+    }
+
+    #[test]
+    fn test_show_both_field() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "-d", ",", "--show-both"])
+            .write_stdin("a,b,c\n")
+            .assert()
+            .stdout("B:a\nB:,\nS:b\nB:,\nB:c\nB:\\n\n");
+    }
+
+    #[test]
+    fn test_show_both_solid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-l", "2", "--show-both", "--", "false"])
+            .write_stdin("a\nb\n")
+            .assert()
+            .success()
+            .stdout("B:a\nB:\\n\nS:b\nB:\\n\n");
+    }
+
+    #[test]
+    fn test_field_reorder() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "3,1,2", "--reorder", "--", TR_CMD, "a", "a"])
+            .write_stdin("a,b,c\nd,e,f\n")
+            .assert()
+            .stdout("c,a,b\nf,d,e\n");
+    }
+
+    #[test]
+    fn test_field_reorder_duplicate() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "3,1,1,2-", "--reorder", "--", TR_CMD, "[:lower:]", "[:upper:]"])
+            .write_stdin("a,b,c\n")
+            .assert()
+            .stdout("C,A,A,B,C\n");
+    }
+
+    #[test]
+    fn test_field_reorder_without_delimiter() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "3,1,2", "--reorder", "--", TR_CMD, "a", "a"])
+            .write_stdin("a b c\n")
+            .assert()
+            .code(1);
+    }
+
+    #[test]
+    fn test_strict_utf8() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--strict-utf8", "-c", "1", "--", "cat"])
+            .write_stdin(b"ab\xffc\n".to_vec())
+            .assert()
+            .code(1)
+            .stderr(predicate::str::contains("Invalid UTF-8 sequence at byte offset 2"));
+    }
+
+    #[test]
+    fn test_strict_utf8_valid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--strict-utf8", "-l", "1", "--", TR_CMD, "[:lower:]", "[:upper:]"])
+            .write_stdin("abc\ndef\n")
+            .assert()
+            .success()
+            .stdout("ABC\ndef\n");
+    }
+
+    #[test]
+    fn test_exoffload_mask_delimiter() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-e", "echo 1,3 5", "--mask-delimiter", ",", "--", SED_CMD, "s/./@/"])
+            .write_stdin("AAA\nBBB\nCCC\nDDD\nEEE\n")
+            .assert()
+            .stdout("@AA\nBBB\n@CC\nDDD\nEEE\n");
+    }
+
+    #[test]
+    fn test_exoffload_mask_delimiter_space() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-e", "echo 2 4", "--mask-delimiter", " ", "--", SED_CMD, "s/./@/"])
+            .write_stdin("AAA\nBBB\nCCC\nDDD\nEEE\n")
+            .assert()
+            .stdout("AAA\n@BB\nCCC\n@DD\nEEE\n");
+    }
+
+    #[test]
+    fn test_exoffload_mask_bitmap() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-e", "printf '0\\n1\\n1\\n0\\n1\\n'", "--mask-bitmap", "--", SED_CMD, "s/./@/"])
+            .write_stdin("AAA\nBBB\nCCC\nDDD\nEEE\n")
+            .assert()
+            .stdout("AAA\n@BB\n@CC\nDDD\n@EE\n");
+    }
+
+    #[test]
+    fn test_regex_line_regexp() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-x", "-g", "foo", "--", SED_CMD, "s/./@/"])
+            .write_stdin("foo\nfoobar\nbarfoo\nfoo\n")
+            .assert()
+            .stdout("@oo\nfoobar\nbarfoo\n@oo\n");
+    }
+
+    #[test]
+    fn test_regex_line_regexp_invert() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-v", "--line-regexp", "-g", "foo|bar", "--", SED_CMD, "s/./@/"])
+            .write_stdin("foo\nfoobar\nbar\n")
+            .assert()
+            .stdout("foo\n@oobar\nbar\n");
+    }
+
+    #[test]
+    fn test_regex_only_line_regexp() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-x", "-og", "fo+", "--", SED_CMD, "s/./@/"])
+            .write_stdin("foo\nfoobar\n")
+            .assert()
+            .stdout("@oo\nfoobar\n");
+    }
+
+    #[test]
+    fn test_solid_require_output() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-og", "B+", "--require-output", "--", GREP_CMD, "A"])
+            .write_stdin("AAA BBB\n")
+            .assert()
+            .code(1)
+            .stdout("AAA ")
+            .stderr(predicate::str::contains("'grep' produced no output"));
+    }
+
+    #[test]
+    fn test_solid_require_output_ok() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-og", "B+", "--require-output", "--", TR_CMD, "B", "b"])
+            .write_stdin("AAA BBB\n")
+            .assert()
+            .success()
+            .stdout("AAA bbb\n");
+    }
+
+    #[test]
+    fn test_solid_no_output() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-og", "B+", "--", GREP_CMD, "A"])
+            .write_stdin("AAA BBB\n")
+            .assert()
+            .success()
+            .stdout("AAA \n");
+    }
+
+    #[test]
+    fn test_field_output_delimiter() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "-d", "\t", "--output-delimiter", ",", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("100\t200\t300\n")
+            .assert()
+            .stdout("100,@@@,300\n");
+    }
+
+    #[test]
+    fn test_field_regex_output_delimiter() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1,3", "--output-delimiter", ";", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("AA  BB\t CC\n")
+            .assert()
+            .stdout("@@;BB;@@\n");
+    }
+
+    #[test]
+    fn test_csv_output_delimiter() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--csv", "-f", "1", "--output-delimiter", ";"])
+            .write_stdin("a,b\n")
+            .assert()
+            .code(1);
+    }
+
+    #[test]
+    fn test_teip_cmd() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "A"])
+            .env("TEIP_CMD", format!("'{}' 's/./@ @/'", SED_CMD))
+            .write_stdin("ABAB\n")
+            .assert()
+            .stdout("@ @B@ @B\n");
+    }
+
+    #[test]
+    fn test_teip_cmd_override() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "A", "--", SED_CMD, "s/./_/"])
+            .env("TEIP_CMD", format!("'{}' 's/./@ @/'", SED_CMD))
+            .write_stdin("ABAB\n")
+            .assert()
+            .stdout("_B_B\n");
+    }
+
+    #[test]
+    fn test_teip_cmd_invalid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "A"])
+            .env("TEIP_CMD", "sed 's/./@/")
+            .write_stdin("ABAB\n")
+            .assert()
+            .code(1)
+            .stderr(predicate::str::contains("TEIP_CMD"));
+    }
+
+    #[test]
+    fn test_csv_solid_nul() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--csv", "-f", "2", "--solid-nul", "--", TR_CMD, "[:lower:]", "[:upper:]"])
+            .write_stdin("a,\"b\nc\",d\ne,f,g\n")
+            .assert()
+            .stdout("a,\"B\nC\",d\ne,F,g\n");
+    }
+
+    #[test]
+    fn test_regex_only_solid_nul() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "b+", "--solid-nul", "--", TR_CMD, "b", "B"])
+            .write_stdin("abba\nb\n")
+            .assert()
+            .stdout("aBBa\nB\n");
+    }
+
+    #[test]
+    fn test_line_unsorted() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "3,1,2", "--", SED_CMD, "s/./@/"])
+            .write_stdin("AAA\nBBB\nCCC\nDDD\nEEE\n")
+            .assert()
+            .stdout("@AA\n@BB\n@CC\nDDD\nEEE\n");
+    }
+
+    #[test]
+    fn test_line_overlapping() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1-3,2-4", "--", SED_CMD, "s/./@/"])
+            .write_stdin("AAA\nBBB\nCCC\nDDD\nEEE\n")
+            .assert()
+            .stdout("@AA\n@BB\n@CC\n@DD\nEEE\n");
+    }
+
+    #[test]
+    fn test_line_overlapping_invert() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-v", "-l", "4-5,2,1-2", "--", SED_CMD, "s/./@/"])
+            .write_stdin("AAA\nBBB\nCCC\nDDD\nEEE\nFFF\n")
+            .assert()
+            .stdout("AAA\nBBB\n@CC\nDDD\nEEE\n@FF\n");
+    }
+
+    #[test]
+    fn test_stats_regex_only() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        let input = "ABC123DEF\n456\n7\n";
+        cmd.args(&["-og", "\\d", "--stats", "--", SED_CMD, "s/./@/"])
+            .write_stdin(input)
+            .assert()
+            .stdout("ABC@@@DEF\n@@@\n@\n")
+            .stderr(predicate::str::contains(format!("{:<10}{:>12}{:>16}", "holes", 7, 7)))
+            .stderr(predicate::str::contains(format!("{:<10}{:>12}{:>16}", "total", 12, input.len())))
+            .stderr(predicate::str::contains(format!("{:<10}{:>12}", "commands", 1)));
+    }
+
+    #[test]
+    fn test_null_if_empty() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\w+", "--null-if-empty", "--", SED_CMD, "/b/s/.*//;s/./@/"])
+            .write_stdin("a1 b2 c3\nb4\n")
+            .assert()
+            .stdout("@1 b2 @3\nb4\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\w+", "--", SED_CMD, "/b/s/.*//;s/./@/"])
+            .write_stdin("a1 b2 c3\nb4\n")
+            .assert()
+            .stdout("@1  @3\n\n");
+    }
+
+    #[test]
+    fn test_null_if_empty_solid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-og", "\\w+", "--null-if-empty", "--", SED_CMD, "s/./@/"])
+            .write_stdin("a1\n")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_annotate_count() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--annotate-count", "--", SED_CMD, "s/./@/"])
+            .write_stdin("a1b22c3\nxyz\n4\n5 6")
+            .assert()
+            .stdout("3\ta1b22c3\n0\txyz\n1\t4\n2\t5 6");
+    }
+
+    #[test]
+    fn test_annotate_count_suffix() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "2-", "--annotate-count", "--annotate-suffix", "--annotate-separator", " # "])
+            .write_stdin("a,b,c\r\nd\r\ne,f\r\n")
+            .assert()
+            .stdout("a,b,c # 2\r\nd # 0\r\ne,f # 1\r\n");
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d", "--profile", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ABC123DEF\n456\n")
+            .assert()
+            .stdout("ABC@@@DEF\n@@@\n")
+            .stderr(predicate::str::contains("seconds"))
+            .stderr(predicate::str::contains("matching"))
+            .stderr(predicate::str::contains("pipe read"))
+            .stderr(predicate::str::contains("total"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ABC123DEF\n456\n")
+            .assert()
+            .stderr("");
+    }
+
+    #[test]
+    fn test_summary_json() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        let out = cmd
+            .args(&["-s", "-og", "\\d+", "--summary-json", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ABC123DEF\n456\nGHI\n")
+            .assert()
+            .success()
+            .stdout("ABC@23DEF\n@56\nGHI\n")
+            .get_output()
+            .stderr
+            .clone();
+        let summary: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(summary["version"], 1);
+        assert_eq!(summary["holes"]["chunks"], 2);
+        assert_eq!(summary["holes"]["bytes"], 6);
+        assert_eq!(summary["holes"]["lines"], 2);
+        assert_eq!(summary["others"]["chunks"], 6);
+        assert_eq!(summary["others"]["bytes"], 12);
+        assert_eq!(summary["lines"], 3);
+        assert_eq!(summary["commands"], 2);
+        assert_eq!(summary["exit_status"], 0);
+        assert!(summary["seconds"]["total"].as_f64().unwrap() > 0.0);
+
+        let path = std::env::temp_dir().join("teip_test_summary_file.json");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "xyz", "--exit-nomatch", "--summary-json", "--summary-file"])
+            .arg(&path)
+            .write_stdin("ABC\n")
+            .assert()
+            .code(1)
+            .stdout("ABC\n")
+            .stderr("");
+        let summary: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(summary["holes"]["chunks"], 0);
+        assert_eq!(summary["others"]["bytes"], 4);
+        assert_eq!(summary["commands"], 0);
+        assert_eq!(summary["exit_status"], 1);
+
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "xyz", "--summary-file", "out.json"])
+            .write_stdin("ABC\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--summary-file requires --summary-json"));
+    }
+
+    #[test]
+    fn test_stats_solid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        let input = "ABC123DEF\n456\n";
+        cmd.args(&["-s", "-og", "\\d+", "--stats", "--", SED_CMD, "s/./@/"])
+            .write_stdin(input)
+            .assert()
+            .stdout("ABC@23DEF\n@56\n")
+            .stderr(predicate::str::contains(format!("{:<10}{:>12}{:>16}", "total", 6, input.len())))
+            .stderr(predicate::str::contains(format!("{:<10}{:>12}", "commands", 2)));
+    }
+
+    #[test]
+    fn test_stats_char() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        let input = "ABCDEF\nGH\n";
+        cmd.args(&["-c", "2-3", "--stats"])
+            .write_stdin(input)
+            .assert()
+            .stderr(predicate::str::contains(format!("{:<10}{:>12}{:>16}", "holes", 2, 3)))
+            .stderr(predicate::str::contains(format!("{:<10}{:>12}{:>16}", "others", 5, input.len() - 3)))
+            .stderr(predicate::str::contains(format!("{:<10}{:>12}", "commands", 0)));
+    }
+
+    #[test]
+    fn test_regex_null_crlf() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-z", "-g", "B", "--highlight-start", "<", "--highlight-end", ">"])
+            .write_stdin("A\r\n\0B\r\n\0C\r\n")
+            .assert()
+            .stdout("A\r\n\0<B\r\n>\0C\r\n");
+    }
+
+    #[test]
+    fn test_line_null_crlf() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-z", "-l", "2", "--", TR_CMD, "\\r\\n", "RN"])
+            .write_stdin("A\r\n\0B\r\n\0")
+            .assert()
+            .stdout("A\r\n\0BRN\0");
+    }
+
+    #[test]
+    fn test_regex_exclude_pattern() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "see", "--exclude-pattern", "\\bhttps?://\\S+", "--", TR_CMD, "[:lower:]", "[:upper:]"])
+            .write_stdin("see http://example.com/abc and https://example.org now\nhttp://example.net\n")
+            .assert()
+            .stdout("SEE http://example.com/abc AND https://example.org NOW\nhttp://example.net\n");
+    }
+
+    #[test]
+    fn test_char_exclude_pattern() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-c", "1-6", "--exclude-pattern", "[0-9]+", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ab12cdef\n")
+            .assert()
+            .stdout("@b12@def\n");
+    }
+
+    #[test]
+    fn test_solid_exclude_pattern_whole() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-og", "[a-z]+", "--exclude-pattern", "b.*", "--", TR_CMD, "[:lower:]", "[:upper:]"])
+            .write_stdin("abc 1 bcd\n")
+            .assert()
+            .stdout("Abc 1 bcd\n");
+    }
+
+    #[test]
+    fn test_exit_nomatch() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "Z", "--exit-nomatch", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ABC\nDEF\n")
+            .assert()
+            .code(1)
+            .stdout("ABC\nDEF\n");
+    }
+
+    #[test]
+    fn test_exit_nomatch_matched() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "E", "--exit-nomatch", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ABC\nDEF\n")
+            .assert()
+            .success()
+            .stdout("ABC\nD@F\n");
+    }
+
+    #[test]
+    fn test_exit_nomatch_dryrun() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "3", "--exit-nomatch"])
+            .write_stdin("ABC\nDEF\n")
+            .assert()
+            .code(1);
+    }
+
+    #[test]
+    fn test_regex_engine_rust() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--regex-engine", "rust", "-og", "\\d+", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("ABC123DEF456\n")
+            .assert()
+            .stdout("ABC@@@DEF@@@\n");
+    }
+
+    #[test]
+    #[cfg(feature = "oniguruma")]
+    fn test_regex_engine_onig() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--regex-engine", "onig", "-og", "\\d+", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("ABC123DEF456\n")
+            .assert()
+            .stdout("ABC@@@DEF@@@\n");
+    }
+
+    #[test]
+    #[cfg(not(feature = "oniguruma"))]
+    fn test_regex_engine_onig_disabled() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--regex-engine", "onig", "-og", "\\d+"])
+            .write_stdin("ABC123DEF456\n")
+            .assert()
+            .code(1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "oniguruma"))]
+    fn test_onig_disabled() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-G", "-og", "\\d+", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("ABC123DEF456\n")
+            .assert()
+            .code(1)
+            .stderr(predicate::str::contains("not enabled in this build"));
+    }
+
+    #[test]
+    fn test_regex_engine_rust_with_onig() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-G", "--regex-engine", "rust", "-og", "\\d+", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("ABC123DEF456\n")
+            .assert()
+            .code(1)
+            .stderr(predicate::str::contains("-G cannot be used with --regex-engine rust"));
+    }
+
+    #[test]
+    fn test_regex_onig_hint() {
+        // -G is suggested only for -g, and only if it is available
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        let assert = cmd.args(&["-g", "(a)\\1"])
+            .write_stdin("aa\n")
+            .assert()
+            .code(1);
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert_eq!(stderr.contains("Hint: -G"), cfg!(feature = "oniguruma"), "{}", stderr);
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--exclude-pattern", "(a)\\1", "-f", "1"])
+            .write_stdin("aa\n")
+            .assert()
+            .code(1)
+            .stderr(predicate::str::contains("not supported").and(predicate::str::contains("Hint").not()));
+    }
+
+    #[test]
+    fn test_regex_engine_invalid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--regex-engine", "pcre", "-og", "\\d+"])
+            .write_stdin("ABC123DEF456\n")
+            .assert()
+            .code(1)
+            .stderr(predicate::str::contains("--regex-engine"));
+    }
+
+    #[test]
+    fn test_show_bypassed() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--show-bypassed", "--", SED_CMD, "s/./@/"])
+            .env("TEIP_HIGHLIGHT_BYPASS", "<{}>")
+            .write_stdin("ABC123DEF\n456\n")
+            .assert()
+            .stdout("<ABC>123<DEF>\n456\n");
+    }
+
+    #[test]
+    fn test_show_bypassed_line() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "2", "--show-bypassed"])
+            .env("TEIP_HIGHLIGHT_BYPASS", "<{}>")
+            .write_stdin("AAA\r\nBBB\r\nCCC\r\n")
+            .assert()
+            .stdout("<AAA>\r\nBBB\r\n<CCC>\r\n");
+    }
+
+    #[test]
+    fn test_show_bypassed_invalid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "2", "--show-bypassed"])
+            .env("TEIP_HIGHLIGHT_BYPASS", "<>")
+            .write_stdin("AAA\n")
+            .assert()
+            .code(1);
+    }
+
+    #[test]
+    fn test_field_delimiter_escape_tab() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "-d", "\\t", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("100\t200\t300\n")
+            .assert()
+            .stdout("100\t@@@\t300\n");
+    }
+
+    #[test]
+    fn test_field_delimiter_escape_hex() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "-d", "\\x2c", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("100,200,300\n")
+            .assert()
+            .stdout("100,@@@,300\n");
+    }
+
+    #[test]
+    fn test_field_delimiter_no_escape() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "-d", "\\t", "--no-escape", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("100\\t200\\t300\n")
+            .assert()
+            .stdout("100\\t@@@\\t300\n");
+    }
+
+    // The warning for the terminal cannot be tested here because stdin is never a terminal.
+    // Check it manually with `teip -l 1` on the terminal.
+    #[test]
+    fn test_no_tty_warning_with_pipe() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1", "--", SED_CMD, "s/./@/"])
+            .write_stdin("AAA\n")
+            .assert()
+            .stdout("@AA\n")
+            .stderr("");
+    }
+
+    #[test]
+    fn test_zero_based_field() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--zero-based", "-f", "0"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("AAA BBB CCC\n")
+            .assert()
+            .stdout("[AAA] BBB CCC\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("AAA BBB CCC\n")
+            .assert()
+            .stdout("[AAA] BBB CCC\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--zero-based", "-f", "1-", "-d", ","])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("AAA,BBB,CCC\n")
+            .assert()
+            .stdout("AAA,[BBB],[CCC]\n");
+    }
+
+    #[test]
+    fn test_zero_based_char_line() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--zero-based", "-c", "0,2"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("ABC\n")
+            .assert()
+            .stdout("[A]B[C]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--zero-based", "-l", "1"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("ABC\nDEF\nGHI\n")
+            .assert()
+            .stdout("ABC\n[DEF]\nGHI\n");
+    }
+
+    // Reading a directory always fails with EISDIR
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_read_error() {
+        use assert_cmd::prelude::*;
+        let mut cmd = std::process::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1", "--", "cat"])
+            .stdin(std::fs::File::open(".").unwrap())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Is a directory"));
+        let mut cmd = std::process::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--keep-going", "2", "-f", "1", "--", "cat"])
+            .stdin(std::fs::File::open(".").unwrap())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("read error 2/2, retrying"))
+            .stderr(predicate::str::contains("giving up after 3 consecutive read errors"));
+    }
+
+    #[test]
+    fn test_input() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--input", "tests/sample.txt", "--head", "2", "--", SED_CMD, "s/./@/g"])
+            .assert()
+            .stdout(predicate::str::starts_with("@@@@@@@@@\n@@@@@@@@@\nEEE333FFF\n"))
+            .stdout(predicate::str::ends_with("YYY131313ZZZ\n"));
+    }
+
+    #[test]
+    fn test_input_multiple() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--input", "tests/sample.txt", "--input", "tests/sample.txt", "-og", "\\d+"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout(predicate::str::starts_with("AAA[111]BBB\n"))
+            .stdout(predicate::str::contains("YYY[131313]ZZZ\nAAA[111]BBB\n"));
+    }
+
+    #[test]
+    fn test_files_from() {
+        let list = std::env::temp_dir().join("teip_test_files_from.txt");
+        std::fs::write(&list, "tests/sample.txt\n\ntests/sample.txt\n").unwrap();
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--files-from", list.to_str().unwrap(), "-l", "1,6", "--", SED_CMD, "s/./@/g"])
+            .assert()
+            .stdout(predicate::str::starts_with("@@@@@@@@@\nCCC222DDD\n"))
+            .stdout(predicate::str::contains("III555JJJ\n@@@@@@@@@\n"));
+    }
+
+    #[test]
+    fn test_input_trailing_args_are_command() {
+        // tests/sample.txt after -- is an argument of the command, not the input
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1", "--", "echo", "tests/sample.txt"])
+            .write_stdin("AAA\n")
+            .assert()
+            .stdout("tests/sample.txt\n");
+    }
+
+    #[test]
+    fn test_input_error() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--input", "tests/not_found.txt", "-l", "1", "--", "cat"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("tests/not_found.txt"));
+        let list = std::env::temp_dir().join("teip_test_files_from_empty.txt");
+        std::fs::write(&list, "").unwrap();
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--files-from", list.to_str().unwrap(), "-l", "1", "--", "cat"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("No input file is given"));
+    }
+
+    #[test]
+    fn test_open_ended_ranges() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "3-", "-d", ","])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a,b,c,d\na\na,b,c\n")
+            .assert()
+            .stdout("a,b,[c],[d]\na\na,b,[c]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f-2", "-d", ","])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a,b,c\na\n")
+            .assert()
+            .stdout("[a],[b],c\n[a]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-c", "-"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("abc\n")
+            .assert()
+            .stdout("[abc]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-v", "-f", "-"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a b\n")
+            .assert()
+            .stdout("a b\n");
+    }
+
+    #[test]
+    fn test_buffer_lines() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--buffer-lines", "2", "-f", "2", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("AAA BBB\nCCC DDD\nEEE FFF\n")
+            .assert()
+            .stdout("AAA @@@\nCCC @@@\nEEE @@@\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--buffer-lines", "1", "-s", "-l", "2", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("AAA\nBBB\nCCC")
+            .assert()
+            .stdout("AAA\n@@@\nCCC");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--buffer-lines", "0", "-l", "2", "--", "cat"])
+            .write_stdin("AAA\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--buffer-lines must be greater than 0"));
+    }
+
+    #[test]
+    fn test_group() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "(\\w+)=(\\w+)", "--group", "2", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("a=xyz b=12 cc=ABCD\nnone\n")
+            .assert()
+            .stdout("a=@@@ b=@@ cc=@@@@\nnone\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "(\\w+)=(\\w+)", "--group", "1", "-v"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a=xyz b=12\n")
+            .assert()
+            .stdout("a[=xyz ]b[=12]\n");
+    }
+
+    #[test]
+    fn test_group_error() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "(\\w+)=(\\w+)", "--group", "3"])
+            .write_stdin("a=b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("does not have the group 3"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "(\\w+)=(\\w+)", "--group", "1"])
+            .write_stdin("a=b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--group is available only with -og"));
+    }
+
+    #[test]
+    fn test_dry_run_summary() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--dry-run", "-og", "\\d+", "--", SED_CMD, "s/./@/g"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a1b22\nxx\n333\nc4444\n")
+            .assert()
+            .stdout("a[1]b[22]\nxx\n[333]\nc[4444]\n")
+            .stderr("selected 4 regions across 3 lines (10 bytes)\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--dry-run", "-l", "1,3"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("AAA\nBBB\nCCC\n")
+            .assert()
+            .stdout("[AAA]\nBBB\n[CCC]\n")
+            .stderr("selected 2 regions across 2 lines (6 bytes)\n");
+    }
+
+    #[test]
+    fn test_stream_placeholder() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--input", "tests/sample.txt", "--stream-placeholder", "--head", "1", "--", SED_CMD, "s|.*|<{}>|"])
+            .assert()
+            .stdout(predicate::str::starts_with("<tests/sample.txt>\nCCC222DDD\n"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--stream-placeholder", "-f", "2", "--", SED_CMD, "s|.*|<{}>|"])
+            .write_stdin("AAA BBB\n")
+            .assert()
+            .stdout("AAA <->\n");
+        // {} is not replaced without --stream-placeholder
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--", SED_CMD, "s|.*|<{}>|"])
+            .write_stdin("AAA BBB\n")
+            .assert()
+            .stdout("AAA <{}>\n");
+    }
+
+    #[test]
+    fn test_stream_placeholder_error() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--stream-placeholder", "-s", "-f", "2", "--", SED_CMD, "s|.*|<{}>|"])
+            .write_stdin("AAA BBB\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--stream-placeholder cannot be used with -s"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--input", "tests/sample.txt", "--input", "tests/sample.csv", "--stream-placeholder", "-f", "2", "--", "cat"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("requires a single input file"));
+    }
+
+    #[test]
+    fn test_list_origin() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "0:0,2"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("AAA BBB CCC\n")
+            .assert()
+            .stdout("[AAA] BBB [CCC]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--zero-based", "-f", "1:1"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("AAA BBB CCC\n")
+            .assert()
+            .stdout("[AAA] BBB CCC\n");
+        // -g with -f, -g selects lines and -f selects zero-based fields
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "A", "-f", "0:1"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("AAA BBB\nCCC DDD\n")
+            .assert()
+            .stdout("AAA [BBB]\nCCC DDD\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-c", "1:2", "--zero-based"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("ABC\n")
+            .assert()
+            .stdout("A[B]C\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "0:1"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("AAA\nBBB\n")
+            .assert()
+            .stdout("AAA\n[BBB]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2:1"])
+            .write_stdin("AAA\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("origin '2' was invalid"));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1", "--", SED_CMD, "s/./@/g"])
+            .env("TEIP_DEPTH", "31")
+            .write_stdin("AAA\n")
+            .assert()
+            .stdout("@@@\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1", "--", SED_CMD, "s/./@/g"])
+            .env("TEIP_DEPTH", "32")
+            .write_stdin("AAA\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("teip is nested too deeply (32 levels)"));
+    }
+
+    #[test]
+    fn test_max_depth_nested() {
+        let teip = assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME"));
+        let teip = teip.to_str().unwrap();
+        // teip -> teip -> teip reaches --max-depth 2 of the innermost one
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1", "--", teip, "-l", "1", "--", teip, "--max-depth", "2", "-l", "1", "--", "cat"])
+            .env_remove("TEIP_DEPTH")
+            .write_stdin("AAA\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("teip is nested too deeply (2 levels)"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1", "--", teip, "-l", "1", "--", teip, "--max-depth", "3", "-l", "1", "--", SED_CMD, "s/./@/g"])
+            .env_remove("TEIP_DEPTH")
+            .write_stdin("AAA\n")
+            .assert()
+            .stdout("@@@\n");
+    }
+
+    #[test]
+    fn test_long_aliases() {
+        let cases: Vec<(Vec<&str>, Vec<&str>, &str)> = vec![
+            (vec!["-g", "B"], vec!["--regex", "B"], "AAA\nBBB\n"),
+            (vec!["-c", "2"], vec!["--chars", "2"], "ABC\n"),
+            (vec!["-l", "2"], vec!["--lines", "2"], "AAA\nBBB\n"),
+            (vec!["-f", "2"], vec!["--fields", "2"], "AAA BBB\n"),
+            (vec!["-f", "2", "-d", ","], vec!["--fields", "2", "--delimiter", ","], "A,B\n"),
+            (vec!["-f", "2", "-D", "[,;]"], vec!["--fields", "2", "--regex-delimiter", "[,;]"], "A;B\n"),
+            (vec!["-s", "-f", "2"], vec!["--solid", "-f", "2"], "AAA BBB\n"),
+            (vec!["-v", "-f", "2"], vec!["--invert", "-f", "2"], "AAA BBB\n"),
+            (vec!["-z", "-l", "2"], vec!["--zero", "-l", "2"], "AAA\0BBB\0"),
+        ];
+        for (short, long, input) in cases {
+            let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+            let expected = cmd.args(&short)
+                .args(&["--", SED_CMD, "s/./@/g"])
+                .write_stdin(input)
+                .output()
+                .unwrap();
+            assert!(expected.status.success());
+            let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+            cmd.args(&long)
+                .args(&["--", SED_CMD, "s/./@/g"])
+                .write_stdin(input)
+                .assert()
+                .success()
+                .stdout(expected.stdout);
+        }
+    }
+
+    #[test]
+    fn test_field_match() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--field-match", "^[0-9]+$", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ab 12 cd 34\n")
+            .assert()
+            .stdout("ab @2 cd @4\n");
+    }
+
+    #[test]
+    fn test_field_match_invert() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--field-match", "^[0-9]+$", "-v", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ab 12 cd 34\n")
+            .assert()
+            .stdout("@b 12 @d 34\n");
+    }
+
+    #[test]
+    fn test_field_match_invert_list() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "1-3", "--field-match", "^[0-9]+$", "-v"])
+            .write_stdin("ab,12,cd,34,ef\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("[ab],12,[cd],34,ef\n");
+    }
+
+    #[test]
+    fn test_field_match_regex_lines() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "x", "-v", "-f", "1-", "--field-match", "a"])
+            .write_stdin("x ab 12\ny ab 12\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("x ab 12\ny [ab] 12\n");
+    }
+
+    #[test]
+    fn test_delimiter_replace() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--delimiter-replace", "\\s+", " ", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("a \t b\t\tc  d\nef  gh\n")
+            .assert()
+            .stdout("a B c d\nef GH\n");
+    }
+
+    #[test]
+    fn test_delimiter_replace_group() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-D", "[=;]", "-f", "2", "--delimiter-replace", "(.)", "<$1>"])
+            .write_stdin("a=1;b=2\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("a<=>[1]<;>b<=>2\n");
+    }
+
+    #[test]
+    fn test_delimiter_replace_with_d() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "2", "--delimiter-replace", ",", ";"])
+            .write_stdin("a,b\n")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_select_delimiters() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "1-", "--select-delimiters", "--", SED_CMD, "s/,/;/"])
+            .write_stdin("a,b b,,c\n,x,\n")
+            .assert()
+            .stdout("a;b b;;c\n;x;\n");
+    }
+
+    #[test]
+    fn test_select_delimiters_ordinal() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "2,4", "--select-delimiters"])
+            .write_stdin("a,b,c,d,e\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("a,b[,]c,d[,]e\n");
+    }
+
+    #[test]
+    fn test_select_delimiters_without_d() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--select-delimiters"])
+            .write_stdin("a b\n")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_delimiter_chars() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2,4", "--delimiter-chars", ",;:"])
+            .write_stdin("a,b;c:d\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("a,[b];c:[d]\n");
+    }
+
+    #[test]
+    fn test_delimiter_chars_preserved() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1-", "--delimiter-chars", ",;:", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("a,b;c:d\n,;:\n")
+            .assert()
+            .stdout("A,B;C:D\n,;:\n");
+    }
+
+    #[test]
+    fn test_delimiter_chars_meta() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2-", "--delimiter-chars", "]-^\\\\\\t"])
+            .write_stdin("a]b-c^d\\e\tf\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("a][b]-[c]^[d]\\[e]\t[f]\n");
+    }
+
+    #[test]
+    fn test_delimiter_regex_captures() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-D", "<(\\w+)=(\\d)>", "--delimiter-regex-captures", "-f", "2,4,5"])
+            .write_stdin("a<x=1>b<yy=2>c\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("a<[x]=1>[b]<[yy]=2>c\n");
+    }
+
+    #[test]
+    fn test_delimiter_regex_captures_exec() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-D", "<(\\w+)=(\\d)>", "--delimiter-regex-captures", "-f", "3,6", "--", SED_CMD, "s/./@/"])
+            .write_stdin("a<x=1>b<yy=2>c\n")
+            .assert()
+            .stdout("a<x=@>b<yy=@>c\n");
+    }
+
+    #[test]
+    fn test_delimiter_regex_captures_without_d() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "--delimiter-regex-captures", "-f", "1"])
+            .write_stdin("a,b\n")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_slurp_chars() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--slurp", "-c", "3-5"])
+            .write_stdin("abc\ndef\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("ab[c\nd]ef\n");
+    }
+
+    #[test]
+    fn test_slurp_chars_solid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--slurp", "-s", "-c", "3-5,7", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("abc\ndef\n")
+            .assert()
+            .stdout("abC\nDeF\n");
+    }
+
+    #[test]
+    fn test_slurp_requires_solid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--slurp", "-c", "3-5", "--", "cat"])
+            .write_stdin("abc\ndef\n")
+            .assert()
+            .failure();
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--slurp", "-l", "1"])
+            .write_stdin("abc\n")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_expand_tabs() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("TEIP_HIGHLIGHT", "[{}]")
+            .args(&["--expand-tabs", "8", "-c", "9-10"])
+            .write_stdin("ab\tcd\n12345678cd\n\t\tx\n")
+            .assert()
+            .stdout("ab\t[cd]\n12345678[cd]\n\t[\t]x\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--expand-tabs", "4", "--no-reexpand", "-c", "5-6", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("a\tbc\td\n")
+            .assert()
+            .stdout("a   @@  d\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--no-reexpand", "-c", "1"])
+            .write_stdin("a\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--no-reexpand requires --expand-tabs"));
+    }
+
+    #[test]
+    fn test_record_bytes() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("TEIP_HIGHLIGHT", "[{}]")
+            .args(&["--record-bytes", "8", "-c", "1-4"])
+            .write_stdin("AAAA1111BBBB2222CC")
+            .assert()
+            .stdout("[AAAA]1111[BBBB]2222[CC]");
+        // Line terminators are a part of the records
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--record-bytes", "8", "-c", "1-4", "-s", "--", TR_CMD, "A-Z", "a-z"])
+            .write_stdin("AA\nA1111BBBB\n222\n")
+            .assert()
+            .stdout("aa\na1111bbbb\n222\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--record-bytes", "8", "-c", "1-4", "--", "cat"])
+            .write_stdin("AAAA1111")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--record-bytes requires -s"));
+    }
+
+    #[test]
+    fn test_after_match() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--after-match", "^SECTION", "2", "--", SED_CMD, "s/./@/"])
+            .write_stdin("SECTION a\n11\n22\n33\nSECTION b\n44\nSECTION c\n55\n66\n77\n")
+            .assert()
+            .stdout("SECTION a\n@1\n@2\n33\nSECTION b\n@4\nSECTION c\n@5\n@6\n77\n");
+    }
+
+    #[test]
+    fn test_after_match_invalid_number() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--after-match", "^SECTION", "two"])
+            .write_stdin("SECTION a\n")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_reverse_lines_last_match() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "^a", "--nth", "1", "--reverse-lines"])
+            .write_stdin("a1\nb\na2\nc\na3\nd\n")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .assert()
+            .stdout("a1\nb\na2\nc\n[a3]\nd\n");
+    }
+
+    #[test]
+    fn test_reverse_lines_list() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "1,3", "--reverse", "--", SED_CMD, "s/./@/"])
+            .write_stdin("ab\ncd\nef\ngh\n")
+            .assert()
+            .stdout("ab\n@d\nef\n@h\n");
+    }
+
+    #[test]
+    fn test_reverse_lines_only_match() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "a", "--reverse-lines"])
+            .write_stdin("a\n")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_escape_output() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--escape-output", "-f", "1"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a\x01b\tc d\x1b\n")
+            .assert()
+            .stdout("[a\\x01b]\tc d\\x1B\n");
+    }
+
+    #[test]
+    fn test_escape_output_command() {
+        // The output of the command is not affected
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--escape-output", "-f", "2", "--", TR_CMD, "a", "a"])
+            .write_stdin("a\x01b c\x01\n")
+            .assert()
+            .stdout("a\x01b c\x01\n");
+    }
+
+    #[test]
+    fn test_between() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "\\d", "--between", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("a1b2c3\n12x\n")
+            .assert()
+            .stdout("A1B2C3\n12X\n");
+    }
+
+    #[test]
+    fn test_between_invert() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "\\d", "--between", "-v"])
+            .write_stdin("a1b2c3\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--between requires -g"));
+    }
+
+    #[test]
+    fn test_output_encoding() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--output-encoding", "utf-16le", "--", SED_CMD, "s/./@/"])
+            .write_stdin("a bc\n")
+            .assert()
+            .stdout(&b"a\x00 \x00@\x00c\x00\n\x00"[..]);
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--output-encoding", "utf-16", "--", SED_CMD, "s/./@/"])
+            .write_stdin("\u{feff}a bc\n")
+            .assert()
+            .stdout(&b"\xff\xfea\x00 \x00@\x00c\x00\n\x00"[..]);
+    }
+
+    #[test]
+    fn test_output_encoding_invalid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--output-encoding", "sjis"])
+            .write_stdin("a b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid value for --output-encoding: 'sjis'"));
+    }
+
+    #[test]
+    fn test_strip_add_affix() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1,3", "--strip-prefix", "http://", "--strip-suffix", "/"])
+            .write_stdin("http://a/ http://b/ https://c/\n")
+            .assert()
+            .stdout("a http://b/ https://c\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--add-suffix", "]", "--add-prefix", "[", "--strip-prefix", "x"])
+            .write_stdin("xa xb xc\n")
+            .assert()
+            .stdout("xa [b] xc\n");
+    }
+
+    #[test]
+    fn test_strip_prefix_with_command() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--strip-prefix", "a", "--", "cat"])
+            .write_stdin("abc\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with the command"));
+    }
+
+    #[test]
+    fn test_length_prefixed() {
+        // Echo each frame in upper case
+        let echo_frames = "$|=1; while (defined($n = <STDIN>)) { read(STDIN, $b, $n); $b = uc $b; print length($b), qq(\\n), $b }";
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--csv", "-f", "2,3", "--length-prefixed", "--", PERL_CMD, "-e", echo_frames])
+            .write_stdin("a,\"b\nc\",d\nx,,y\n")
+            .assert()
+            .stdout("a,\"B\nC\",D\nx,,Y\n");
+    }
+
+    #[test]
+    fn test_length_prefixed_invalid() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--length-prefixed", "--", SED_CMD, "s/^/x/"])
+            .write_stdin("a b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid length prefix"));
+    }
+
+    #[test]
+    fn test_no_merge() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-c", "2-4,6", "--no-merge"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("abcdef\nxy\n")
+            .assert()
+            .stdout("a[b][c][d]e[f]\nx[y]\n");
+    }
+
+    #[test]
+    fn test_open_range_per_line() {
+        // The end of 3- is the last field of each line
+        let input = "a b\na b c d e\na b c d e f g h\n";
+        let cases: Vec<(Vec<&str>, &str)> = vec![
+            (vec!["-f", "3-"], "a b\na b [c] [d] [e]\na b [c] [d] [e] [f] [g] [h]\n"),
+            (vec!["-f", "3-", "-D", " "], "a b\na b [c] [d] [e]\na b [c] [d] [e] [f] [g] [h]\n"),
+            (vec!["-f", "3-", "-d", " "], "a b\na b [c] [d] [e]\na b [c] [d] [e] [f] [g] [h]\n"),
+            (vec!["-f", "3-", "-v"], "[a] [b]\n[a] [b] c d e\n[a] [b] c d e f g h\n"),
+        ];
+        for (args, expected) in cases {
+            let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+            cmd.args(&args)
+                .env("TEIP_HIGHLIGHT", "[{}]")
+                .write_stdin(input)
+                .assert()
+                .stdout(expected);
+        }
+    }
+
+    #[test]
+    fn test_input_buffer_readahead() {
+        let input = "AAA BBB\nCCC DDD\n".repeat(1000) + "EEE";
+        let cases: Vec<(Vec<&str>, String)> = vec![
+            (vec!["-f", "2"], "AAA @@@\nCCC @@@\n".repeat(1000) + "EEE"),
+            (vec!["-l", "2"], "AAA BBB\n@@@@@@@\n".to_string() + &"AAA BBB\nCCC DDD\n".repeat(999) + "EEE"),
+            (vec!["-og", "C+|E+"], "AAA BBB\n@@@ DDD\n".repeat(1000) + "@@@"),
+        ];
+        for (args, expected) in cases {
+            let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+            cmd.args(&["--input-buffer-readahead", "3"])
+                .args(&args)
+                .args(&["--", SED_CMD, "s/./@/g"])
+                .write_stdin(input.as_str())
+                .assert()
+                .stdout(expected);
+        }
+    }
+
+    #[test]
+    fn test_empty_blank_lines() {
+        let input = "a\n\n \t\nb\n\n";
+        let cases: Vec<(Vec<&str>, &str)> = vec![
+            (vec!["--empty-lines"], "a\n[]\n \t\nb\n[]\n"),
+            (vec!["--blank-lines"], "a\n[]\n[ \t]\nb\n[]\n"),
+            (vec!["--empty-lines", "-v"], "[a]\n\n[ \t]\n[b]\n\n"),
+            (vec!["--blank-lines", "-v"], "[a]\n\n \t\n[b]\n\n"),
+            // The record including empty lines is not empty
+            (vec!["--empty-lines", "-z"], "a\n\n \t\nb\n\n"),
+        ];
+        for (args, expected) in cases {
+            let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+            cmd.args(&args)
+                .env("TEIP_HIGHLIGHT", "[{}]")
+                .write_stdin(input)
+                .assert()
+                .stdout(expected);
+        }
+    }
+
+    #[test]
+    fn test_display_columns() {
+        // あ occupies the columns 2-3, and is selected if either of them is in the list
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-c", "3-4", "--display-columns"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a\u{3042}b\u{3044}c\n")
+            .assert()
+            .stdout("a[\u{3042}b]\u{3044}c\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-c", "6", "--display-columns"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a\u{3042}b\u{3044}c\n")
+            .assert()
+            .stdout("a\u{3042}b\u{3044}c\n".replace('\u{3044}', "[\u{3044}]"));
+    }
+
+    #[test]
+    fn test_bytes() {
+        // あ is the bytes 2-4, and is selected entirely if any of them is in the list
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-b", "3"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a\u{3042}b\n")
+            .assert()
+            .stdout("a[\u{3042}]b\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-b", "1,4-5", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("a\u{3042}b\u{3044}c\n")
+            .assert()
+            .stdout("@@@\u{3044}c\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-b", "1", "-c", "1"])
+            .write_stdin("a\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("-b cannot be used with -c"));
+    }
+
+    #[test]
+    fn test_on_no_command() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--on-no-command", "dry-run"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a b\n")
+            .assert()
+            .stdout("[a] b\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--on-no-command", "error"])
+            .write_stdin("a b\n")
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("No command is given"));
+        // --dry-run does not require the command
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--on-no-command", "error", "--dry-run"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a b\n")
+            .assert()
+            .success()
+            .stdout("[a] b\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--on-no-command", "skip"])
+            .write_stdin("a b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid value for --on-no-command"));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--trace"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a12b\n3\n")
+            .assert()
+            .stdout("a[12]b\n[3]\n")
+            .stderr(concat!(
+                "BYPASS line=1 offset=0 bytes=1 \"a\"\n",
+                "SELECT line=1 offset=1 bytes=2 \"12\"\n",
+                "BYPASS line=1 offset=3 bytes=1 \"b\"\n",
+                "BYPASS line=1 offset=4 bytes=1 \"\\n\"\n",
+                "SELECT line=2 offset=0 bytes=1 \"3\"\n",
+                "BYPASS line=2 offset=1 bytes=1 \"\\n\"\n",
+            ));
+    }
+
+    #[test]
+    fn test_min_match_length() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "\\w+", "-o", "--min-match-length", "3", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("a bc def ghij\nxy z\n")
+            .assert()
+            .stdout("a bc @@@ @@@@\nxy z\n");
+        // Short matches are the holes with -v
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\w+", "-v", "--min-match-length", "3"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a bc def\n")
+            .assert()
+            .stdout("[a bc ]def\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "\\w+", "--min-match-length", "3"])
+            .write_stdin("a bc def\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--min-match-length is available only with -og"));
+    }
+
+    #[test]
+    fn test_field_default() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "5", "--field-default", "N/A", "--", SED_CMD, "s/^/</"])
+            .write_stdin("a,b,c,d,e\na,b,c\n\n")
+            .assert()
+            .stdout("a,b,c,d,<e\na,b,c,,<N/A\n,,,,<N/A\n");
+        // White-space separated fields are added with a space
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2,4", "--field-default", "-"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a  b\nx\n")
+            .assert()
+            .stdout("a  [b]    [-]\nx [-]  [-]\n");
+    }
+
+    #[test]
+    fn test_auto_delimiter() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--auto-delimiter", "-f", "2"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("id,name,note\n1,foo,a b\n")
+            .assert()
+            .stdout("id,[name],note\n1,[foo],a b\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--auto-delimiter", "--verbose", "-f", "2"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("id\tname,x\tnote\n1\tfoo,y\tbar\n")
+            .assert()
+            .stdout("id\t[name,x]\tnote\n1\t[foo,y]\tbar\n")
+            .stderr(predicate::str::contains("\"\\t\" is detected"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--auto-delimiter", "-d", ",", "-f", "2"])
+            .write_stdin("a,b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--auto-delimiter is available only with -f"));
+    }
+
+    #[test]
+    fn test_field_match_full() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--field-match", "abc|x"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("abc xabcx x\n")
+            .assert()
+            .stdout("[abc] [xabcx] [x]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--field-match", "abc|x", "--field-match-full"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("abc xabcx x\n")
+            .assert()
+            .stdout("[abc] xabcx [x]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--field-match-full"])
+            .write_stdin("abc\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--field-match-full requires --field-match"));
+    }
+
+    #[test]
+    fn test_deny_commands() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "-s", "--", SED_CMD, "s/./@/"])
+            .env("TEIP_DENY_COMMANDS", "rm, sed,sed.exe")
+            .write_stdin("a b\n")
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("is denied by TEIP_DENY_COMMANDS"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "-s", "--yes", "--", SED_CMD, "s/./@/"])
+            .env("TEIP_DENY_COMMANDS", "rm, sed,sed.exe")
+            .write_stdin("a b\n")
+            .assert()
+            .success()
+            .stdout("a @\n");
+        // Dry run never executes the command
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--dry-run", "--", SED_CMD, "s/./@/"])
+            .env("TEIP_DENY_COMMANDS", "sed,sed.exe")
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a b\n")
+            .assert()
+            .success()
+            .stdout("a [b]\n");
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_deny_commands_shell() {
+        // Commands in the script of -e are checked
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-e", "rm -f teip_test_deny_commands_shell; echo 1", "--", "cat"])
+            .env("TEIP_DENY_COMMANDS", "rm")
+            .write_stdin("a\n")
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains("'rm' is denied by TEIP_DENY_COMMANDS"));
+        // --field-command runs even in dry run
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--field-command", "grep -q a && rm -f teip_test_deny_commands_shell", "--dry-run"])
+            .env("TEIP_DENY_COMMANDS", "rm")
+            .write_stdin("a\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("'rm' is denied by TEIP_DENY_COMMANDS"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-e", "echo 1", "--yes", "--", "tr", "a-z", "A-Z"])
+            .env("TEIP_DENY_COMMANDS", "echo,tr")
+            .write_stdin("a\n")
+            .assert()
+            .success()
+            .stdout("A\n");
+    }
+
+    #[test]
+    fn test_record_sep() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--input-record-sep", "\\x1e", "--output-record-sep", "\\n", "-f", "2", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("a b\x1ec\nd e\x1e")
+            .assert()
+            .stdout("a B\nc\nD e\n");
+        // -z with another separator of the output
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-z", "-l", "2", "--output-record-sep", "\\n"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a\0b\0")
+            .assert()
+            .stdout("a\n[b]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--input-record-sep", "ab", "-l", "1"])
+            .write_stdin("a\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("must be a single byte"));
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "1,3", "--wrap-before", "<", "--wrap-after", ">", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("a,b,c\nd,e,f\n")
+            .assert()
+            .stdout("<A>,b,<C>\n<D>,e,<F>\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-d", ",", "-f", "2", "--wrap-before", "<", "--wrap-after", ">", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("a,b,c\n")
+            .assert()
+            .stdout("a,<B>,c\n");
+        // Built-in transforms
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "2", "--wrap-before", "<", "--wrap-after", ">", "--add-prefix", "x"])
+            .write_stdin("a,b,c\n")
+            .assert()
+            .stdout("a,<xb>,c\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "2", "--wrap-before", "<"])
+            .write_stdin("a,b,c\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("require the command"));
+    }
+
+    #[test]
+    fn test_max_fields() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "1-", "--max-fields", "2"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a,b,c,d\na\n")
+            .assert()
+            .stdout("[a],[b,c,d]\n[a]\n");
+        // White-space separated fields keep the spaces in the last field
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "3", "--max-fields", "3"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("2024 INFO  server  started  ok\n")
+            .assert()
+            .stdout("2024 INFO  [server  started  ok]\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "3,1", "--reorder", "--max-fields", "3"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a,b,c,d\n")
+            .assert()
+            .stdout("[c,d],[a]\n");
+    }
+
+    #[test]
+    fn test_every() {
+        // The fields are counted through the lines
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1-", "--every", "3"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a b c d\ne f g h\n")
+            .assert()
+            .stdout("a b [c] d\ne [f] g h\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1-", "--every", "3", "--offset", "1", "--", SED_CMD, "s/./@/"])
+            .write_stdin("a b c d\ne f g h\n")
+            .assert()
+            .stdout("@ b c @\ne f @ h\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1-", "--offset", "2"])
+            .write_stdin("a b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--offset requires --every"));
+    }
+
+    #[test]
+    fn test_replace_map() {
+        let map = std::env::temp_dir().join("teip_test_replace_map.tsv");
+        std::fs::write(&map, "JP\tJapan\nUS\tUnited States\n").unwrap();
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--replace-map", map.to_str().unwrap()])
+            .write_stdin("alice JP\nbob US\ncarol FR\ndave JPN\n")
+            .assert()
+            .stdout("alice Japan\nbob United States\ncarol FR\ndave JPN\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "2", "--replace-map", map.to_str().unwrap(), "--", "cat"])
+            .write_stdin("alice JP\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with the command"));
+    }
+
+    #[test]
+    fn test_include_eol() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("TEIP_HIGHLIGHT", "[{}]")
+            .args(&["-g", "\\r\\n", "--include-eol"])
+            .write_stdin("unix\nwindows\r\nmac\n")
+            .assert()
+            .stdout("unix\n[windows]\r\nmac\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("TEIP_HIGHLIGHT", "[{}]")
+            .args(&["-g", "\\r\\n"])
+            .write_stdin("unix\nwindows\r\nmac\n")
+            .assert()
+            .stdout("unix\nwindows\r\nmac\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-g", "\\r\\n", "--include-eol", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("unix\nwindows\r\n")
+            .assert()
+            .stdout("unix\n@@@@@@@\r\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "x", "--include-eol"])
+            .write_stdin("unix\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--include-eol is available only with -g without -o"));
+    }
+
+    #[test]
+    fn test_field_command_with_field_match() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--field-match", "a", "--field-command", "exit 0"])
+            .write_stdin("a b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--field-match and --field-command cannot be used together"));
+    }
+
+    #[test]
+    fn test_only_selected() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "1,3", "--only-selected", "-0"])
+            .write_stdin("a b,c,d e\nf,g,h\n")
+            .assert()
+            .stdout("a b\0d e\0f\0h\0");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--only-selected", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("ABC123DEF456\nGHI\n789\n")
+            .assert()
+            .stdout("123\n456\n789\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "-0"])
+            .write_stdin("a b\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("-0 requires --only-selected"));
+    }
+
+    #[test]
+    fn test_continuation() {
+        let input = "INFO start\nERROR failed\n  at foo\n\tat bar\nINFO end\n  at baz";
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-l", "2", "--continuation", "^\\s", "--", SED_CMD, "s/./@/g"])
+            .write_stdin(input)
+            .assert()
+            .stdout("INFO start\n@@@@@@@@@@@@\n@@@@@@@@\n@@@@@@@\nINFO end\n  at baz");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("TEIP_HIGHLIGHT", "[{}]")
+            .args(&["-g", "failed(?s:.*)bar", "--continuation", "^\\s"])
+            .write_stdin(input)
+            .assert()
+            .stdout("INFO start\n[ERROR failed]\n[  at foo]\n[\tat bar]\nINFO end\n  at baz");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("TEIP_HIGHLIGHT", "[{}]")
+            .args(&["-v", "-g", "ERROR", "--continuation", "^\\s"])
+            .write_stdin(input)
+            .assert()
+            .stdout("[INFO start]\nERROR failed\n  at foo\n\tat bar\n[INFO end]\n[  at baz]");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--continuation", "^\\s"])
+            .write_stdin(input)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--continuation is available only with"));
+    }
+
+    #[test]
+    fn test_count() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--count", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("a1b22\nc\n3\n")
+            .assert()
+            .stdout("3\n")
+            .stderr("");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--count", "--count-to", "stderr", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("a1b22\nc\n3\n")
+            .assert()
+            .stdout("a@b@@\nc\n@\n")
+            .stderr("3\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--nth", "2-", "--count", "--count-to", "stdout"])
+            .write_stdin("a1b22\nc\n3\n")
+            .assert()
+            .stdout("2\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "\\d+", "--count-to", "stderr"])
+            .write_stdin("a1\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--count-to requires --count"));
+    }
+
+    #[test]
+    fn test_posix_classes() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-D", "[[:space:]]+", "-f", "2", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("a  b\tc\n")
+            .assert()
+            .stdout("a  @\tc\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--regex-engine", "rust", "-og", "[[:space:]]+", "--", SED_CMD, "s/./@/g"])
+            .write_stdin("a  b\tc\n")
+            .assert()
+            .stdout("a@@b@c\n");
+        // The check does not depend on the engine
+        let engines: &[&str] = if cfg!(feature = "oniguruma") { &["rust", "onig"] } else { &["rust"] };
+        for &engine in engines {
+            let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+            let engine_args: &[&str] = if engine == "onig" { &["-G"] } else { &[] };
+            cmd.args(engine_args)
+                .args(&["-og", "[:space:]"])
+                .write_stdin("a b\n")
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains("POSIX character class [:space:] must be in brackets like [[:space:]]"));
+            let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+            cmd.args(engine_args)
+                .args(&["-D", "[[:spaces:]]", "-f", "2"])
+                .write_stdin("a b\n")
+                .assert()
+                .failure()
+                .stderr(predicate::str::contains("POSIX character class [:spaces:] is not supported"));
+        }
+    }
+
+    #[test]
+    fn test_jsonl_key() {
+        let input = concat!(
+            "{\"id\":1,\"email\":\"alice@example.com\"}\n",
+            "{\"email\": \"bob@example.com\", \"id\": 2}\r\n",
+            "{\"id\":3}\n",
+            "{\"id\":4,\"email\":null,\"sub\":{\"email\":\"x@example.com\"}}\n",
+            "not json\n",
+        );
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--jsonl-key", "email", "--", SED_CMD, "s/@.*/@example.org/"])
+            .write_stdin(input)
+            .assert()
+            .stdout(concat!(
+                "{\"id\":1,\"email\":\"alice@example.org\"}\n",
+                "{\"email\": \"bob@example.org\", \"id\": 2}\r\n",
+                "{\"id\":3}\n",
+                "{\"id\":4,\"email\":null,\"sub\":{\"email\":\"x@example.com\"}}\n",
+                "not json\n",
+            ));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("TEIP_HIGHLIGHT", "[{}]")
+            .args(&["--jsonl-key", "v", "--jsonl-stringify"])
+            .write_stdin("{\"v\":100}\n{\"v\":true}\n{\"v\":[1]}\n{\"v\":\"a\\\"b\"}\n")
+            .assert()
+            .stdout("{\"v\":\"[100]\"}\n{\"v\":\"[true]\"}\n{\"v\":[1]}\n{\"v\":\"[a\\\"b]\"}\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--jsonl-key", "v", "-f", "1"])
+            .write_stdin("{}\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--jsonl-key cannot be used with the other selectors"));
+    }
+
+    #[test]
+    fn test_jsonl_key_escape() {
+        // The command is given the decoded string
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--jsonl-key", "s", "--", SED_CMD, "s/\"/Q/g; s/\\\\/B/g"])
+            .write_stdin("{\"id\":1,\"s\":\"a\\\"b\\\\c\\u00e9\\u0041\"}\n")
+            .assert()
+            .stdout("{\"id\":1,\"s\":\"aQbBcéA\"}\n");
+        // The result is encoded back to a JSON string
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--jsonl-key", "s", "--", TR_CMD, "xyz", "\"\\\\\\001"])
+            .write_stdin("{\"s\":\"xyz\"}\n")
+            .assert()
+            .stdout("{\"s\":\"\\\"\\\\\\u0001\"}\n");
+        // The escaped newline is a part of the hole only with -s
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--jsonl-key", "s", "-s", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("{\"s\":\"a\\nb\"}\n")
+            .assert()
+            .stdout("{\"s\":\"A\\nB\"}\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["--jsonl-key", "s", "--", TR_CMD, "a-z", "A-Z"])
+            .write_stdin("{\"s\":\"a\\nb\"}\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("contains a newline"));
+    }
+
+    #[test]
+    fn test_join_fields() {
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-d", ",", "-f", "2-", "--join-fields", "+", "--", AWK_CMD, "-F+", "{print $1+$2+$3}"])
+            .write_stdin("a,1,2,3\nb,10,20,30\nc,4,5,6")
+            .assert()
+            .stdout("a,6,,\nb,60,,\nc,15,,");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-s", "-f", "1,3", "--field-join", "+", "--join-placement", "last", "--", AWK_CMD, "-F+", "{print $1+$2}"])
+            .write_stdin("1 x 2\n3 y 4\n")
+            .assert()
+            .stdout(" x 3\n y 7\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1,3", "--join-fields", ":"])
+            .env("TEIP_HIGHLIGHT", "[{}]")
+            .write_stdin("a b c\nd e\n")
+            .assert()
+            .stdout("[a:c] b \n[d] e\n");
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-og", "a", "--join-fields", "+"])
+            .write_stdin("a\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--join-fields requires -f"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1", "--join-fields", "+", "--join-placement", "middle"])
+            .write_stdin("a\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Invalid value for --join-placement"));
+    }
+
+    #[test]
+    fn test_join_fields_separator() {
+        // Escape sequences are interpreted like -d
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1,3", "--join-fields", "\\t", "--", AWK_CMD, "-F\t", "{print $2 \"-\" $1}"])
+            .write_stdin("1 2 3\n")
+            .assert()
+            .stdout("3-1 2 \n");
+        // The line terminator in the separator is rejected up front
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1,3", "--join-fields", "\\nX", "--", "cat"])
+            .write_stdin("1 2 3\n")
+            .assert()
+            .code(1)
+            .stdout("")
+            .stderr(predicate::str::contains("<separator> of --join-fields includes '\\n'"));
+        let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.args(&["-f", "1,3", "--join-fields", "\nX", "--no-escape", "--", "cat"])
+            .write_stdin("1 2 3\n")
+            .assert()
+            .code(1);
+    }
+
+    // test for multi-byte characters, executed on UNIX environment (non Windows)
+    cfg_if::cfg_if! {
+        if #[cfg(not(windows))] {
+            #[test]
+            fn test_unbuffered() {
+                use assert_cmd::prelude::*;
+                use std::io::{BufRead, BufReader, Write};
+                use std::process::Stdio;
+                use std::sync::mpsc;
+                use std::time::Duration;
+                let mut child = std::process::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap()
+                    .args(&["--unbuffered", "-f", "2", "--", "cat"])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .unwrap();
+                let mut stdin = child.stdin.take().unwrap();
+                let stdout = child.stdout.take().unwrap();
+                let (tx, rx) = mpsc::channel();
+                std::thread::spawn(move || {
+                    let mut line = String::new();
+                    BufReader::new(stdout).read_line(&mut line).unwrap();
+                    tx.send(line).unwrap();
+                });
+                // The first line must be printed while the input is still open
+                stdin.write_all(b"AAA BBB\n").unwrap();
+                stdin.flush().unwrap();
+                let line = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+                assert_eq!(line, "AAA BBB\n");
+                drop(stdin);
+                assert!(child.wait().unwrap().success());
+            }
+
+            #[test]
+            fn test_csv_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                // test for UTF-8 as well to test the CSV parser
+                // Do not use "sed" here because it does not support UTF-8 on Windows environment on GitHub.
+                cmd.args(&["--csv", "-f", "2", "--", SED_CMD, "s/./@/g"])
+                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと\"\n")
+                    .assert()
+                    .stdout("名前,@@,ノート\n１レコード目,@@@@\n@@@,かきく\n２レコード目,@@@@\n@@@,\"たちつ\nてと\"\n");
+            }
+
+            #[test]
+            fn test_csv_end_nolf_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-f", "3", "--", SED_CMD, "s/./@/g"])
+                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと\"")
+                    .assert()
+                    .stdout("名前,作者,@@@\n１レコード目,\"あいう\nえお\",@@@\n２レコード目,\"さしす\nせそ\",@@@@\n@@@");
+            }
+
+            #[test]
+            fn test_csv_non_delim_comma_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-f", "1", "--", SED_CMD, "s/./@/g"])
+                    .write_stdin("\"名,前\",作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n\"２レコード,目\",\"さしす\nせそ\",\"たちつ\nてと\"")
+                    .assert()
+                    .stdout("@@@@@,作者,ノート\n@@@@@@,\"あいう\nえお\",かきく\n@@@@@@@@@,\"さしす\nせそ\",\"たちつ\nてと\"");
+            }
+
+            #[test]
+            fn test_csv_double_quotation_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-f", "2", "--", SED_CMD, "s/./@/g"])
+                    .write_stdin("名前,\"作\"\"者\",ノート\n１レコード目,\"あ\"\"いう\"\"\nえお\",かきく\n２レコード目,\"\"\"さしす\n\"\"せそ\",\"たちつ\nてと\"\n")
+                    .assert()
+                    .stdout("名前,@@@@@@,ノート\n１レコード目,@@@@@@@@\n@@@,かきく\n２レコード目,@@@@@@\n@@@@@,\"たちつ\nてと\"\n");
+            }
+
+            #[test]
+            fn test_solid_csv_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-s", "-f", "2", "--", SED_CMD, "s/./@/g"])
+                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと\"\n")
+                    .assert()
+                    .stdout("名前,@@,ノート\n１レコード目,@@@@\n@@@,かきく\n２レコード目,@@@@\n@@@,\"たちつ\nてと\"\n");
+            }
+            #[test]
+            fn test_solid_csv_end_nolf_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-s", "-f", "3", "--", SED_CMD, "s/./@/g"])
+                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと\"")
+                    .assert()
+                    .stdout("名前,作者,@@@\n１レコード目,\"あいう\nえお\",@@@\n２レコード目,\"さしす\nせそ\",@@@@\n@@@");
+            }
+
+            #[test]
+            fn test_solid_csv_non_delim_comma_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-s", "-f", "1", "--", SED_CMD, "s/./@/g"])
+                    .write_stdin("\"名,前\",作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n\"２レコード,目\",\"さしす\nせそ\",\"たちつ\nてと\"")
+                    .assert()
+                    .stdout("@@@@@,作者,ノート\n@@@@@@,\"あいう\nえお\",かきく\n@@@@@@@@@,\"さしす\nせそ\",\"たちつ\nてと\"");
+            }
+
+            #[test]
+            fn test_solid_csv_double_quotation_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-s", "-f", "2", "--", SED_CMD, "s/./@/g"])
+                    .write_stdin("名前,\"作\"\"者\",ノート\n１レコード目,\"あ\"\"いう\"\"\nえお\",かきく\n２レコード目,\"\"\"さしす\n\"\"せそ\",\"たちつ\nてと\"\n")
+                    .assert()
+                    .stdout("名前,@@@@@@,ノート\n１レコード目,@@@@@@@@\n@@@,かきく\n２レコード目,@@@@@@\n@@@@@,\"たちつ\nてと\"\n");
+            }
+
+            #[test]
+            fn test_solid_csv_crlf_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-s", "-f", "3", "--", TR_CMD, "\\r", "@"])
+                    .write_stdin("AAA,BBB,CCC\r\n1AAA,\"1BBB\r\nBB\",1CCC\r\n2AAA,2BBB,\"2CCC\r\nCC\"\r\n")
+                    .assert()
+                         .stdout("AAA,BBB,CCC\r\n1AAA,\"1BBB\r\nBB\",1CCC\r\n2AAA,2BBB,\"2CCC@\nCC\"\r\n");
+            }
+
+            #[test]
+            fn test_solid_csv_remove_newlines_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-s", "-f", "2", "--", TR_CMD, "-d", "\\n"])
+                    .write_stdin("名前,作者,ノート\n１レコード目,\"あい\nうえお\",かきく\n２レコード目,\"\"\"さしす\nせそ\",\"たちつ\nてと\"\n")
+                    .assert()
+                    .stdout("名前,作者,ノート\n１レコード目,\"あいうえお\",かきく\n２レコード目,\"\"\"さしすせそ\",\"たちつ\nてと\"\n");
+            }
+
+            #[test]
+            fn test_solid_csv_chomp_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-s", "--chomp", "-f", "2,3", "--", TR_CMD, "\\n", "@"])
+                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと,\n")
+                    .assert()
+                    .stdout("名前,作者,ノート\n１レコード目,\"あいう@えお\",かきく\n２レコード目,\"さしす@せそ\",\"たちつ@てと,");
+            }
+
+            #[test]
+            fn test_solid_csv_nochomp_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--csv", "-s", "-f", "2,3", "--", TR_CMD, "\\n", "@"])
+                    .write_stdin("名前,作者,ノート\n１レコード目,\"あいう\nえお\",かきく\n２レコード目,\"さしす\nせそ\",\"たちつ\nてと,\n")
+                    .assert()
+                    .stdout("名前,作者@,ノート@\n１レコード目,\"あいう@えお\"@,かきく@\n２レコード目,\"さしす@せそ\"@,\"たちつ@てと,@@");
+            }
+
+            #[test]
+            fn test_solid_replace() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                // separaate arguments -I @
+                 cmd.args(&["-I", "@", "-s", "-f", "2", "--", _ECHO_CMD, ">>@<<"])
+                     .write_stdin("AAA BBB CCC\nDDD EEE FFF\n")
+                     .assert()
+                     .stdout("AAA >>BBB<< CCC\nDDD >>EEE<< FFF\n");
+            }
 
             #[test]
             fn test_solid_replace_join() {
@@ -982,6 +3386,446 @@ mod cmdtest {
 
             }
 
+            #[test]
+            fn test_solid_arg() {
+                // The hole is added as the last argument
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--solid-arg", "-f", "2", "--", _ECHO_CMD, "-n", "x"])
+                    .write_stdin("AAA BBB CCC\nDDD EEE FFF\n")
+                    .assert()
+                    .stdout("AAA x BBB CCC\nDDD x EEE FFF\n");
+                // {} is replaced with the hole
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--solid-arg", "-d", ",", "-f", "2", "--", _ECHO_CMD, "<{}>", "end"])
+                    .write_stdin("AAA,BBB\n")
+                    .assert()
+                    .stdout("AAA,<BBB> end\n");
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--solid-arg", "-I", "@", "-f", "2", "--", _ECHO_CMD])
+                    .write_stdin("AAA BBB\n")
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("--solid-arg cannot be used with -I"));
+            }
+
+            #[test]
+            fn test_trim_eol_preserve() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-z", "-s", "--chomp", "-og", "[ac]+\\r?\\n", "--trim-eol-preserve", "--", SED_CMD, "s/$/!/"])
+                    .write_stdin("aaa\r\nbbb\r\nccc\n")
+                    .assert()
+                    .stdout("aaa!\r\nbbb\r\nccc!\n");
+                // CR printed by the command is not left
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-s", "-f", "2", "--trim-eol-preserve", "--", SED_CMD, "s/$/\\r/"])
+                    .write_stdin("AAA BBB\r\nCCC DDD\r\n")
+                    .assert()
+                    .stdout("AAA BBB\r\nCCC DDD\r\n");
+            }
+
+            #[test]
+            fn test_on_error_default() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-s", "-f", "2", "--", "sh", "-c", "read x; [ $x = 2 ] && exit 3; echo $((x*10))"])
+                    .write_stdin("a 1\nb 2\nc 3\n")
+                    .assert()
+                    .success()
+                    .stdout("a 10\nb \nc 30\n");
+            }
+
+            #[test]
+            fn test_on_error_passthrough() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--on-error", "passthrough", "-s", "-f", "2", "--", "sh", "-c", "read x; [ $x = 2 ] && exit 3; echo $((x*10))"])
+                    .write_stdin("a 1\nb 2\nc 3\n")
+                    .assert()
+                    .success()
+                    .stdout("a 10\nb 2\nc 30\n");
+            }
+
+            #[test]
+            fn test_on_error_empty() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--on-error", "empty", "-s", "-f", "2", "--", "sh", "-c", "read x; [ $x = 2 ] && exit 3; echo $((x*10))"])
+                    .write_stdin("a 1\nb 2\nc 3\n")
+                    .assert()
+                    .success()
+                    .stdout("a 10\nb \nc 30\n");
+            }
+
+            #[test]
+            fn test_on_error_abort() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--on-error", "abort", "-s", "-f", "2", "--", "sh", "-c", "read x; [ $x = 2 ] && exit 3; echo $((x*10))"])
+                    .write_stdin("a 1\nb 2\nc 3\n")
+                    .assert()
+                    .failure()
+                    .stdout("a 10\nb ");
+            }
+
+            #[test]
+            fn test_on_error_marker() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--on-error", "marker", "--on-error-marker", "<E>", "-s", "-f", "2", "--", "sh", "-c", "read x; [ $x = 2 ] && exit 3; echo $((x*10))"])
+                    .write_stdin("a 1\nb 2\nc 3\n")
+                    .assert()
+                    .success()
+                    .stdout("a 10\nb <E>\nc 30\n");
+            }
+
+            #[test]
+            fn test_on_error_strict_utf8() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--strict-utf8", "--on-error", "marker", "-c", "1"])
+                    .write_stdin(b"a\xffb\nok\n".to_vec())
+                    .env("TEIP_HIGHLIGHT", "[{}]")
+                    .assert()
+                    .success()
+                    .stdout("[E]RROR\n[o]k\n");
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--strict-utf8", "--on-error", "abort", "-c", "1"])
+                    .write_stdin(b"a\xffb\nok\n".to_vec())
+                    .assert()
+                    .failure();
+            }
+
+            #[test]
+            fn test_group_separator() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-s", "-l", "1-", "--group-separator=--", "--", "fmt", "-w", "10"])
+                    .write_stdin("the quick brown fox\njumps over the lazy dog\nend\nvery long line\n")
+                    .assert()
+                    .stdout("the quick\nbrown fox\n--\njumps\nover the\nlazy dog\nend\nvery\nlong line\n");
+            }
+
+            // Output of teip for the uncompressed tests/sample.csv
+            #[cfg(any(feature = "zstd", feature = "bzip2"))]
+            fn sample_csv_field() -> Vec<u8> {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                let out = cmd.args(&["--input", "tests/sample.csv", "--csv", "-f", "3", "--", "sed", "s/./@/"])
+                    .output()
+                    .unwrap();
+                assert!(String::from_utf8_lossy(&out.stdout).ends_with(",@13123\n"));
+                out.stdout
+            }
+
+            #[test]
+            #[cfg(feature = "zstd")]
+            fn test_zstd() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--zstd", "--input", "tests/sample.csv.zst", "--csv", "-f", "3", "--", "sed", "s/./@/"])
+                    .assert()
+                    .stdout(sample_csv_field());
+            }
+
+            #[test]
+            #[cfg(feature = "bzip2")]
+            fn test_bzip2_stdin() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--bzip2", "--csv", "-f", "3", "--", "sed", "s/./@/"])
+                    .write_stdin(std::fs::read("tests/sample.csv.bz2").unwrap())
+                    .assert()
+                    .stdout(sample_csv_field());
+            }
+
+            #[test]
+            #[cfg(all(feature = "zstd", feature = "bzip2"))]
+            fn test_auto_decompress() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                let mut expected = sample_csv_field();
+                expected.extend(sample_csv_field());
+                expected.extend(sample_csv_field());
+                cmd.args(&["--auto-decompress", "--input", "tests/sample.csv.zst", "--input", "tests/sample.csv.bz2", "--input", "tests/sample.csv"])
+                    .args(&["--csv", "-f", "3", "--", "sed", "s/./@/"])
+                    .assert()
+                    .stdout(expected);
+            }
+
+            #[test]
+            #[cfg(feature = "zstd")]
+            fn test_zstd_invalid() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--zstd", "--input", "tests/sample.csv", "-f", "1"])
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("tests/sample.csv: "));
+            }
+
+            #[test]
+            #[cfg(not(feature = "zstd"))]
+            fn test_zstd_disabled() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--zstd", "--input", "tests/sample.csv.zst", "-f", "1"])
+                    .assert()
+                    .code(1)
+                    .stderr(predicate::str::contains("--zstd is not enabled in this build"));
+            }
+
+            #[test]
+            #[cfg(not(feature = "bzip2"))]
+            fn test_auto_decompress_disabled() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--auto-decompress", "--input", "tests/sample.csv.bz2", "-f", "1"])
+                    .assert()
+                    .code(1)
+                    .stderr(predicate::str::contains("tests/sample.csv.bz2: bzip2 is not enabled in this build"));
+            }
+
+            #[test]
+            fn test_no_merge_solid() {
+                // The command is executed once per character
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-c", "2-4,6", "-s", "--no-merge", "--stats", "--", "sh", "-c", "read c; echo \"($c)\""])
+                    .write_stdin("abcdef\n")
+                    .assert()
+                    .stdout("a(b)(c)(d)e(f)\n")
+                    .stderr(predicate::str::contains(format!("{:<10}{:>12}", "commands", 4)));
+            }
+
+            #[test]
+            fn test_command_timeout_total() {
+                // Each command spawned by -s takes 0.4 seconds
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                let assert = cmd.args(&["--command-timeout-total", "1", "-s", "-f", "1", "--", "sh", "-c", "sleep 0.4; tr a-z A-Z"])
+                    .write_stdin("a 1\nb 2\nc 3\nd 4\ne 5\nf 6\n")
+                    .assert()
+                    .code(124)
+                    .stderr(predicate::str::contains("--command-timeout-total expired"));
+                let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+                assert!(stdout.starts_with("A 1\nB 2\n"), "{:?}", stdout);
+                assert!(!stdout.contains("F"), "{:?}", stdout);
+            }
+
+            #[test]
+            fn test_command_timeout_total_stream() {
+                // The input is given slowly, and it is cut at the end of a line
+                let teip = assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME"));
+                let teip = teip.to_str().unwrap();
+                let mut cmd = assert_cmd::Command::new("sh");
+                let assert = cmd.args(&["-c", "for i in 1 2 3 4 5 6 7 8; do echo a $i; sleep 0.3; done | \"$0\" --command-timeout-total 1 -f 1 -- tr a-z A-Z", teip])
+                    .assert()
+                    .code(124);
+                let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+                assert!(stdout.starts_with("A 1\nA 2\nA 3\n"), "{:?}", stdout);
+                assert!(stdout.ends_with('\n') && !stdout.contains("A 8"), "{:?}", stdout);
+            }
+
+            #[test]
+            fn test_command_timeout_total_stalled() {
+                // The input stalls after the first line, and the blocked read is given up at the deadline
+                use assert_cmd::prelude::*;
+                use std::io::Write;
+                use std::process::Stdio;
+                use std::time::{Duration, Instant};
+                let mut child = std::process::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap()
+                    .args(&["--command-timeout-total", "1", "-f", "1", "--", "tr", "a-z", "A-Z"])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()
+                    .unwrap();
+                let start = Instant::now();
+                let mut stdin = child.stdin.take().unwrap();
+                stdin.write_all(b"a 1\n").unwrap();
+                stdin.flush().unwrap();
+                // Like (echo a 1; sleep 5; echo b 2) | teip ...
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_secs(5));
+                    let _ = stdin.write_all(b"b 2\n");
+                });
+                let output = child.wait_with_output().unwrap();
+                assert!(start.elapsed() < Duration::from_secs(4), "{:?}", start.elapsed());
+                assert_eq!(output.status.code(), Some(124));
+                assert_eq!(String::from_utf8(output.stdout).unwrap(), "A 1\n");
+            }
+
+            #[test]
+            fn test_keep_command_stderr_on_stdout() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-f", "2", "-s", "--keep-command-stderr-on-stdout", "--", "sh", "-c", "tr a-z A-Z; echo warn >&2"])
+                    .write_stdin("a b\nc d\n")
+                    .assert()
+                    .stdout("a B\nwarn\nc D\nwarn\n")
+                    .stderr("");
+                // Standard error is separated by default
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-f", "2", "-s", "--", "sh", "-c", "tr a-z A-Z; echo warn >&2"])
+                    .write_stdin("a b\nc d\n")
+                    .assert()
+                    .stdout("a B\nc D\n")
+                    .stderr("warn\nwarn\n");
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-f", "2", "--keep-command-stderr-on-stdout", "--", "cat"])
+                    .write_stdin("a b\n")
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("available only with -s"));
+            }
+
+            #[test]
+            fn test_dedup() {
+                // The command prints the number of times it has been spawned
+                let counter = std::env::temp_dir().join("teip_test_dedup_count.txt");
+                let _ = std::fs::remove_file(&counter);
+                let script = format!("echo x >> {}; wc -l < {}", counter.display(), counter.display());
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-s", "-f", "2", "--dedup", "--stats", "--", "sh", "-c", &script])
+                    .write_stdin("a x\nb x\nc y\nd x\n")
+                    .assert()
+                    .stdout(predicate::str::is_match("^a +1\nb +1\nc +2\nd +1\n$").unwrap())
+                    .stderr(predicate::str::contains(format!("{:<10}{:>12}", "commands", 2)));
+                // The oldest result is discarded when the cache is full
+                std::fs::remove_file(&counter).unwrap();
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-s", "-f", "2", "--dedup", "--dedup-cache", "1", "--", "sh", "-c", &script])
+                    .write_stdin("a x\nb x\nc y\nd x\n")
+                    .assert()
+                    .stdout(predicate::str::is_match("^a +1\nb +1\nc +2\nd +3\n$").unwrap());
+            }
+
+            #[test]
+            fn test_nul_in_hole() {
+                // NUL in the hole is given to the command as it is in the newline mode
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-c", "3-5", "--", "cat"])
+                    .write_stdin("a b\0c d\n")
+                    .assert()
+                    .stdout("a b\0c d\n");
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-s", "-c", "3-5", "--", "tr", "\\0", "@"])
+                    .write_stdin("a b\0c d\n")
+                    .assert()
+                    .stdout("a b@c d\n");
+                // NUL terminates the holes of --solid-nul
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--solid-nul", "-c", "3-5", "--", "cat"])
+                    .write_stdin("a b\0c d\n")
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("A hole includes '\\0'"));
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--length-prefixed", "-c", "3-5", "--", "cat"])
+                    .write_stdin("a b\0c d\n")
+                    .assert()
+                    .stdout("a b\0c d\n");
+            }
+
+            #[test]
+            fn test_field_command() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.env("TEIP_HIGHLIGHT", "[{}]")
+                    .args(&["--field-command", "grep -qx '[0-9]*'"])
+                    .write_stdin("ab 12 cd 34\n5 e\n")
+                    .assert()
+                    .stdout("ab [12] cd [34]\n[5] e\n");
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-d", ",", "-f", "1-3", "-v", "--field-command", "grep -qx '[0-9]*'", "--", "tr", "a-z", "A-Z"])
+                    .write_stdin("ab,12,cd,ef\n")
+                    .assert()
+                    .stdout("AB,12,CD,ef\n");
+                // The command is executed once for each distinct field
+                let log = std::env::temp_dir().join("teip_test_field_command.log");
+                let _ = std::fs::remove_file(&log);
+                let pred = format!("echo >> '{}'; grep -qx '[0-9]*'", log.to_str().unwrap());
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["--field-command", &pred, "--", "sed", "s/./@/g"])
+                    .write_stdin("ab 12 ab\n12 cd ab\n")
+                    .assert()
+                    .stdout("ab @@ ab\n@@ cd ab\n");
+                assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 3);
+            }
+
+            #[test]
+            fn test_keep_selection_order() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-l", "1-", "--keep-selection-order", "--", "sh", "-c", "cat; echo extra"])
+                    .write_stdin("a\nb\n")
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("The command printed more results than the 2 holes"));
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-l", "1-", "--keep-selection-order", "--", "sed", "2d"])
+                    .write_stdin("a\nb\nc\n")
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("The command printed fewer results than the holes"));
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-og", "\\d", "--keep-selection-order", "--", "tr", "0-9", "a-j"])
+                    .write_stdin("a1b2\n3\n")
+                    .assert()
+                    .success()
+                    .stdout("abbc\nd\n");
+                // Extra results are ignored by default
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-l", "1-", "--", "sh", "-c", "cat; echo extra"])
+                    .write_stdin("a\nb\n")
+                    .assert()
+                    .success()
+                    .stdout("a\nb\n");
+            }
+
+            #[test]
+            fn test_list_fd() {
+                let teip = assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME"));
+                let teip = teip.to_str().unwrap();
+                // The list is given by fd 3 and the data by stdin
+                let mut cmd = assert_cmd::Command::new("sh");
+                cmd.args(&["-c", "\"$0\" -f - --list-fd 3 -- sed 's/./@/' 3<<EOF\n2\nEOF", teip])
+                    .write_stdin("a b c\nd e f\n")
+                    .assert()
+                    .stdout("a @ c\nd @ f\n");
+            }
+
+            #[test]
+            fn test_list_nul() {
+                let teip = assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME"));
+                let teip = teip.to_str().unwrap();
+                let list = std::env::temp_dir().join("teip_test_list_nul.txt");
+                std::fs::write(&list, "1\u{0}3-\n\u{0}").unwrap();
+                let mut cmd = assert_cmd::Command::new("sh");
+                cmd.args(&["-c", "\"$0\" -f - --list-fd 3 --list-nul -- sed 's/./@/' 3< \"$1\"", teip, list.to_str().unwrap()])
+                    .write_stdin("a b c d\ne f g h\n")
+                    .assert()
+                    .stdout("@ b @ @\n@ f @ @\n");
+                // A comma is not a delimiter of the ranges
+                std::fs::write(&list, "1,2\u{0}3").unwrap();
+                let mut cmd = assert_cmd::Command::new("sh");
+                cmd.args(&["-c", "\"$0\" -f - --list-fd 3 --list-nul 3< \"$1\"", teip, list.to_str().unwrap()])
+                    .write_stdin("a b c\n")
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("range '1,2' was invalid"));
+            }
+
+            #[test]
+            fn test_list_fd_without_hyphen() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-f", "1", "--list-fd", "3"])
+                    .write_stdin("a b c\n")
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("--list-fd requires - as <list>"));
+            }
+
+            #[test]
+            fn test_command_per_line() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-og", "[a-e]", "--command-per-line", "--", "awk", "{print NR}"])
+                    .write_stdin("a b c\nx y z\nd e\n")
+                    .assert()
+                    .stdout("1 2 3\nx y z\n1 2\n");
+            }
+
+            #[test]
+            fn test_command_per_line_exhausted() {
+                let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+                cmd.args(&["-og", "\\w", "--command-per-line", "--", "head", "-n", "1"])
+                    .write_stdin("a b c\n")
+                    .assert()
+                    .failure()
+                    .stderr(predicate::str::contains("exhausted"));
+            }
+
             #[test]
             fn test_solid_replace_multi() {
                 let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();